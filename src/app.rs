@@ -1,80 +1,1275 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crate::state::State;
+use crate::config::{Config, RedrawMode};
+use crate::export::ExportError;
+use crate::fbout::FbOutput;
+use crate::fps::FpsMeter;
+use crate::geometry::Geometry;
+use crate::metrics::{self, FrameLog};
+use crate::palette::{self, MAX_STOPS, Palette};
+use crate::png::Depth;
+use crate::presets;
+use crate::remote::{self, ParamInbox};
+use crate::render_thread::{RenderThread, StateFn};
+use crate::state::FrameHook;
+use crate::state::{State, StateError};
 use winit::{
     application::ApplicationHandler,
-    event::WindowEvent,
-    event_loop::ActiveEventLoop,
-    window::{Window, WindowId},
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoopProxy},
+    keyboard::{Key, KeyCode, NamedKey, PhysicalKey},
+    monitor::MonitorHandle,
+    window::{Fullscreen, Window, WindowId, WindowLevel},
 };
 
+#[derive(Debug)]
+pub enum UserEvent {
+    // 外部入力のパラメータが ParamInbox に届いた
+    Params,
+    // --watch: エフェクト（番号）のシェーダーファイルが更新された
+    Shader { effect: usize, source: String },
+    // --render-thread: デバイスを失ったあと State を作り直せず、描画スレッドが止まった
+    RenderFailed(String),
+}
+
 #[derive(Default)]
 pub struct App {
+    // タイトル表示・ペーシング・復旧の基準になる最初のウィンドウ
     pub state: Option<State>,
+    // --all-monitors: 2つ目以降のモニターのウィンドウ。それぞれが次のフレームを要求して描く
+    outputs: Vec<State>,
+    // --watch: エフェクトごとに最後に読み込んだシェーダー
+    shaders: BTreeMap<usize, String>,
     pub animating: bool,
-    fps_frames: u32,
-    fps_last: Option<Instant>,
+    config: Config,
+    inbox: Option<Arc<ParamInbox>>,
+    fps: FpsMeter,
+    last_frame: Option<Instant>,
+    cpu_sum: Duration,
+    wait_sum: Duration,
+    uniform_bytes_last: u64,
+    next_tick: Option<Instant>,
+    // 2つ目以降の出力の次の描画時刻（--interval などで間隔を空けるとき）
+    output_tick: Option<Instant>,
+    // --fps-cap: 前回の描画予定時刻。ここから 1/cap ずつ進めてずれを溜めない
+    cap_due: Option<Instant>,
+    surface_errors: u32,
+    timeouts: u32,
+    // --redraw=on-input: 入力・リサイズで立ち、描画後に下ろす
+    dirty: bool,
+    fb: Option<FbOutput>,
+    metrics: Option<FrameLog>,
+    palette_edit: Option<PaletteEditor>,
+    // '#' で始まる色の入力中の文字列（'#' は含まない）
+    hex_entry: Option<String>,
+    // タイトルの FPS の後ろに出す状態表示
+    overlay: Option<String>,
+    started: Option<Instant>,
+    // 描画を始められずに終了した（main が終了コードに使う）
+    pub failed: bool,
+    // --battery-fps: 電源につながっているとき（--fps-cap、None なら制限なし）と
+    // 電池のときの描画回数の上限。電源の状態が変わると config.fps_cap をどちらかにする
+    ac_fps_cap: Option<f64>,
+    battery_fps_cap: Option<f64>,
+    // 最後に電源の状態を調べた時刻と、そのときの結果（電池なら true）
+    power_checked: Option<Instant>,
+    on_battery: Option<bool>,
+    // --render-thread 時は State をこちらに移す（state は None になる）
+    render_thread: Option<RenderThread>,
+    // 描画スレッドからの失敗の知らせに使う
+    proxy: Option<EventLoopProxy<UserEvent>>,
+    on_frame: Option<FrameHook>,
+    worst_dt: Duration,
+    // --debug-surface: 直近のサーフェス復旧をタイトルに出す（内容と時刻）
+    recovery: Option<(&'static str, Instant)>,
+    // --watchdog: 最後にフレームを出した時刻（間引き中は次の描画予定時刻）と、
+    // 止まってから何段階目の復旧まで試したか
+    last_present: Option<Instant>,
+    stall_level: u32,
+    // --idle-timeout: 最後の入力の時刻と、入力がなくて止めている間は true
+    last_input: Option<Instant>,
+    idle: bool,
+    // ←/→ で選んでいるプリセット（presets::PRESETS の番号）と、名前をタイトルに出し始めた時刻
+    preset: Option<usize>,
+    preset_note: Option<Instant>,
+    // 幅か高さが 0 のサイズが来たら true。次に 0 でないサイズが来るまで描画も要求もしない
+    minimized: bool,
+}
+
+// P で入る簡易パレットエディタ。数字キーで色を選び、←→ で R/G/B、↑↓ で値を変える
+struct PaletteEditor {
+    stop: usize,
+    channel: usize,
+}
+
+impl PaletteEditor {
+    fn status(&self, palette: &Palette) -> String {
+        let stops: Vec<String> = palette
+            .stops
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                if i == self.stop {
+                    format!("[{}]", palette::hex(*c))
+                } else {
+                    palette::hex(*c)
+                }
+            })
+            .collect();
+        format!(
+            "palette {}  {}",
+            stops.join(" "),
+            ["R", "G", "B"][self.channel]
+        )
+    }
+}
+
+const DIGITS: [KeyCode; MAX_STOPS] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+];
+
+fn title(fps: Option<f64>, gpu_ms: Option<f64>, overlay: Option<String>) -> String {
+    let mut t = String::from("Swarm Wallpaper");
+    if let Some(fps) = fps {
+        t += &format!("  |  {fps:.1} FPS");
+    }
+    if let Some(ms) = gpu_ms {
+        t += &format!("  |  GPU {ms:.2} ms");
+    }
+    if let Some(o) = overlay {
+        t += &format!("  |  {o}");
+    }
+    t
+}
+
+// 同じウィンドウで State を作り直す（デバイス喪失やウォッチドッグ）。差し替えたシェーダーも当て直す
+fn build_state(
+    window: Arc<Window>,
+    config: &Config,
+    shaders: &BTreeMap<usize, String>,
+) -> Result<State, StateError> {
+    let mut state = pollster::block_on(State::new(window, config))?;
+    for (&effect, src) in shaders {
+        apply_shader(&mut state, effect, src);
+    }
+    Ok(state)
 }
 
-impl ApplicationHandler for App {
+// --watch で読み込んだシェーダーでそのエフェクトのパイプラインを作り直す。
+// 失敗したらエラーを出して、それまでのパイプラインで描き続ける
+fn apply_shader(s: &mut State, effect: usize, src: &str) {
+    let name = crate::effects::EFFECTS[effect].name;
+    match s.reload_shader(effect, src) {
+        Ok(()) => eprintln!("Reloaded {name}"),
+        Err(e) => eprintln!("Reloading {name} failed; keeping the previous shader:\n{e}"),
+    }
+}
+
+// 電源の状態を調べ直す間隔
+const POWER_CHECK: Duration = Duration::from_secs(30);
+
+// サーフェス復旧の表示を出しておく時間
+const RECOVERY_NOTE: Duration = Duration::from_secs(1);
+
+// プリセットの名前をタイトルに出しておく時間
+const PRESET_NOTE: Duration = Duration::from_secs(2);
+
+// 起動直後はこの間だけタイトルにアダプタ名を出し、その後 FPS 表示に切り替える
+const ADAPTER_TITLE: Duration = Duration::from_secs(3);
+
+// この回数連続でサーフェスエラーが出たらサーフェスを作り直す
+const SURFACE_RETRY_LIMIT: u32 = 3;
+pub const DEFAULT_TIMEOUT_LIMIT: u32 = 5;
+
+impl App {
+    pub fn new(config: Config, proxy: EventLoopProxy<UserEvent>) -> Self {
+        if config.watch {
+            crate::watch::spawn(proxy.clone(), config.shader.clone());
+        }
+        // --shader は --watch の差し替えと同じ形で持ち、後から開くウィンドウや作り直しにも使う
+        let mut shaders = BTreeMap::new();
+        if let Some(path) = &config.shader {
+            match crate::effects::load_custom(path) {
+                Ok(src) => {
+                    shaders.insert(crate::effects::CUSTOM, src);
+                }
+                Err(e) => eprintln!(
+                    "Shader {} not used; keeping the built-in one:\n{e}",
+                    path.display()
+                ),
+            }
+        }
+        let inbox = ParamInbox::new(proxy.clone());
+        if let Some(path) = &config.param_pipe {
+            remote::spawn_pipe(path.clone(), inbox.clone());
+        }
+        if let Some(port) = config.osc_port {
+            #[cfg(feature = "osc")]
+            crate::osc::spawn(port, inbox.clone());
+            #[cfg(not(feature = "osc"))]
+            eprintln!("osc_port {port} ignored: built without the `osc` feature");
+        }
+        if let Some(device) = &config.midi_device {
+            #[cfg(feature = "midi")]
+            crate::midi::spawn(device.clone(), config.midi_map.clone(), inbox.clone());
+            #[cfg(not(feature = "midi"))]
+            eprintln!(
+                "MIDI device {} ignored: built without the `midi` feature",
+                device.display()
+            );
+        }
+        if let Some(path) = &config.audio_pcm {
+            #[cfg(feature = "audio")]
+            crate::audio::spawn(path.clone(), inbox.clone());
+            #[cfg(not(feature = "audio"))]
+            eprintln!(
+                "audio_pcm {} ignored: built without the `audio` feature",
+                path.display()
+            );
+        }
+        let fb = config
+            .fb_output
+            .clone()
+            .map(|p| FbOutput::spawn(p, config.fb_rate.unwrap_or(10.0)));
+        let metrics = metrics::open(config.log_metrics.as_deref());
+        Self {
+            ac_fps_cap: config.fps_cap,
+            battery_fps_cap: config.battery_fps,
+            config,
+            inbox: Some(inbox),
+            proxy: Some(proxy),
+            shaders,
+            fb,
+            metrics,
+            ..Default::default()
+        }
+    }
+
+    // 埋め込み側から、毎フレームの描画直前に呼ばれる処理を登録する
+    pub fn set_on_frame(&mut self, hook: impl FnMut(&mut State, u32) + Send + 'static) {
+        self.on_frame = Some(Box::new(hook));
+    }
+
+    // State への操作。すべての出力に同じものを適用し、描画スレッドがあればそちらで実行する
+    fn with_state(&mut self, mut f: StateFn) {
+        if let Some(s) = self.state.as_mut() {
+            f(s);
+            for s in &mut self.outputs {
+                f(s);
+            }
+        } else if let Some(rt) = self.render_thread.as_ref() {
+            rt.send(f);
+        }
+    }
+
+    // ウィンドウごとのイベント（リサイズなど）の宛先
+    fn state_for(&mut self, id: WindowId) -> Option<&mut State> {
+        if self.state.as_ref().is_some_and(|s| s.window.id() == id) {
+            return self.state.as_mut();
+        }
+        self.outputs.iter_mut().find(|s| s.window.id() == id)
+    }
+
+    // ウィンドウを開いて描画の準備をし、最初のフレームを描いてから表示する
+    fn open_window(
+        &self,
+        event_loop: &ActiveEventLoop,
+        attrs: winit::window::WindowAttributes,
+    ) -> Option<State> {
+        // Wayland の背景レイヤーは描画先そのものなので、レンダラより先に作る
+        let monitor = match &attrs.fullscreen {
+            Some(Fullscreen::Borderless(m)) => m.clone(),
+            _ => None,
+        };
+        let window = event_loop.create_window(attrs).expect("create window");
+        let layer = if self.config.wallpaper {
+            crate::platform::prepare_wallpaper(&window, monitor.as_ref())
+        } else {
+            Ok(())
+        };
+
+        let mut state = match pollster::block_on(State::new(Arc::new(window), &self.config)) {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("Could not start renderer: {e}");
+                return None;
+            }
+        };
+        for (&effect, src) in &self.shaders {
+            apply_shader(&mut state, effect, src);
+        }
+
+        if self.config.wallpaper {
+            // 埋め込みは非表示のうちに行い、最初のフレームを描いてから出す
+            state.window.set_visible(false);
+            let attached = layer.and_then(|()| {
+                if state.on_layer() {
+                    Ok(())
+                } else {
+                    crate::platform::attach_wallpaper(&state.window)
+                }
+            });
+            if let Err(e) = attached {
+                eprintln!("Could not attach to the desktop ({e}); using a normal window.");
+            }
+        }
+
+        if self.config.overlay
+            && let Err(e) = state.window.set_cursor_hittest(false)
+        {
+            // 対応していない環境ではクリックはウィンドウが受ける
+            eprintln!("Could not make the overlay click-through: {e}");
+        }
+
+        if !self.config.show_early || self.config.wallpaper {
+            if let Err(e) = state.render() {
+                eprintln!("Initial render failed: {e:?}");
+            }
+            state.window.set_visible(true);
+        }
+
+        state
+            .window
+            .set_title(&format!("Swarm Wallpaper  |  {}", state.adapter_summary()));
+        Some(state)
+    }
+
+    // 2つ目以降の出力を1フレーム描く。失ったサーフェスは構成し直すだけにする。
+    // 次のフレームは主ウィンドウを待たずにそれぞれが要求する
+    fn render_output(&mut self, id: WindowId) {
+        let scheduled = self.animating && !self.on_input_only();
+        let Some(s) = self.outputs.iter_mut().find(|s| s.window.id() == id) else {
+            return;
+        };
+        if let Some(hook) = self.on_frame.as_mut() {
+            let frame = s.renderer.frame;
+            hook(s, frame);
+        }
+        match s.render() {
+            Ok(()) | Err(wgpu::SurfaceError::Timeout) => (),
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                let (w, h) = (s.config.width, s.config.height);
+                s.resize(w, h);
+            }
+            Err(e) => eprintln!("Surface error: {e:?}"),
+        }
+        if !scheduled {
+            return;
+        }
+        let now = Instant::now();
+        let due = if let Some(iv) = self.config.interval {
+            Some(now + Duration::from_secs_f64(iv))
+        } else if let Some(n) = self.config.fps_divisor.filter(|n| *n > 1) {
+            let hz = s.renderer.refresh_hz() as f64;
+            Some(now + Duration::from_secs_f64((n as f64 - 0.5) / hz))
+        } else {
+            self.config
+                .fps_cap
+                .map(|cap| now + Duration::from_secs_f64(1.0 / cap))
+        };
+        match due {
+            // 全出力をまとめて about_to_wait で起こす
+            Some(t) => self.output_tick = Some(self.output_tick.map_or(t, |o| o.min(t))),
+            None => s.window.request_redraw(),
+        }
+    }
+
+    // --idle-timeout: 入力がないまま時間が経ったら、今のフレームのまま止める
+    fn check_idle(&mut self) {
+        let Some(limit) = self.config.idle_timeout.map(Duration::from_secs_f64) else {
+            return;
+        };
+        if !self.animating || self.render_thread.is_some() {
+            return;
+        }
+        if self.last_input.is_some_and(|t| t.elapsed() >= limit) {
+            if self.config.verbose {
+                eprintln!("No input for {limit:?}; pausing until the next input.");
+            }
+            self.idle = true;
+            self.set_animating(false);
+        }
+    }
+
+    // 入力があったことを記録する。止めていたら再開して true
+    fn note_input(&mut self) -> bool {
+        self.last_input = Some(Instant::now());
+        if !self.idle {
+            return false;
+        }
+        self.idle = false;
+        self.set_animating(true);
+        true
+    }
+
+    // --battery-fps: 電池と電源の切り替わりに合わせて描画回数の上限を変える
+    fn check_power(&mut self) {
+        let Some(battery_cap) = self.battery_fps_cap else {
+            return;
+        };
+        if self
+            .power_checked
+            .is_some_and(|t| t.elapsed() < POWER_CHECK)
+        {
+            return;
+        }
+        self.power_checked = Some(Instant::now());
+        let on_battery = crate::power::is_on_battery();
+        if self.on_battery == Some(on_battery) {
+            return;
+        }
+        // 起動時に電源につながっていれば何も変わらない
+        let switched = self.on_battery.is_some();
+        self.on_battery = Some(on_battery);
+        self.config.fps_cap = if on_battery {
+            eprintln!("On battery power; limiting to {battery_cap} FPS.");
+            Some(battery_cap)
+        } else {
+            if switched {
+                eprintln!("On AC power again; restoring the frame rate.");
+            }
+            self.ac_fps_cap
+        };
+        self.cap_due = None;
+    }
+
+    // Space: アニメーションを止める/再開する。止めたあとも1回だけ描いて最後の場面を残す
+    fn toggle_pause(&mut self) {
+        self.idle = false;
+        self.set_animating(!self.animating);
+    }
+
+    fn set_animating(&mut self, animating: bool) {
+        self.animating = animating;
+        let paused = !self.animating;
+        self.next_tick = None;
+        self.output_tick = None;
+        self.cap_due = None;
+        if !paused {
+            // 止めていた間を FPS や最悪フレーム間隔、--watchdog の停止に数えない
+            self.fps.reset();
+            self.last_frame = Some(Instant::now());
+            self.last_present = None;
+        }
+        self.with_state(Box::new(move |s| {
+            s.set_paused(paused);
+            s.window.request_redraw();
+        }));
+        self.refresh_overlay();
+    }
+
+    fn toggle_palette_editor(&mut self) {
+        if self.palette_edit.take().is_none() {
+            if self.config.palette.stops.len() < 2 {
+                self.config.palette = Palette {
+                    stops: vec![[0, 0, 0], [255, 255, 255]],
+                };
+            }
+            self.palette_edit = Some(PaletteEditor {
+                stop: 0,
+                channel: 0,
+            });
+        }
+        self.apply_palette();
+    }
+
+    // エディタのキーなら処理して true
+    fn edit_palette(&mut self, code: KeyCode, repeat: bool) -> bool {
+        let Some(ed) = self.palette_edit.as_mut() else {
+            return false;
+        };
+        let stops = &mut self.config.palette.stops;
+        match code {
+            _ if DIGITS.contains(&code) => {
+                let i = DIGITS.iter().position(|k| *k == code).unwrap();
+                // 末尾より先を選んだら最後の色を複製して足す
+                while stops.len() <= i && stops.len() < MAX_STOPS {
+                    stops.push(*stops.last().unwrap());
+                }
+                ed.stop = i.min(stops.len() - 1);
+            }
+            KeyCode::ArrowLeft => ed.channel = (ed.channel + 2) % 3,
+            KeyCode::ArrowRight => ed.channel = (ed.channel + 1) % 3,
+            KeyCode::ArrowUp => {
+                let v = &mut stops[ed.stop][ed.channel];
+                *v = v.saturating_add(8);
+            }
+            KeyCode::ArrowDown => {
+                let v = &mut stops[ed.stop][ed.channel];
+                *v = v.saturating_sub(8);
+            }
+            KeyCode::Delete | KeyCode::Backspace if stops.len() > 2 => {
+                stops.remove(ed.stop);
+                ed.stop = ed.stop.min(stops.len() - 1);
+            }
+            // E: 設定ファイルに書き出す
+            KeyCode::KeyE if !repeat => {
+                let v = format!("\"{}\"", self.config.palette.to_config_string());
+                match crate::config::save_key("palette", &v) {
+                    Ok(path) => eprintln!("Palette saved to {}", path.display()),
+                    Err(e) => eprintln!("Could not save palette: {e}"),
+                }
+                return true;
+            }
+            _ => return false,
+        }
+        self.apply_palette();
+        true
+    }
+
+    fn refresh_overlay(&mut self) {
+        let mut parts = Vec::new();
+        if let Some(ed) = &self.palette_edit {
+            parts.push(ed.status(&self.config.palette));
+        }
+        if let Some(hex) = &self.hex_entry {
+            parts.push(format!("color #{hex}_"));
+        }
+        if let Some((what, _)) = self.recovery {
+            parts.push(format!("⚠ {what}"));
+        }
+        if let Some(i) = self.preset.filter(|_| self.preset_note.is_some()) {
+            parts.push(format!("preset: {}", presets::PRESETS[i].name));
+        }
+        if self.idle {
+            parts.push("idle".into());
+        } else if !self.animating {
+            parts.push("paused".into());
+        }
+        self.overlay = (!parts.is_empty()).then(|| parts.join("  |  "));
+        if let Some(s) = self.state.as_ref() {
+            s.window
+                .set_title(&title(self.fps.current(), s.gpu_ms(), self.overlay.clone()));
+        }
+    }
+
+    // '#' に続けて16進6桁を打ち Enter で base_color に反映。Esc で取り消し。入力中なら true
+    fn enter_hex(&mut self, event: &KeyEvent) -> bool {
+        let Some(buf) = self.hex_entry.as_mut() else {
+            if event.text.as_deref() == Some("#") {
+                self.hex_entry = Some(String::new());
+                self.refresh_overlay();
+                return true;
+            }
+            return false;
+        };
+        match &event.logical_key {
+            Key::Named(NamedKey::Enter) => {
+                let text = std::mem::take(buf);
+                self.hex_entry = None;
+                match palette::parse_hex(&text) {
+                    Ok(c) => {
+                        self.config.base_color = c;
+                        self.with_state(Box::new(move |s| {
+                            s.renderer.set_base_color(c);
+                            s.window.request_redraw();
+                        }));
+                    }
+                    Err(e) => eprintln!("Ignoring color: {e}"),
+                }
+            }
+            Key::Named(NamedKey::Escape) => self.hex_entry = None,
+            Key::Named(NamedKey::Backspace) => {
+                buf.pop();
+            }
+            _ => {
+                if let Some(t) = event.text.as_deref() {
+                    buf.extend(t.chars().filter(char::is_ascii_hexdigit));
+                    buf.truncate(6);
+                }
+            }
+        }
+        self.refresh_overlay();
+        true
+    }
+
+    fn apply_palette(&mut self) {
+        self.refresh_overlay();
+        let palette = self.config.palette.clone();
+        self.with_state(Box::new(move |s| {
+            s.renderer.set_palette(&palette);
+            s.window.request_redraw();
+        }));
+        self.dirty = true;
+    }
+
+    fn on_input_only(&self) -> bool {
+        self.config.redraw == RedrawMode::OnInput
+    }
+
+    // ←/→ でプリセットを dir 個進める
+    fn cycle_preset(&mut self, dir: isize) {
+        let i = presets::step(self.preset, dir);
+        let p = &presets::PRESETS[i];
+        self.preset = Some(i);
+        self.preset_note = Some(Instant::now());
+        // パレットエディタや E での保存がプリセットの色から始まるように合わせておく
+        self.config.palette = Palette {
+            stops: p.palette.to_vec(),
+        };
+        self.config.noise = p.noise;
+        eprintln!("Preset: {}", p.name);
+        self.refresh_overlay();
+        self.with_state(Box::new(move |s| {
+            s.apply_preset(&presets::PRESETS[i]);
+            s.window.request_redraw();
+        }));
+    }
+
+    // 復旧したことが分かるように、しばらくタイトルに出す（--debug-surface 時のみ）
+    fn note_recovery(&mut self, what: &'static str) {
+        if self.config.debug_surface {
+            self.recovery = Some((what, Instant::now()));
+            self.refresh_overlay();
+        }
+    }
+
+    fn recover_surface(&mut self, event_loop: &ActiveEventLoop) {
+        self.surface_errors = 0;
+        self.note_recovery("surface recreated");
+        let Some(s) = self.state.as_mut() else {
+            return;
+        };
+        match s.recreate_surface() {
+            Ok(()) => eprintln!("Surface recreated after repeated errors."),
+            Err(e) => {
+                eprintln!("Surface recreation failed ({e}); rebuilding renderer.");
+                self.rebuild_state(event_loop);
+            }
+        }
+        if let Some(s) = self.state.as_ref() {
+            s.window.request_redraw();
+        }
+    }
+
+    // デバイスから作り直す。失敗したら終了する
+    fn rebuild_state(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(old) = self.state.take() else {
+            return;
+        };
+        let window = old.window.clone();
+        let config = self.current_config(&old);
+        // 古いデバイスとサーフェスを先に解放する
+        drop(old);
+        match build_state(window, &config, &self.shaders) {
+            Ok(state) => {
+                state.window.request_redraw();
+                self.state = Some(state);
+            }
+            Err(e) => {
+                eprintln!("Renderer rebuild failed: {e}");
+                self.failed = true;
+                event_loop.exit();
+            }
+        }
+    }
+
+    // 作り直すときは今のエフェクトのまま始める（N で切り替えていても戻さない）
+    fn current_config(&self, s: &State) -> Config {
+        Config {
+            effect: Some(s.renderer.effect().name.to_string()),
+            ..self.config.clone()
+        }
+    }
+
+    // ドライバのリセットなどでデバイスが失われたら、同じウィンドウのまま State を作り直す
+    fn check_device_lost(&mut self, event_loop: &ActiveEventLoop) {
+        if self.state.as_ref().is_some_and(State::device_lost) {
+            eprintln!("Rebuilding the renderer after device loss.");
+            self.rebuild_state(event_loop);
+            self.note_recovery("device lost: renderer rebuilt");
+        }
+        let mut i = 0;
+        while i < self.outputs.len() {
+            if !self.outputs[i].device_lost() {
+                i += 1;
+                continue;
+            }
+            let old = self.outputs.remove(i);
+            let window = old.window.clone();
+            let config = self.current_config(&old);
+            drop(old);
+            match build_state(window, &config, &self.shaders) {
+                Ok(s) => {
+                    eprintln!(
+                        "Rebuilt the renderer of output {} after device loss.",
+                        i + 1
+                    );
+                    s.window.request_redraw();
+                    self.outputs.insert(i, s);
+                    i += 1;
+                }
+                // ウィンドウも閉じ、残りの出力で続ける
+                Err(e) => eprintln!("Output {} dropped after device loss: {e}", i + 1),
+            }
+        }
+    }
+
+    // --watchdog: 連続描画中にフレームが止まったら、まずサーフェスを構成し直し、
+    // それでも戻らなければ State ごと作り直す。戻るまで作り直しを繰り返す
+    fn check_watchdog(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(limit) = self.config.watchdog.map(Duration::from_secs_f64) else {
+            return;
+        };
+        if !self.animating || self.minimized || self.on_input_only() || self.state.is_none() {
+            return;
+        }
+        // --interval などで次の描画を待っている間は止まっているとみなさない
+        if let Some(t) = self.next_tick {
+            self.last_present = Some(self.last_present.map_or(t, |l| l.max(t)));
+        }
+        let last = *self.last_present.get_or_insert_with(Instant::now);
+        let stalled = last.elapsed();
+        if stalled >= limit {
+            self.stall_level += 1;
+            let s = self.state.as_mut().unwrap();
+            if self.stall_level == 1 {
+                eprintln!(
+                    "Watchdog: no frame for {:.1} s; reconfiguring the surface.",
+                    stalled.as_secs_f64()
+                );
+                let (w, h) = (s.config.width, s.config.height);
+                s.resize(w, h);
+                self.note_recovery("watchdog: surface reconfigured");
+            } else {
+                eprintln!(
+                    "Watchdog: still no frame after {:.1} s; rebuilding the renderer (attempt {}).",
+                    stalled.as_secs_f64(),
+                    self.stall_level - 1
+                );
+                self.rebuild_state(event_loop);
+                self.note_recovery("watchdog: renderer rebuilt");
+            }
+            // 次の段階までまた limit だけ待つ
+            self.last_present = Some(Instant::now());
+            if let Some(s) = self.state.as_ref() {
+                s.window.request_redraw();
+            }
+        }
+        // 描画要求が途絶えても次の確認で起きられるようにする
+        if event_loop.control_flow() == ControlFlow::Wait
+            && let Some(last) = self.last_present
+        {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(last + limit));
+        }
+    }
+}
+
+impl ApplicationHandler<UserEvent> for App {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        match event {
+            UserEvent::RenderFailed(e) => {
+                eprintln!("Renderer rebuild failed: {e}");
+                // スレッドはもう終わっている。State は残っていない
+                if let Some(rt) = self.render_thread.take() {
+                    rt.stop();
+                }
+                self.failed = true;
+                event_loop.exit();
+            }
+            UserEvent::Shader { effect, source } => {
+                // 後から開くウィンドウや作り直した State にも使う
+                self.shaders.insert(effect, source.clone());
+                self.with_state(Box::new(move |s| {
+                    apply_shader(s, effect, &source);
+                    s.window.request_redraw();
+                }));
+            }
+            UserEvent::Params => {
+                let Some(inbox) = self.inbox.as_ref() else {
+                    return;
+                };
+                let values = inbox.drain();
+                self.with_state(Box::new(move |s| {
+                    for (name, value) in &values {
+                        if let Err(e) = s.set_param(name, *value) {
+                            eprintln!("{e}");
+                        }
+                    }
+                    s.window.request_redraw();
+                }));
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        self.check_device_lost(event_loop);
+        self.check_watchdog(event_loop);
+        self.check_power();
+        self.check_idle();
+        if self.on_input_only() {
+            // 入力が無ければ何も要求せず GPU を休ませる。
+            // 主ウィンドウが最小化中なら他の出力だけ描いて下ろす
+            if self.dirty {
+                if let Some(s) = self.state.as_ref().filter(|_| !self.minimized) {
+                    s.window.request_redraw();
+                }
+                for o in &self.outputs {
+                    o.window.request_redraw();
+                }
+                if self.minimized {
+                    self.dirty = false;
+                }
+            }
+            return;
+        }
+        // --interval: 次の描画時刻まで WaitUntil で眠る
+        if !self.animating {
+            return;
+        }
+        let now = Instant::now();
+        if self.output_tick.is_some_and(|t| now >= t) {
+            self.output_tick = None;
+            for o in &self.outputs {
+                o.window.request_redraw();
+            }
+        }
+        if !self.minimized && self.next_tick.is_some_and(|t| now >= t) {
+            self.next_tick = None;
+            if let Some(s) = self.state.as_ref() {
+                s.window.request_redraw();
+            }
+        }
+        let next = [self.next_tick.filter(|_| !self.minimized), self.output_tick]
+            .into_iter()
+            .flatten()
+            .min();
+        event_loop.set_control_flow(next.map_or(ControlFlow::Wait, ControlFlow::WaitUntil));
+    }
+
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = event_loop
-            .create_window(Window::default_attributes().with_title("Swarm Wallpaper"))
-            .expect("create window");
+        // 最初の描画が終わるまで非表示にして黒フレームを見せない
+        let mut attrs = Window::default_attributes()
+            .with_title("Swarm Wallpaper")
+            .with_visible(self.config.show_early);
+
+        if self.config.reset_geometry {
+            Geometry::clear();
+        } else if self.config.remember_geometry && self.config.monitor.is_none() {
+            // CLI で出力先を指定した場合はそちらを優先
+            match Geometry::load() {
+                Some(g) if g.visible_on(event_loop.available_monitors()) => {
+                    attrs = attrs.with_position(g.pos).with_inner_size(g.size);
+                }
+                Some(_) => eprintln!("Saved window geometry is off-screen; ignoring it."),
+                None => (),
+            }
+        }
+
+        if let Some(sel) = &self.config.monitor {
+            match find_monitor(event_loop, sel) {
+                Some(m) => {
+                    attrs = attrs
+                        .with_position(m.position())
+                        .with_fullscreen(Some(Fullscreen::Borderless(Some(m))));
+                }
+                None => {
+                    eprintln!("Monitor {sel:?} not found. Available monitors:");
+                    for (i, m) in event_loop.available_monitors().enumerate() {
+                        eprintln!("  {i}: {}", m.name().unwrap_or_else(|| "<unnamed>".into()));
+                    }
+                    self.failed = true;
+                    event_loop.exit();
+                    return;
+                }
+            }
+        }
 
-        let state = pollster::block_on(State::new(window));
+        if self.config.wallpaper {
+            attrs = attrs.with_decorations(false);
+        }
+        if self.config.overlay {
+            attrs = attrs
+                .with_transparent(true)
+                .with_decorations(false)
+                .with_window_level(WindowLevel::AlwaysOnBottom);
+        }
 
-        state.window.set_visible(true);
+        // --all-monitors: モニターごとに全画面のウィンドウを開く。先頭が最初のウィンドウになる
+        let mut monitors: Vec<MonitorHandle> = Vec::new();
+        if self.config.all_monitors {
+            if self.config.render_thread {
+                eprintln!(
+                    "--all-monitors is not supported with --render-thread; using one window."
+                );
+            } else {
+                monitors = event_loop.available_monitors().collect();
+            }
+        }
+        let per_monitor = |m: &MonitorHandle| {
+            attrs
+                .clone()
+                .with_position(m.position())
+                .with_fullscreen(Some(Fullscreen::Borderless(Some(m.clone()))))
+        };
 
-        self.state = Some(state);
+        let first = monitors.first().map_or_else(|| attrs.clone(), per_monitor);
+        let Some(state) = self.open_window(event_loop, first) else {
+            self.failed = true;
+            event_loop.exit();
+            return;
+        };
+        for m in monitors.iter().skip(1) {
+            match self.open_window(event_loop, per_monitor(m)) {
+                Some(s) => {
+                    s.window.request_redraw();
+                    self.outputs.push(s);
+                }
+                None => eprintln!(
+                    "Skipping monitor {}",
+                    m.name().unwrap_or_else(|| "<unnamed>".into())
+                ),
+            }
+        }
+
+        self.started = Some(Instant::now());
+        self.last_input = self.started;
         self.animating = true;
-        self.state.as_ref().unwrap().window.request_redraw();
-        self.fps_frames = 0;
-        self.fps_last = Some(Instant::now());
-        self.state.as_ref().unwrap().window.request_redraw();
+        self.fps.reset();
+        self.last_frame = Some(Instant::now());
+        if self.config.render_thread {
+            let config = self.config.clone();
+            let shaders = self.shaders.clone();
+            self.render_thread = Some(RenderThread::spawn(
+                state,
+                &self.config,
+                self.on_frame.take(),
+                self.metrics.take(),
+                self.proxy.clone(),
+                Box::new(move |window, effect| {
+                    let config = Config {
+                        effect: Some(effect.to_string()),
+                        ..config.clone()
+                    };
+                    build_state(window, &config, &shaders)
+                }),
+            ));
+            return;
+        }
+        state.window.request_redraw();
+        self.state = Some(state);
     }
 
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
+        // 2つ目以降の出力は描画とサイズ・リフレッシュレートだけを個別に扱う
+        if self.state.as_ref().is_some_and(|s| s.window.id() != id) {
+            match event {
+                WindowEvent::RedrawRequested => self.render_output(id),
+                WindowEvent::Resized(size) => {
+                    if let Some(s) = self.state_for(id)
+                        && size.width > 0
+                        && size.height > 0
+                    {
+                        s.resize(size.width, size.height);
+                        s.window.request_redraw();
+                    }
+                }
+                WindowEvent::Moved(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                    if let Some(s) = self.state_for(id) {
+                        s.update_refresh_rate();
+                        s.update_scale_factor();
+                    }
+                }
+                WindowEvent::KeyboardInput { .. } | WindowEvent::CloseRequested => {
+                    self.window_event_for_all(event_loop, event)
+                }
+                _ => (),
+            }
+            return;
+        }
+        self.window_event_for_all(event_loop, event);
+    }
+}
+
+impl App {
+    // 最初のウィンドウのイベントと、どのウィンドウで起きても全体に効くイベント
+    fn window_event_for_all(&mut self, event_loop: &ActiveEventLoop, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => {
                 self.animating = false;
+                if let Some(rt) = self.render_thread.take() {
+                    self.state = rt.stop();
+                }
                 if let Some(s) = self.state.as_mut() {
-                    let _ = s.device.poll(wgpu::PollType::Wait);
+                    if self.config.remember_geometry
+                        && s.window.fullscreen().is_none()
+                        && let Some(Err(e)) = Geometry::of(&s.window).map(|g| g.save())
+                    {
+                        eprintln!("Could not save window geometry: {e}");
+                    }
+                    let _ = s.renderer.device.poll(wgpu::PollType::Wait);
                 }
                 event_loop.exit();
             }
 
+            WindowEvent::CursorMoved { position, .. } => {
+                self.dirty = true;
+                self.note_input();
+                self.with_state(Box::new(move |s| s.set_mouse(position.x, position.y)));
+            }
+
+            WindowEvent::MouseInput { .. } | WindowEvent::MouseWheel { .. } => {
+                self.dirty = true;
+                self.note_input();
+            }
+
+            WindowEvent::KeyboardInput { event, .. } => {
+                self.dirty = true;
+                if event.state != ElementState::Pressed {
+                    return;
+                }
+                // 止まっていたら最初のキーは再開だけに使う
+                if self.note_input() {
+                    return;
+                }
+                if self.enter_hex(&event) {
+                    return;
+                }
+                // エディタ中は数字・矢印キーをパレット操作に使う（矢印はリピート可）
+                if let PhysicalKey::Code(code) = event.physical_key
+                    && self.edit_palette(code, event.repeat)
+                {
+                    return;
+                }
+                if event.repeat {
+                    return;
+                }
+                if event.physical_key == PhysicalKey::Code(KeyCode::KeyP) {
+                    self.toggle_palette_editor();
+                    return;
+                }
+                if event.physical_key == PhysicalKey::Code(KeyCode::Space) {
+                    self.toggle_pause();
+                    return;
+                }
+                // ←/→: noise のプリセットを順に切り替え
+                if let PhysicalKey::Code(code @ (KeyCode::ArrowLeft | KeyCode::ArrowRight)) =
+                    event.physical_key
+                {
+                    let dir = if code == KeyCode::ArrowRight { 1 } else { -1 };
+                    self.cycle_preset(dir);
+                    return;
+                }
+                let mut cmd: StateFn = match event.physical_key {
+                    // F: 静止スナップショット切り替え / R: 再キャプチャ / I: 色反転
+                    PhysicalKey::Code(KeyCode::KeyF) => Box::new(|s| s.renderer.toggle_frozen()),
+                    PhysicalKey::Code(KeyCode::KeyR) => Box::new(|s| s.renderer.recapture()),
+                    // C: サーフェスフォーマットを順に切り替え
+                    PhysicalKey::Code(KeyCode::KeyC) => Box::new(|s| s.cycle_format()),
+                    // V: present モードを順に切り替え（Fifo → Mailbox → Immediate）
+                    PhysicalKey::Code(KeyCode::KeyV) => Box::new(|s| s.cycle_present_mode()),
+                    PhysicalKey::Code(KeyCode::KeyI) => Box::new(|s| s.renderer.toggle_invert()),
+                    // [ / ]: アニメーション速度を半分/倍に
+                    PhysicalKey::Code(KeyCode::BracketLeft) => {
+                        Box::new(|s| s.renderer.set_speed(s.renderer.speed() * 0.5))
+                    }
+                    PhysicalKey::Code(KeyCode::BracketRight) => {
+                        Box::new(|s| s.renderer.set_speed(s.renderer.speed() * 2.0))
+                    }
+                    // G: gradient の向きを 15 度ずつ回す
+                    PhysicalKey::Code(KeyCode::KeyG) => {
+                        Box::new(|s| s.renderer.rotate_gradient(15.0))
+                    }
+                    // K: 万華鏡の分割数を切り替え（なし → 3 → 4 → 6 → 8 → 12）
+                    PhysicalKey::Code(KeyCode::KeyK) => {
+                        Box::new(|s| s.renderer.cycle_kaleidoscope())
+                    }
+                    // N: 次のエフェクトへ
+                    PhysicalKey::Code(KeyCode::KeyN) => Box::new(|s| s.cycle_effect()),
+                    // D: 今の状態をログに出す（不具合報告用）
+                    PhysicalKey::Code(KeyCode::KeyD) => {
+                        let fps = self.fps.current();
+                        Box::new(move |s| eprintln!("{}", s.dump(fps)))
+                    }
+                    // F12: スクリーンショット（PNG）
+                    PhysicalKey::Code(KeyCode::F12) => {
+                        let depth = self.config.capture_depth;
+                        let dir = self.config.capture_dir.clone().unwrap_or_default();
+                        Box::new(move |s| {
+                            let name = crate::export::frame_name(&s.renderer);
+                            save_png(s, &dir, &name, depth, "capture");
+                        })
+                    }
+                    // S: スクリーンショットをカレントディレクトリに日時の名前で保存
+                    PhysicalKey::Code(KeyCode::KeyS) => Box::new(|s| {
+                        let name = crate::export::screenshot_name();
+                        save_png(s, Path::new(""), &name, Depth::Eight, "screenshot");
+                    }),
+                    // 1/2/3: R/G/B チャンネルのマスク切り替え
+                    PhysicalKey::Code(
+                        code @ (KeyCode::Digit1 | KeyCode::Digit2 | KeyCode::Digit3),
+                    ) => {
+                        let bit = match code {
+                            KeyCode::Digit1 => 0,
+                            KeyCode::Digit2 => 1,
+                            _ => 2,
+                        };
+                        Box::new(move |s| s.renderer.toggle_channel(bit))
+                    }
+                    _ => return,
+                };
+                self.with_state(Box::new(move |s| {
+                    cmd(s);
+                    s.window.request_redraw();
+                }));
+            }
+
+            // モニター間を移動したらリフレッシュレートを取り直す
+            WindowEvent::Moved(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                let verbose = self.config.verbose;
+                self.with_state(Box::new(move |s| {
+                    s.update_scale_factor();
+                    if let Some(hz) = s.update_refresh_rate()
+                        && verbose
+                    {
+                        eprintln!("Refresh rate: {hz:.2} Hz");
+                    }
+                }));
+            }
+
             WindowEvent::Resized(size) => {
-                if let Some(s) = self.state.as_mut() {
+                let minimized = size.width == 0 || size.height == 0;
+                if minimized != self.minimized && self.config.verbose {
+                    eprintln!(
+                        "{}",
+                        if minimized {
+                            "Minimized; rendering paused."
+                        } else {
+                            "Restored."
+                        }
+                    );
+                }
+                if self.minimized && !minimized {
+                    // 最小化していた間を止まっていたとみなさない
+                    self.last_present = None;
+                }
+                self.minimized = minimized;
+                if minimized {
+                    return;
+                }
+                self.dirty = true;
+                self.with_state(Box::new(move |s| {
                     s.resize(size.width, size.height);
                     // 直後に一度描画
                     s.window.request_redraw();
-                }
+                }));
             }
 
+            // 最小化中は Outdated が返り続けるので取得しに行かない
+            WindowEvent::RedrawRequested if self.minimized => (),
             WindowEvent::RedrawRequested => {
+                if self
+                    .recovery
+                    .is_some_and(|(_, t)| t.elapsed() >= RECOVERY_NOTE)
+                {
+                    self.recovery = None;
+                    self.refresh_overlay();
+                }
+                if self.preset_note.is_some_and(|t| t.elapsed() >= PRESET_NOTE) {
+                    self.preset_note = None;
+                    self.refresh_overlay();
+                }
+                let mut recovered = None;
                 if let Some(s) = self.state.as_mut() {
+                    if let Some(hook) = self.on_frame.as_mut() {
+                        let frame = s.renderer.frame;
+                        hook(s, frame);
+                    }
                     match s.render() {
                         Ok(()) => {
-                            self.fps_frames += 1;
-                            if let Some(t0) = self.fps_last {
-                                let dt = t0.elapsed();
-                                if dt >= Duration::from_secs(1) {
-                                    let fps = (self.fps_frames as f64) / (dt.as_secs_f64());
-                                    s.window
-                                        .set_title(&format!("Swarm Wallpaper  |  {:.1} FPS", fps));
-                                    self.fps_frames = 0;
-                                    self.fps_last = Some(Instant::now());
+                            self.surface_errors = 0;
+                            self.timeouts = 0;
+                            self.dirty = false;
+                            let now = Instant::now();
+                            if self.stall_level > 0 {
+                                eprintln!("Watchdog: frames are being presented again.");
+                                self.stall_level = 0;
+                            }
+                            self.last_present = Some(now);
+                            let dt = self.last_frame.map_or(Duration::ZERO, |t| now - t);
+                            self.last_frame = Some(now);
+                            self.worst_dt = self.worst_dt.max(dt);
+                            metrics::record(&mut self.metrics, now, dt);
+                            if let Some(fb) = self.fb.as_mut()
+                                && fb.due(now)
+                            {
+                                let [w, h] = s.renderer.output_size();
+                                let r = s
+                                    .renderer
+                                    .read_rgba8()
+                                    .and_then(|px| fb.send(now, w, h, px));
+                                if let Err(e) = r {
+                                    export_failed("fb output", e);
                                 }
                             }
-                            if self.animating {
+                            self.cpu_sum += s.timings.cpu;
+                            self.wait_sum += s.timings.wait;
+                            if self.fps.tick(dt) {
+                                let fps = self.fps.current().unwrap_or(0.0);
+                                if self.started.is_none_or(|t| t.elapsed() >= ADAPTER_TITLE) {
+                                    s.window.set_title(&title(
+                                        Some(fps),
+                                        s.gpu_ms(),
+                                        self.overlay.clone(),
+                                    ));
+                                }
+                                if self.config.verbose {
+                                    let n = self.fps.window_frames() as f64;
+                                    let bytes = s
+                                        .renderer
+                                        .uniform_bytes
+                                        .saturating_sub(self.uniform_bytes_last);
+                                    eprintln!(
+                                        "{:.1} FPS  worst {:.2} ms  cpu {:.2} ms  wait {:.2} ms  uniforms {:.1} B/frame",
+                                        fps,
+                                        self.worst_dt.as_secs_f64() * 1000.0,
+                                        self.cpu_sum.as_secs_f64() * 1000.0 / n,
+                                        self.wait_sum.as_secs_f64() * 1000.0 / n,
+                                        bytes as f64 / n,
+                                    );
+                                }
+                                self.uniform_bytes_last = s.renderer.uniform_bytes;
+                                self.cpu_sum = Duration::ZERO;
+                                self.wait_sum = Duration::ZERO;
+                                self.worst_dt = Duration::ZERO;
+                            }
+                            if self.config.redraw == RedrawMode::OnInput {
+                                // 次の入力まで待つ
+                            } else if let Some(iv) = self.config.interval {
+                                self.next_tick = Some(Instant::now() + Duration::from_secs_f64(iv));
+                            } else if let Some(n) = self.config.fps_divisor.filter(|n| *n > 1) {
+                                // N-1 回分の vsync を見送り、残り半周期で次を描き始めて
+                                // Fifo の present が N 回目の vsync に乗るようにする
+                                let hz = s.renderer.refresh_hz() as f64;
+                                self.next_tick =
+                                    Some(now + Duration::from_secs_f64((n as f64 - 0.5) / hz));
+                            } else if let Some(cap) = self.config.fps_cap {
+                                // 遅れていたら待たずに次を描く
+                                let budget = Duration::from_secs_f64(1.0 / cap);
+                                let due = (self.cap_due.unwrap_or(now) + budget).max(now);
+                                self.cap_due = Some(due);
+                                self.next_tick = Some(due);
+                            } else if self.animating {
                                 s.window.request_redraw();
                             }
                         }
                         Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                            self.surface_errors += 1;
                             let w = s.config.width;
                             let h = s.config.height;
                             s.resize(w, h); // ★再構成
+                            recovered = Some("surface reconfigured");
                             if self.animating {
                                 s.window.request_redraw();
                             }
@@ -82,19 +1277,67 @@ impl ApplicationHandler for App {
                         Err(wgpu::SurfaceError::OutOfMemory) => {
                             eprintln!("Out of memory — exiting.");
                             self.animating = false;
-                            let _ = s.device.poll(wgpu::PollType::Wait);
+                            let _ = s.renderer.device.poll(wgpu::PollType::Wait);
                             event_loop.exit();
                         }
                         Err(wgpu::SurfaceError::Timeout) => {
-                            // スキップでOK。次フレームで回復しがち
+                            // 単発ならスキップでOK。続くならスワップチェーンが詰まっている
+                            self.timeouts += 1;
+                            let limit = self.config.timeout_limit.unwrap_or(DEFAULT_TIMEOUT_LIMIT);
+                            if self.timeouts >= limit {
+                                eprintln!(
+                                    "Warning: {} consecutive surface timeouts; reconfiguring.",
+                                    self.timeouts
+                                );
+                                self.timeouts = 0;
+                                let (w, h) = (s.config.width, s.config.height);
+                                s.resize(w, h);
+                                recovered = Some("surface timeouts");
+                            }
+                            if self.animating {
+                                s.window.request_redraw();
+                            }
                         }
                         Err(e) => {
                             eprintln!("Surface error: {e:?}");
+                            self.surface_errors += 1;
+                            if self.animating {
+                                s.window.request_redraw();
+                            }
                         }
                     };
                 }
+                if let Some(what) = recovered {
+                    self.note_recovery(what);
+                }
+                if self.surface_errors >= SURFACE_RETRY_LIMIT {
+                    self.recover_surface(event_loop);
+                }
             }
             _ => (),
         }
     }
 }
+
+// 書き出しの失敗はここでまとめてログに出し、描画は続ける
+fn save_png(s: &mut State, dir: &Path, name: &str, depth: Depth, what: &str) {
+    match crate::export::capture_png(&mut s.renderer, dir, name, depth) {
+        Ok(path) => eprintln!("Saved {}", path.display()),
+        Err(e) => export_failed(what, e),
+    }
+}
+
+fn export_failed(what: &str, e: ExportError) {
+    eprintln!("{what} failed: {e}");
+}
+
+// 名前の完全一致を優先し、なければインデックスとして解釈する
+fn find_monitor(event_loop: &ActiveEventLoop, sel: &str) -> Option<MonitorHandle> {
+    let monitors: Vec<MonitorHandle> = event_loop.available_monitors().collect();
+    if let Some(m) = monitors.iter().find(|m| m.name().as_deref() == Some(sel)) {
+        return Some(m.clone());
+    }
+    sel.parse::<usize>()
+        .ok()
+        .and_then(|i| monitors.get(i).cloned())
+}
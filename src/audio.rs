@@ -0,0 +1,166 @@
+// --audio-pcm: モノラル 44.1 kHz の生の PCM（s16le）を FIFO かファイルから読み、
+// FFT で低音・中音・高音の強さ 0..1 を求めてパラメータ bass / mid / treble として送る。
+// 音声デバイスは自分では開かない。再生中の音を使うなら、parec などで書き出した FIFO を渡す:
+//   mkfifo /tmp/swarm-audio
+//   parec -d @DEFAULT_MONITOR@ --format=s16le --rate=44100 --channels=1 > /tmp/swarm-audio
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::remote::ParamInbox;
+
+pub const SAMPLE_RATE: f32 = 44100.0;
+// 1回の解析に使うサンプル数（約 23 ms）
+const FFT_SIZE: usize = 1024;
+// 帯域の境目（Hz）。bass: 20..250, mid: 250..2000, treble: 2000..8000
+const BANDS: [(f32, f32); 3] = [(20.0, 250.0), (250.0, 2000.0), (2000.0, 8000.0)];
+// 下がるときだけゆっくりにして、拍の頭はすぐ反映する（1回の解析あたりの残り具合）
+const RELEASE: f32 = 0.8;
+// 自動ゲインの基準になる最大値の減り方。静かな曲でも 0..1 を使い切る
+const PEAK_DECAY: f32 = 0.999;
+const PEAK_FLOOR: f32 = 1e-3;
+
+pub const PARAMS: [&str; 3] = ["bass", "mid", "treble"];
+
+// 開けなければ音への反応なしで続ける。FIFO は書き手が来るまで開けないのでスレッド内で開く
+pub fn spawn(path: PathBuf, inbox: Arc<ParamInbox>) {
+    let _ = std::thread::Builder::new()
+        .name("audio".into())
+        .spawn(move || {
+            let mut file = match std::fs::File::open(&path) {
+                Ok(f) => f,
+                Err(e) => {
+                    eprintln!("Audio disabled: {}: {e}", path.display());
+                    return;
+                }
+            };
+            let mut analyzer = Analyzer::default();
+            let mut bytes = vec![0u8; FFT_SIZE * 2];
+            loop {
+                if let Err(e) = file.read_exact(&mut bytes) {
+                    // 止まったら静かになったものとして戻しておく
+                    eprintln!("Audio input {} ended: {e}", path.display());
+                    for name in PARAMS {
+                        inbox.push(name, 0.0);
+                    }
+                    return;
+                }
+                let samples: Vec<f32> = bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                    .collect();
+                for (name, v) in PARAMS.iter().zip(analyzer.feed(&samples)) {
+                    inbox.push(name, v);
+                }
+            }
+        });
+}
+
+#[derive(Default)]
+pub struct Analyzer {
+    peak: [f32; 3],
+    level: [f32; 3],
+}
+
+impl Analyzer {
+    // FFT_SIZE 個のサンプルから、滑らかにした帯域ごとの強さ 0..1
+    pub fn feed(&mut self, samples: &[f32]) -> [f32; 3] {
+        let bands = band_magnitudes(samples);
+        for (i, m) in bands.into_iter().enumerate() {
+            self.peak[i] = (self.peak[i] * PEAK_DECAY).max(m).max(PEAK_FLOOR);
+            let v = (m / self.peak[i]).min(1.0);
+            self.level[i] = v.max(self.level[i] * RELEASE);
+        }
+        self.level
+    }
+}
+
+// ハン窓を掛けた FFT の振幅を帯域ごとに平均する
+fn band_magnitudes(samples: &[f32]) -> [f32; 3] {
+    let n = samples.len();
+    let mut re: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let w = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / n as f32).cos();
+            s * w
+        })
+        .collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    let bin_hz = SAMPLE_RATE / n as f32;
+    BANDS.map(|(lo, hi)| {
+        let (a, b) = ((lo / bin_hz).ceil() as usize, (hi / bin_hz) as usize);
+        let b = b.min(n / 2);
+        if a > b {
+            return 0.0;
+        }
+        let sum: f32 = (a..=b).map(|k| re[k].hypot(im[k])).sum();
+        sum / (b - a + 1) as f32
+    })
+}
+
+// 長さが 2 の累乗の、その場で計算する radix-2 FFT
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two());
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -std::f32::consts::TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (s, c) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * c - im[b] * s;
+                let ti = re[b] * s + im[b] * c;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(hz: f32) -> Vec<f32> {
+        (0..FFT_SIZE)
+            .map(|i| (std::f32::consts::TAU * hz * i as f32 / SAMPLE_RATE).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn tones_land_in_their_band() {
+        for (hz, band) in [(80.0, 0), (800.0, 1), (5000.0, 2)] {
+            let m = band_magnitudes(&tone(hz));
+            let loudest = (0..3).max_by(|&a, &b| m[a].total_cmp(&m[b])).unwrap();
+            assert_eq!(loudest, band, "{hz} Hz: {m:?}");
+        }
+    }
+
+    #[test]
+    fn levels_jump_up_and_fall_slowly() {
+        let mut a = Analyzer::default();
+        let loud = a.feed(&tone(80.0));
+        assert!(loud[0] > 0.9, "{loud:?}");
+        let quiet = a.feed(&vec![0.0; FFT_SIZE]);
+        assert!(quiet[0] > 0.5 && quiet[0] < loud[0], "{quiet:?}");
+    }
+}
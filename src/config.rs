@@ -0,0 +1,938 @@
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Path, PathBuf};
+
+use crate::effects;
+use crate::palette::Palette;
+use crate::png::Depth;
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // 最初の描画を待たずにウィンドウを表示する（旧挙動）
+    pub show_early: bool,
+    // 名前（MonitorHandle::name）またはインデックスで出力先を指定
+    pub monitor: Option<String>,
+    pub verbose: bool,
+    pub invert: bool,
+    // 軸ごとのドリフト速度（60 FPS 換算のピクセル/フレーム）。0 ならその軸は静止
+    pub time_scale: [f32; 2],
+    // 指定秒ごとに1フレームだけ描画し、その間はアイドル
+    pub interval: Option<f64>,
+    pub redraw: RedrawMode,
+    // リフレッシュレートの 1/N で描画する（60Hz で 2 なら 30fps）
+    pub fps_divisor: Option<u32>,
+    // 描画回数/秒の上限。present モードとは別にアイドルで待つ
+    pub fps_cap: Option<f64>,
+    // 電池で動いている間だけ使う fps_cap。電源につながっているときは fps_cap に戻す
+    pub battery_fps: Option<f64>,
+    // サーフェスの present モード（fifo / mailbox / immediate）。対応していなければ Fifo
+    pub present_mode: wgpu::PresentMode,
+    // swarm の MSAA のサンプル数（1, 2, 4, 8）。0 と 1 は無効。対応していなければ下げる
+    pub msaa: u32,
+    // swarm のコンピュートのワークグループの大きさ（64, 128, 256）。未指定なら 64
+    pub workgroup: Option<u32>,
+    pub color_space: ColorSpace,
+    // 模様の乱数の種。指定しなければ起動ごとに変わる（Config::default() は 0 と同じ）
+    pub seed: Option<u32>,
+    // この回数連続で Timeout が出たらサーフェスを構成し直す（既定 5）
+    pub timeout_limit: Option<u32>,
+    // この秒数フレームが完了しなければサーフェスの再構成 → State の作り直しと段階的に復旧する
+    pub watchdog: Option<f64>,
+    // この秒数入力がなければアニメーションを止め、次の入力で再開する
+    pub idle_timeout: Option<f64>,
+    // 接続中のモニターごとにウィンドウを開き、それぞれ全画面で描く
+    pub all_monitors: bool,
+    // 背景に敷く PNG 画像と、その上に重ねるエフェクトの不透明度（既定 1）
+    pub background: Option<PathBuf>,
+    pub overlay_opacity: Option<f32>,
+    // カーソルへの反応の強さ（swarm は引き寄せ、noise は明るさ）。負なら逆向き、0 で無効
+    pub mouse_force: f32,
+    // 描画を専用スレッドで行う（render_thread.rs 参照）
+    pub render_thread: bool,
+    // bit0=R, bit1=G, bit2=B。立っているビットのチャンネルを0にする
+    pub channel_mask: u32,
+    // シーンを描く解像度の倍率 (0.1..1)。未指定なら 1、または高DPI検出で自動設定
+    pub render_scale: Option<f32>,
+    pub auto_render_scale: bool,
+    // これを超える画素数の出力を高DPIとみなす
+    pub hidpi_threshold: Option<u64>,
+    // アニメーション速度の倍率（[ と ] で半分/倍）
+    pub speed: Option<f32>,
+    // シーンを描くエフェクト名（effects.rs）。未指定なら noise
+    pub effect: Option<String>,
+    // starfield: 星の密度 0..1 と速度
+    pub star_density: Option<f32>,
+    pub star_speed: Option<f32>,
+    // matrix: 落下速度と列の密度 0..1
+    pub rain_speed: Option<f32>,
+    pub rain_density: Option<f32>,
+    // gradient: 向き（度、反時計回り、0 で左→右）。radial なら中心からの放射状
+    pub gradient_angle: f32,
+    pub gradient_radial: bool,
+    // swarm: 0 以外ならエージェントを縁の滑らかな円で描く（縁の幅、ピクセル）
+    pub particle_edge: f32,
+    // swarm: エージェント数（既定 1024）
+    pub agents: Option<u32>,
+    // swarm: 画面の面積に合わせてエージェント数を増減する。agents は最初の大きさでの数で、
+    // max_agents（既定は agents の 4 倍）まで増やす
+    pub scale_agents: bool,
+    pub max_agents: Option<u32>,
+    // swarm: 指定するとこの刻み（秒）の固定ステップで進め、フレーム間は補間する
+    pub fixed_dt: Option<f32>,
+    // swarm: エージェントの寿命の範囲（秒）。未指定なら死なない
+    pub lifetime: Option<[f32; 2]>,
+    // swarm: 画面の端に来たエージェントの扱い
+    pub edge: Edge,
+    // swarm: 色に使う速さを前のステップの値とこの割合で混ぜる（0..1）。0 なら混ぜない
+    pub speed_smooth: f32,
+    // swarm: エージェント同士をこれ以上近づけない距離（画面の高さが 2）。0 なら無効
+    pub min_dist: f32,
+    // 輝度を色に写すパレット（"#rrggbb, ..."）。空ならグレースケール
+    pub palette: Palette,
+    // 2〜3色の簡易パレット（--color-a/-b/-c）。指定があれば palette の代わりに a → b → c と並べる
+    pub colors: [Option<[u8; 3]>; 3],
+    // 黒の部分に敷く色（sRGB）。既定は黒で変化なし
+    pub base_color: [u8; 3],
+    // 前フレームとの混合率 0..1（ちらつき低減）。0 なら混ぜない
+    pub smooth: f32,
+    // 残像: 前フレームにこの値を掛けて今のフレームに足す（0..1）。0 なら無効、有効なら smooth より優先
+    pub trail_decay: f32,
+    // 最終段でシーンを読む UV の変換 [a, b, c, d, tx, ty]（renderer::UV_IDENTITY 参照）
+    pub uv_transform: Option<[f32; 6]>,
+    // 万華鏡の分割数（2 未満で無効）と中心（UV、既定は画面中央）
+    pub kaleidoscope: u32,
+    pub kaleidoscope_center: Option<[f32; 2]>,
+    // 前フレームから変化した部分を明るくする強さ。0 なら無効
+    pub motion_boost: f32,
+    // パラメータを1行ずつ受け取る名前付きパイプ（remote.rs 参照）
+    pub param_pipe: Option<PathBuf>,
+    // 描画結果を生の RGBA で書き出す先（fbout.rs 参照）と、書き出し回数/秒
+    pub fb_output: Option<PathBuf>,
+    pub fb_rate: Option<f64>,
+    // フレーム間隔を追記する CSV（metrics.rs 参照）
+    pub log_metrics: Option<PathBuf>,
+    // F12 のスクリーンショットのビット深度と保存先（既定はカレントディレクトリ）
+    pub capture_depth: Depth,
+    pub capture_dir: Option<PathBuf>,
+    // 指定すると窓を開かずにフレーム範囲を生の RGBA で書き出して終了する
+    pub export: Option<PathBuf>,
+    pub export_start: u32,
+    pub frames: Option<u32>,
+    pub export_size: Option<[u32; 2]>,
+    // 書き出しで同時に待つ読み戻しの上限（既定 3）。1 なら1フレームずつ待つ
+    pub export_in_flight: Option<usize>,
+    // 窓を開かずに frames 枚を PNG で書き出して終了する。出力先は位置引数（既定はカレントディレクトリ）
+    pub headless: bool,
+    pub headless_dir: Option<PathBuf>,
+    // noise の代わりに描く利用者のシェーダー（fs_main を含む本体。effects::load_custom）
+    pub shader: Option<PathBuf>,
+    // 名前にこの文字列を含むアダプタを優先する（大文字小文字は区別しない）
+    pub adapter: Option<String>,
+    // 使えるアダプタを表示して終了する
+    pub list_adapters: bool,
+    // OSC を受け付ける UDP ポート（osc フィーチャ有効時のみ）
+    pub osc_port: Option<u16>,
+    // [midi] セクション（midi フィーチャ有効時のみ）
+    pub midi_device: Option<PathBuf>,
+    pub midi_map: Vec<MidiMapping>,
+    // 音に反応させる生の PCM（s16le）の FIFO かファイル（audio フィーチャ有効時のみ、audio.rs 参照）
+    pub audio_pcm: Option<PathBuf>,
+    // 終了時のウィンドウ位置・サイズを保存して次回起動時に復元する
+    pub remember_geometry: bool,
+    pub reset_geometry: bool,
+    // 設定とシェーダーを検証して終了する（ウィンドウは開かない）
+    pub dry_run: bool,
+    // サーフェスの再構成・作り直しをしばらくタイトルに出す
+    pub debug_surface: bool,
+    // デスクトップの背景として描く（Windows ではアイコンの裏の WorkerW に入れる）
+    pub wallpaper: bool,
+    // 透明で最背面の、クリックを通すウィンドウとして描く。暗いところからデスクトップが見える
+    pub overlay: bool,
+    // 設定ファイルに誤りがあれば既定値で続けずに終了する
+    pub strict_config: bool,
+    // 時間方向のアンチエイリアス（フレームごとにずらして前フレームと混ぜる）
+    pub taa: bool,
+    // 開発用: noise.wgsl をディスクから読み、保存されるたびに作り直す
+    pub watch: bool,
+    // 起動できなくなったとき用。設定ファイルを読まず、最小限の設定で起動する
+    pub safe_mode: bool,
+    // noise エフェクトの種類
+    pub noise: NoiseKind,
+    // 読み込めずに無視した設定ファイルのエラー（--dry-run で報告する）
+    pub config_error: Option<String>,
+}
+
+// continuous: 毎フレーム描画 / on-input: 入力やリサイズがあったときだけ描画
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RedrawMode {
+    #[default]
+    Continuous,
+    OnInput,
+}
+
+// noise の種類。hash は毎フレーム変わる砂嵐、それ以外は時間とともに流れる滑らかな模様。
+// 値はそのまま Params.noise_kind としてシェーダーに渡す
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseKind {
+    #[default]
+    Hash = 0,
+    Value = 1,
+    Perlin = 2,
+    Simplex = 3,
+}
+
+// swarm の端の扱い。wrap は反対側へ回り込み、bounce は速度を反転して跳ね返り、clamp は端で止める。
+// 値はそのまま Params.edge としてシェーダーに渡す
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Edge {
+    #[default]
+    Wrap = 0,
+    Bounce = 1,
+    Clamp = 2,
+}
+
+// サーフェスの形式の選び方。srgb は sRGB 形式でGPUに変換させ、linear は sRGB でない形式を選んで
+// 最終段のシェーダーで変換する。どちらも見た目は同じになる
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+// MIDI CC 番号 → パラメータ名と値域
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "midi"), allow(dead_code))]
+pub struct MidiMapping {
+    pub cc: u8,
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+// 値を取らないCLIフラグ
+const SWITCHES: &[&str] = &[
+    "show_early",
+    "verbose",
+    "invert",
+    "remember_geometry",
+    "reset_geometry",
+    "dry_run",
+    "auto_render_scale",
+    "render_thread",
+    "debug_surface",
+    "wallpaper",
+    "overlay",
+    "strict_config",
+    "taa",
+    "all_monitors",
+    "watch",
+    "safe_mode",
+    "headless",
+    "list_adapters",
+];
+
+impl Config {
+    // デフォルト → 設定ファイル → CLI の順に上書き
+    pub fn load() -> Result<Self, String> {
+        Self::load_from(
+            config_path().filter(|p| p.exists()).as_deref(),
+            std::env::args().skip(1),
+        )
+    }
+
+    // 設定ファイルに誤りがあれば場所を報告してファイル全体を無視する。
+    // 打ち間違い1つで常駐の起動が止まらないように、終了するのは --strict-config のときだけ
+    pub fn load_from(
+        path: Option<&Path>,
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<Self, String> {
+        let args: Vec<String> = args.into_iter().collect();
+        let safe = args.iter().any(|a| a == "--safe-mode");
+        let mut cfg = Config::default();
+        let mut file_error = None;
+        if let Some(path) = path.filter(|_| !safe) {
+            let mut from_file = Config::default();
+            match from_file.merge_file(path) {
+                Ok(()) => cfg = from_file,
+                Err(e) => file_error = Some(e),
+            }
+        }
+        cfg.apply_args(args)?;
+        if let Some(e) = file_error {
+            if cfg.strict_config {
+                return Err(e);
+            }
+            eprintln!("{e}\nIgnoring the config file and using defaults.");
+            cfg.config_error = Some(e);
+        }
+        cfg.apply_colors();
+        if cfg.seed.is_none() {
+            let seed = RandomState::new().hash_one("seed") as u32;
+            cfg.seed = Some(seed);
+            if cfg.verbose {
+                eprintln!("Seed {seed} (--seed {seed} reproduces this pattern)");
+            }
+        }
+        if cfg.safe_mode {
+            let forced = cfg.force_safe_settings();
+            eprintln!(
+                "Safe mode: config file ignored; forced {}.",
+                forced.join(", ")
+            );
+        }
+        Ok(cfg)
+    }
+
+    // --color-a/-b/-c をパレットにする。a だけなら白へ、b だけなら黒から
+    fn apply_colors(&mut self) {
+        let [a, b, c] = self.colors;
+        if a.is_none() && b.is_none() {
+            return;
+        }
+        let mut stops = vec![a.unwrap_or([0, 0, 0]), b.unwrap_or([255, 255, 255])];
+        stops.extend(c);
+        self.palette = Palette { stops };
+    }
+
+    // --safe-mode: 起動を妨げそうな設定を無難な値にする。変えたものの説明を返す
+    fn force_safe_settings(&mut self) -> Vec<&'static str> {
+        // 初期値に戻しておけば、CLI で何を指定していても同じ状態になる
+        let safe = Config {
+            safe_mode: true,
+            render_scale: Some(0.5),
+            verbose: self.verbose,
+            dry_run: self.dry_run,
+            seed: self.seed,
+            ..Config::default()
+        };
+        let mut forced = vec!["effect noise", "render scale 0.5"];
+        let extras = [
+            (self.render_thread, "no render thread"),
+            (self.wallpaper, "no wallpaper mode"),
+            (self.overlay, "no overlay mode"),
+            (self.adapter.is_some(), "the default adapter"),
+            (self.shader.is_some(), "built-in shaders"),
+            (self.all_monitors, "a single window"),
+            (self.watch, "no shader watching"),
+            (self.taa, "no TAA"),
+            (self.msaa > 1, "no MSAA"),
+            (
+                self.present_mode != wgpu::PresentMode::Fifo,
+                "Fifo presentation",
+            ),
+            (self.background.is_some(), "no background image"),
+            (
+                self.param_pipe.is_some() || self.osc_port.is_some() || self.audio_pcm.is_some(),
+                "no remote parameters",
+            ),
+            (self.export.is_some(), "no export"),
+            (self.headless, "no headless rendering"),
+        ];
+        forced.extend(extras.iter().filter(|(on, _)| *on).map(|(_, what)| *what));
+        *self = safe;
+        forced
+    }
+
+    pub fn apply_args(&mut self, args: impl IntoIterator<Item = String>) -> Result<(), String> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // --key=value 形式も受け付ける
+            let (name, inline) = match arg.split_once('=') {
+                Some((n, v)) => (n, Some(v)),
+                None => (arg.as_str(), None),
+            };
+            let key = match name {
+                "-v" => "verbose".to_string(),
+                a if a.starts_with("--") => a[2..].replace('-', "_"),
+                // 位置引数は --headless の出力先だけ
+                _ if self.headless_dir.is_none() => {
+                    self.headless_dir = Some(PathBuf::from(&arg));
+                    continue;
+                }
+                _ => return Err(format!("unknown argument: {arg}")),
+            };
+            if let Some(v) = inline {
+                self.set(&key, v)?;
+            } else if SWITCHES.contains(&key.as_str()) {
+                self.set(&key, "true")?;
+            } else {
+                let v = args
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires a value"))?;
+                self.set(&key, &v)?;
+            }
+        }
+        if let Some(dir) = &self.headless_dir
+            && !self.headless
+        {
+            return Err(format!("unknown argument: {}", dir.display()));
+        }
+        Ok(())
+    }
+
+    // `key = value` 形式のTOMLサブセット。[section] はキーに "section." を付ける
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        self.merge_str(&text)
+            .map_err(|e| format!("{}:{e}", path.display()))
+    }
+
+    pub fn merge_str(&mut self, text: &str) -> Result<(), String> {
+        let mut section = String::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let n = i + 1;
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("{n}: expected `]` to close the section: {line:?}"))?;
+                section = name.trim().to_string();
+                continue;
+            }
+            let (k, v) = line
+                .split_once('=')
+                .ok_or_else(|| format!("{n}: expected `key = value`, found {line:?}"))?;
+            let (k, v) = (k.trim(), v.trim());
+            if k.is_empty() {
+                return Err(format!("{n}: missing key before `=`"));
+            }
+            if v.starts_with('"') && (v.len() < 2 || !v.ends_with('"')) {
+                return Err(format!("{n}: unterminated string for `{k}`"));
+            }
+            let key = if section.is_empty() {
+                k.to_string()
+            } else {
+                format!("{section}.{k}")
+            };
+            self.set(&key, unquote(v))
+                .map_err(|e| format!("{n}: {e}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, v: &str) -> Result<(), String> {
+        match key {
+            "show_early" => self.show_early = parse(key, v)?,
+            "monitor" => self.monitor = Some(v.to_string()),
+            "verbose" => self.verbose = parse(key, v)?,
+            "invert" => self.invert = parse(key, v)?,
+            "time_scale" => self.time_scale = parse_vec2(key, v)?,
+            "interval" => self.interval = Some(parse(key, v)?),
+            "timeout_limit" => self.timeout_limit = Some(parse(key, v)?),
+            "watchdog" => self.watchdog = Some(parse(key, v)?).filter(|s| *s > 0.0),
+            "idle_timeout" => self.idle_timeout = Some(parse(key, v)?).filter(|s| *s > 0.0),
+            "render_thread" => self.render_thread = parse(key, v)?,
+            "fps_cap" => self.fps_cap = Some(parse(key, v)?).filter(|c| *c > 0.0),
+            "present_mode" => {
+                self.present_mode = match v {
+                    "fifo" => wgpu::PresentMode::Fifo,
+                    "mailbox" => wgpu::PresentMode::Mailbox,
+                    "immediate" => wgpu::PresentMode::Immediate,
+                    _ => {
+                        return Err(format!(
+                            "invalid value for `{key}`: {v:?} (expected fifo, mailbox or immediate)"
+                        ));
+                    }
+                }
+            }
+            "seed" => self.seed = Some(parse(key, v)?),
+            "msaa" => {
+                self.msaa = parse(key, v)?;
+                if ![1, 2, 4, 8].contains(&self.msaa) {
+                    return Err(format!(
+                        "invalid value for `{key}`: {v:?} (expected 1, 2, 4 or 8)"
+                    ));
+                }
+            }
+            "workgroup" => {
+                let n = parse(key, v)?;
+                if ![64, 128, 256].contains(&n) {
+                    return Err(format!(
+                        "invalid value for `{key}`: {v:?} (expected 64, 128 or 256)"
+                    ));
+                }
+                self.workgroup = Some(n);
+            }
+            "battery_fps" => self.battery_fps = Some(parse(key, v)?).filter(|c| *c > 0.0),
+            "fps_divisor" => self.fps_divisor = Some(parse::<u32>(key, v)?.max(1)),
+            "redraw" => {
+                self.redraw = match v {
+                    "continuous" => RedrawMode::Continuous,
+                    "on-input" => RedrawMode::OnInput,
+                    _ => {
+                        return Err(format!(
+                            "invalid value for `{key}`: {v:?} (expected continuous or on-input)"
+                        ));
+                    }
+                }
+            }
+            "remember_geometry" => self.remember_geometry = parse(key, v)?,
+            "reset_geometry" => self.reset_geometry = parse(key, v)?,
+            "dry_run" => self.dry_run = parse(key, v)?,
+            "debug_surface" => self.debug_surface = parse(key, v)?,
+            "wallpaper" => self.wallpaper = parse(key, v)?,
+            "overlay" => self.overlay = parse(key, v)?,
+            "strict_config" => self.strict_config = parse(key, v)?,
+            "taa" => self.taa = parse(key, v)?,
+            "color_space" => {
+                self.color_space = match v {
+                    "srgb" => ColorSpace::Srgb,
+                    "linear" => ColorSpace::Linear,
+                    _ => {
+                        return Err(format!(
+                            "invalid value for `{key}`: {v:?} (expected srgb or linear)"
+                        ));
+                    }
+                }
+            }
+            "noise" => {
+                self.noise = match v {
+                    "hash" => NoiseKind::Hash,
+                    "value" => NoiseKind::Value,
+                    "perlin" => NoiseKind::Perlin,
+                    "simplex" => NoiseKind::Simplex,
+                    _ => {
+                        return Err(format!(
+                            "invalid value for `{key}`: {v:?} (expected hash, value, perlin or simplex)"
+                        ));
+                    }
+                }
+            }
+            "all_monitors" => self.all_monitors = parse(key, v)?,
+            "watch" => self.watch = parse(key, v)?,
+            "safe_mode" => self.safe_mode = parse(key, v)?,
+            "background" => self.background = Some(PathBuf::from(v)),
+            "mouse_force" => self.mouse_force = parse(key, v)?,
+            "overlay_opacity" => self.overlay_opacity = Some(parse::<f32>(key, v)?.clamp(0.0, 1.0)),
+            "midi.device" => self.midi_device = Some(PathBuf::from(v)),
+            k if k.starts_with("midi.cc") => {
+                let cc = parse::<u8>(key, &k["midi.cc".len()..])?;
+                let parts: Vec<&str> = v.split_whitespace().collect();
+                let [name, min, max] = parts.as_slice() else {
+                    return Err(format!(
+                        "invalid value for `{key}`: expected \"name min max\""
+                    ));
+                };
+                self.midi_map.retain(|m| m.cc != cc);
+                self.midi_map.push(MidiMapping {
+                    cc,
+                    name: name.to_string(),
+                    min: parse(key, min)?,
+                    max: parse(key, max)?,
+                });
+            }
+            "fb_output" => self.fb_output = Some(PathBuf::from(v)),
+            "fb_rate" => self.fb_rate = Some(parse(key, v)?),
+            "log_metrics" => self.log_metrics = Some(PathBuf::from(v)),
+            "capture_depth" => {
+                self.capture_depth = match v {
+                    "8" => Depth::Eight,
+                    "16" => Depth::Sixteen,
+                    _ => {
+                        return Err(format!(
+                            "invalid value for `{key}`: {v:?} (expected 8 or 16)"
+                        ));
+                    }
+                }
+            }
+            "capture_dir" => self.capture_dir = Some(PathBuf::from(v)),
+            "export" => self.export = Some(PathBuf::from(v)),
+            "export_start" => self.export_start = parse(key, v)?,
+            "headless" => self.headless = parse(key, v)?,
+            "adapter" => self.adapter = Some(v.to_string()),
+            "shader" => self.shader = Some(PathBuf::from(v)),
+            "list_adapters" => self.list_adapters = parse(key, v)?,
+            "frames" => self.frames = Some(parse(key, v)?),
+            "export_in_flight" => self.export_in_flight = Some(parse::<usize>(key, v)?.max(1)),
+            "export_size" => {
+                let (w, h) = v
+                    .split_once('x')
+                    .ok_or_else(|| format!("invalid value for `{key}`: {v:?} (expected WxH)"))?;
+                self.export_size = Some([parse(key, w)?, parse(key, h)?]);
+            }
+            "osc_port" => self.osc_port = Some(parse(key, v)?),
+            "audio_pcm" => self.audio_pcm = Some(PathBuf::from(v)),
+            "param_pipe" => self.param_pipe = Some(PathBuf::from(v)),
+            "channel_mask" => self.channel_mask = parse::<u32>(key, v)? & 0b111,
+            "render_scale" | "scale" => self.render_scale = Some(parse(key, v)?),
+            "auto_render_scale" => self.auto_render_scale = parse(key, v)?,
+            "hidpi_threshold" => self.hidpi_threshold = Some(parse(key, v)?),
+            "palette" => {
+                self.palette = Palette::parse(v).map_err(|e| format!("invalid `{key}`: {e}"))?
+            }
+            "speed" => self.speed = Some(parse(key, v)?),
+            "effect" => {
+                if effects::find(v).is_none() {
+                    return Err(format!(
+                        "unknown effect {v:?} (available: {})",
+                        effects::names()
+                    ));
+                }
+                self.effect = Some(v.to_string());
+            }
+            "star_density" => self.star_density = Some(parse::<f32>(key, v)?.clamp(0.0, 1.0)),
+            "star_speed" => self.star_speed = Some(parse(key, v)?),
+            "rain_speed" => self.rain_speed = Some(parse(key, v)?),
+            "rain_density" => self.rain_density = Some(parse::<f32>(key, v)?.clamp(0.0, 1.0)),
+            "fixed_dt" => self.fixed_dt = Some(parse(key, v)?),
+            "lifetime" => {
+                let [a, b] = parse_vec2(key, v)?;
+                self.lifetime = Some([a.min(b).max(0.0), a.max(b).max(0.0)]);
+            }
+            "agents" => self.agents = Some(parse::<u32>(key, v)?.max(1)),
+            "scale_agents" => self.scale_agents = parse(key, v)?,
+            "max_agents" => self.max_agents = Some(parse::<u32>(key, v)?.max(1)),
+            "particle_edge" => self.particle_edge = parse::<f32>(key, v)?.max(0.0),
+            "min_dist" => self.min_dist = parse::<f32>(key, v)?.max(0.0),
+            "speed_smooth" => self.speed_smooth = parse::<f32>(key, v)?.clamp(0.0, 0.99),
+            "edge" => {
+                self.edge = match v {
+                    "wrap" => Edge::Wrap,
+                    "bounce" => Edge::Bounce,
+                    "clamp" => Edge::Clamp,
+                    _ => {
+                        return Err(format!(
+                            "invalid value for `{key}`: {v:?} (expected wrap, bounce or clamp)"
+                        ));
+                    }
+                }
+            }
+            "gradient_angle" => {
+                (self.gradient_angle, self.gradient_radial) = match v {
+                    "horizontal" => (0.0, false),
+                    "vertical" => (90.0, false),
+                    "diagonal" => (45.0, false),
+                    "radial" => (self.gradient_angle, true),
+                    _ => (parse(key, v)?, false),
+                }
+            }
+            "base_color" => {
+                self.base_color =
+                    crate::palette::parse_hex(v).map_err(|e| format!("invalid `{key}`: {e}"))?
+            }
+            "palette_file" => self.palette = Palette::load(Path::new(v))?,
+            "color_a" | "color_b" | "color_c" => {
+                let i = match key {
+                    "color_a" => 0,
+                    "color_b" => 1,
+                    _ => 2,
+                };
+                self.colors[i] = Some(
+                    crate::palette::parse_hex(v).map_err(|e| format!("invalid `{key}`: {e}"))?,
+                );
+            }
+            "smooth" => self.smooth = parse::<f32>(key, v)?.clamp(0.0, 1.0),
+            "uv_transform" => self.uv_transform = Some(parse_uv_transform(v)?),
+            "kaleidoscope" => self.kaleidoscope = parse(key, v)?,
+            "kaleidoscope_center" => self.kaleidoscope_center = Some(parse_vec2(key, v)?),
+            "motion_boost" => self.motion_boost = parse::<f32>(key, v)?.max(0.0),
+            "trail_decay" => self.trail_decay = parse::<f32>(key, v)?.clamp(0.0, 1.0),
+            _ => return Err(format!("unknown option `{key}`")),
+        }
+        Ok(())
+    }
+}
+
+// プリセット名か "a b c d tx ty" の6つの数（カンマ区切りや TOML の配列も可）
+fn parse_uv_transform(v: &str) -> Result<[f32; 6], String> {
+    Ok(match v {
+        "none" => crate::renderer::UV_IDENTITY,
+        "mirror-x" => [-1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+        "mirror-y" => [1.0, 0.0, 0.0, -1.0, 0.0, 0.0],
+        // 左上のタイルが元の向きになるよう中心をずらす
+        "tile-2x" => [2.0, 0.0, 0.0, 2.0, 0.5, 0.5],
+        _ => {
+            let n: Vec<f32> = v
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|t| !t.is_empty())
+                .map(|t| parse("uv_transform", t))
+                .collect::<Result<_, _>>()?;
+            n.try_into().map_err(|_| {
+                format!(
+                    "invalid `uv_transform`: {v:?} (expected none, mirror-x, mirror-y, tile-2x \
+                     or six numbers a b c d tx ty)"
+                )
+            })?
+        }
+    })
+}
+
+fn parse<T: std::str::FromStr>(key: &str, v: &str) -> Result<T, String> {
+    v.parse()
+        .map_err(|_| format!("invalid value for `{key}`: {v:?}"))
+}
+
+// "x,y" または TOML の配列 "[x, y]"
+fn parse_vec2(key: &str, v: &str) -> Result<[f32; 2], String> {
+    let inner = v.trim_start_matches('[').trim_end_matches(']');
+    let parts: Vec<&str> = inner.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+        [x, y] => Ok([parse(key, x)?, parse(key, y)?]),
+        _ => Err(format!("invalid value for `{key}`: {v:?} (expected x,y)")),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn unquote(v: &str) -> &str {
+    v.strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(v)
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(d) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(d).join("swarm-wallpaper"));
+    }
+    if let Some(d) = std::env::var_os("APPDATA") {
+        return Some(PathBuf::from(d).join("swarm-wallpaper"));
+    }
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config").join("swarm-wallpaper"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("config.toml"))
+}
+
+// 設定ファイルのトップレベルのキーを書き換える（なければ先頭に足す）。value はそのまま書く
+pub fn save_key(key: &str, value: &str) -> Result<PathBuf, String> {
+    let path = config_path().ok_or("no config directory")?;
+    let text = std::fs::read_to_string(&path).unwrap_or_default();
+    let line = format!("{key} = {value}");
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    // 最初の [section] より前だけを探す
+    let top = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top].iter().position(|l| {
+        strip_comment(l)
+            .split_once('=')
+            .is_some_and(|(k, _)| k.trim() == key)
+    });
+    match existing {
+        Some(i) => lines[i] = line,
+        None => lines.insert(0, line),
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    }
+    std::fs::write(&path, lines.join("\n") + "\n")
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(text: &str) -> Result<Config, String> {
+        let mut cfg = Config::default();
+        cfg.merge_str(text).map(|()| cfg)
+    }
+
+    #[test]
+    fn malformed_lines_report_line_and_key() {
+        let cases = [
+            ("verbose = true\nbogus = 1\n", "2: unknown option `bogus`"),
+            ("\n\nspeed = fast\n", "3: invalid value for `speed`"),
+            ("smooth 0.5\n", "1: expected `key = value`"),
+            ("[midi\ndevice = \"x\"\n", "1: expected `]`"),
+            ("= 3\n", "1: missing key"),
+            ("effect = \"noise\n", "1: unterminated string for `effect`"),
+            (
+                "[midi]\ncc7 = \"speed\"\n",
+                "2: invalid value for `midi.cc7`",
+            ),
+        ];
+        for (text, want) in cases {
+            let err = merge(text)
+                .err()
+                .unwrap_or_else(|| panic!("{text:?} parsed"));
+            assert!(
+                err.starts_with(want),
+                "{text:?}: got {err:?}, want {want:?}"
+            );
+        }
+        // コメント中や文字列中の記号は誤りにしない
+        let cfg = merge("# [broken\nbase_color = \"#102030\" # = x\n").unwrap();
+        assert_eq!(
+            cfg.base_color,
+            crate::palette::parse_hex("#102030").unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_file_falls_back_to_defaults_unless_strict() {
+        let path = std::env::temp_dir().join(format!("swarm-config-{}.toml", std::process::id()));
+        std::fs::write(&path, "speed = 2\nsmoth = 0.5\n").unwrap();
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        // ファイル全体を無視し、CLI の指定だけが効く
+        let cfg = Config::load_from(Some(&path), args(&["--invert"])).unwrap();
+        assert_eq!(cfg.speed, Config::default().speed);
+        assert!(cfg.invert);
+        let err = cfg.config_error.unwrap();
+        assert!(err.contains(":2: unknown option `smoth`"), "{err}");
+
+        let err = Config::load_from(Some(&path), args(&["--strict-config"])).unwrap_err();
+        assert!(err.contains(":2: unknown option `smoth`"), "{err}");
+
+        std::fs::write(&path, "speed = 2\n").unwrap();
+        let cfg = Config::load_from(Some(&path), args(&["--strict-config"])).unwrap();
+        assert_eq!(cfg.speed, Some(2.0));
+        assert!(cfg.config_error.is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn safe_mode_ignores_file_and_forces_defaults() {
+        let path = std::env::temp_dir().join(format!("swarm-safe-{}.toml", std::process::id()));
+        std::fs::write(&path, "effect = \"swarm\"\nspeed = 2\n").unwrap();
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let cfg = Config::load_from(
+            Some(&path),
+            args(&["--safe-mode", "--render-thread", "--effect", "matrix"]),
+        )
+        .unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(cfg.safe_mode);
+        assert_eq!(cfg.effect, None);
+        assert_eq!(cfg.speed, None);
+        assert!(!cfg.render_thread);
+        assert_eq!(cfg.render_scale, Some(0.5));
+    }
+
+    #[test]
+    fn colors_build_a_palette() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let cfg = Config::load_from(
+            None,
+            args(&["--color-b", "#ff8800", "--color-a", "#102030"]),
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.palette.stops,
+            vec![[0x10, 0x20, 0x30], [0xff, 0x88, 0x00]]
+        );
+
+        let cfg = Config::load_from(
+            None,
+            args(&[
+                "--color-a",
+                "#000000",
+                "--color-b",
+                "#ff0000",
+                "--color-c",
+                "#ffffff",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(cfg.palette.stops.len(), 3);
+
+        // 指定がなければグレースケールのまま
+        assert!(
+            Config::load_from(None, args(&[]))
+                .unwrap()
+                .palette
+                .stops
+                .is_empty()
+        );
+        assert!(Config::load_from(None, args(&["--color-a", "red"])).is_err());
+    }
+
+    #[test]
+    fn present_mode_accepts_the_three_names() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.present_mode, wgpu::PresentMode::Fifo);
+        cfg.set("present_mode", "immediate").unwrap();
+        assert_eq!(cfg.present_mode, wgpu::PresentMode::Immediate);
+        cfg.set("present_mode", "mailbox").unwrap();
+        assert_eq!(cfg.present_mode, wgpu::PresentMode::Mailbox);
+        assert!(cfg.set("present_mode", "vsync").is_err());
+    }
+
+    #[test]
+    fn headless_takes_an_output_directory() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let cfg = Config::load_from(None, args(&["--headless", "--frames", "10", "out/"])).unwrap();
+        assert!(cfg.headless);
+        assert_eq!(cfg.frames, Some(10));
+        assert_eq!(cfg.headless_dir, Some(PathBuf::from("out/")));
+
+        assert!(Config::load_from(None, args(&["out/"])).is_err());
+        assert!(Config::load_from(None, args(&["--headless", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn file_values_are_overridden_by_cli() {
+        let path = std::env::temp_dir().join(format!("swarm-file-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "noise = \"perlin\"\ncolor_a = \"#102030\"\nfps_cap = 30\nscale = 0.5\n\
+             present_mode = \"mailbox\"\nseed = 7\n",
+        )
+        .unwrap();
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let cfg = Config::load_from(Some(&path), args(&["--seed", "8", "--scale=0.75"])).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(cfg.noise, NoiseKind::Perlin);
+        assert_eq!(cfg.palette.stops[0], [0x10, 0x20, 0x30]);
+        assert_eq!(cfg.fps_cap, Some(30.0));
+        assert_eq!(cfg.present_mode, wgpu::PresentMode::Mailbox);
+        assert_eq!(cfg.render_scale, Some(0.75));
+        assert_eq!(cfg.seed, Some(8));
+    }
+
+    #[test]
+    fn workgroup_accepts_only_supported_sizes() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(Config::default().workgroup, None);
+        for n in ["64", "128", "256"] {
+            let cfg = Config::load_from(None, args(&["--workgroup", n])).unwrap();
+            assert_eq!(cfg.workgroup, Some(n.parse().unwrap()));
+        }
+        let err = Config::load_from(None, args(&["--workgroup", "100"])).unwrap_err();
+        assert!(err.contains("64, 128 or 256"), "{err}");
+    }
+
+    #[test]
+    fn audio_pcm_takes_a_path() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let cfg = Config::load_from(None, args(&["--audio-pcm", "/tmp/swarm-audio"])).unwrap();
+        assert_eq!(cfg.audio_pcm, Some(PathBuf::from("/tmp/swarm-audio")));
+        // 以前の --audio は受け付けない
+        assert!(Config::load_from(None, args(&["--audio", "/tmp/swarm-audio"])).is_err());
+    }
+
+    #[test]
+    fn edge_modes_parse() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(Config::default().edge, Edge::Wrap);
+        for (v, edge) in [
+            ("wrap", Edge::Wrap),
+            ("bounce", Edge::Bounce),
+            ("clamp", Edge::Clamp),
+        ] {
+            let cfg = Config::load_from(None, args(&["--edge", v])).unwrap();
+            assert_eq!(cfg.edge, edge);
+        }
+        let err = Config::load_from(None, args(&["--edge", "stick"])).unwrap_err();
+        assert!(err.contains("wrap, bounce or clamp"), "{err}");
+    }
+}
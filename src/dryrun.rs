@@ -0,0 +1,94 @@
+// --dry-run: ウィンドウを開かずに設定・シェーダー・参照ファイルを検証する
+use std::path::Path;
+
+use crate::config::{self, Config};
+use crate::effects;
+use crate::renderer;
+
+// 問題がなければ true
+pub fn run(cfg: &Config) -> bool {
+    let mut problems = 0;
+    let mut checked = 0;
+
+    // 誤りのある設定ファイルは Config::load で無視され、エラーだけが残っている
+    match config::config_path().filter(|p| p.exists()) {
+        Some(p) => match &cfg.config_error {
+            Some(e) => {
+                problems += 1;
+                println!("FAIL  config {}\n{e}", p.display());
+            }
+            None => println!("ok    config {}", p.display()),
+        },
+        None => println!("ok    config (defaults, no file)"),
+    }
+    checked += 1;
+
+    let effects = effects::EFFECTS.iter().map(|e| (e.name, e.source));
+    for (name, src) in effects.chain(renderer::SHADERS.iter().copied()) {
+        checked += 1;
+        if let Err(e) = validate_wgsl(src) {
+            problems += 1;
+            println!("FAIL  shader {name}\n{e}");
+        } else {
+            println!("ok    shader {name}");
+        }
+    }
+
+    if let Some(p) = &cfg.shader {
+        checked += 1;
+        match effects::load_custom(p) {
+            Ok(_) => println!("ok    shader {}", p.display()),
+            Err(e) => {
+                problems += 1;
+                println!("FAIL  shader {}\n{e}", p.display());
+            }
+        }
+    }
+
+    let mut check_file = |what: &str, path: &Path| {
+        checked += 1;
+        if path.exists() {
+            println!("ok    {what} {}", path.display());
+        } else {
+            problems += 1;
+            println!("FAIL  {what} {}: not found", path.display());
+        }
+    };
+    if let Some(p) = &cfg.param_pipe {
+        check_file("param_pipe", p);
+    }
+    if let Some(p) = &cfg.midi_device {
+        check_file("midi.device", p);
+    }
+    if let Some(p) = &cfg.audio_pcm {
+        check_file("audio_pcm", p);
+    }
+    if let Some(p) = &cfg.background {
+        checked += 1;
+        match crate::png::load(p) {
+            Ok((w, h, _)) => println!("ok    background {} ({w}x{h})", p.display()),
+            Err(e) => {
+                problems += 1;
+                println!("FAIL  background {e}");
+            }
+        }
+    }
+
+    if problems == 0 {
+        println!("dry run: {checked} checks passed");
+    } else {
+        println!("dry run: {problems} of {checked} checks failed");
+    }
+    problems == 0
+}
+
+pub(crate) fn validate_wgsl(src: &str) -> Result<naga::Module, String> {
+    let module = naga::front::wgsl::parse_str(src).map_err(|e| e.emit_to_string(src))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    )
+    .validate(&module)
+    .map_err(|e| e.emit_to_string(src))?;
+    Ok(module)
+}
@@ -0,0 +1,259 @@
+// シーンを描くエフェクト。全画面三角形の頂点シェーダーと共通の Params/Frame 定義を前に連結する
+pub struct Effect {
+    pub name: &'static str,
+    pub source: &'static str,
+    // src/shaders 内の本体のファイル名（--watch で読み直す）。群れは2ファイルに分かれるので対象外
+    pub file: Option<&'static str>,
+    // 必要なダウンレベル機能。空ならどのバックエンドでも動く
+    pub needs: wgpu::DownlevelFlags,
+    pub kind: Kind,
+    // 切り替えたときに戻すパラメータ（set_param の名前と値）
+    pub defaults: &'static [(&'static str, f32)],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    // fs_main を全画面三角形で描く
+    Fullscreen,
+    // cs_main で群れを更新し、vs_agent/fs_agent で描く（swarm.rs）
+    Swarm,
+}
+
+// ストレージバッファを使う機能。WebGL2 相当の制限ではバッファ数も 0 になる
+const STORAGE: wgpu::DownlevelFlags = wgpu::DownlevelFlags::VERTEX_STORAGE
+    .union(wgpu::DownlevelFlags::FRAGMENT_STORAGE)
+    .union(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE)
+    .union(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+
+use std::path::Path;
+
+macro_rules! scene_src {
+    ($file:literal) => {
+        concat!(
+            include_str!("shaders/fullscreen.wgsl"),
+            include_str!("shaders/scene.wgsl"),
+            include_str!($file)
+        )
+    };
+}
+
+// 全画面エフェクトの前に付ける部分（シェーダーの再読み込みで使う）
+pub const SCENE_PRELUDE: &str = concat!(
+    include_str!("shaders/fullscreen.wgsl"),
+    include_str!("shaders/scene.wgsl")
+);
+
+// 群れはシミュレーションと描画の2ファイルを続ける
+const SWARM_SRC: &str = concat!(
+    scene_src!("shaders/compute.wgsl"),
+    include_str!("shaders/swarm.wgsl")
+);
+
+pub const EFFECTS: &[Effect] = &[
+    Effect {
+        name: "noise",
+        source: scene_src!("shaders/noise.wgsl"),
+        file: Some("noise.wgsl"),
+        needs: wgpu::DownlevelFlags::empty(),
+        kind: Kind::Fullscreen,
+        defaults: &[],
+    },
+    Effect {
+        name: "starfield",
+        source: scene_src!("shaders/starfield.wgsl"),
+        file: Some("starfield.wgsl"),
+        needs: wgpu::DownlevelFlags::empty(),
+        kind: Kind::Fullscreen,
+        defaults: &[("star_density", 0.5), ("star_speed", 1.0)],
+    },
+    Effect {
+        name: "matrix",
+        source: scene_src!("shaders/matrix.wgsl"),
+        file: Some("matrix.wgsl"),
+        needs: wgpu::DownlevelFlags::empty(),
+        kind: Kind::Fullscreen,
+        defaults: &[("rain_speed", 1.0), ("rain_density", 0.7)],
+    },
+    Effect {
+        name: "gradient",
+        source: scene_src!("shaders/gradient.wgsl"),
+        file: Some("gradient.wgsl"),
+        needs: wgpu::DownlevelFlags::empty(),
+        kind: Kind::Fullscreen,
+        defaults: &[("gradient_angle", 0.0), ("gradient_radial", 0.0)],
+    },
+    Effect {
+        name: "swarm",
+        source: SWARM_SRC,
+        file: None,
+        needs: wgpu::DownlevelFlags::COMPUTE_SHADERS,
+        kind: Kind::Swarm,
+        defaults: &[],
+    },
+];
+
+pub const DEFAULT: &str = "noise";
+
+// --shader で差し替えるエフェクト（noise）
+pub const CUSTOM: usize = 0;
+
+// --shader: 利用者のシェーダーを読んで検証する。ファイルには fs_main などの本体だけを書き、
+// vs_main と Params/Frame の定義は SCENE_PRELUDE を前に付けて与える（reload_shader と同じ形）
+pub fn load_custom(path: &Path) -> Result<String, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let full = format!("{SCENE_PRELUDE}{source}");
+    let note = || {
+        format!(
+            "(line numbers include the {} lines of the built-in prelude)",
+            SCENE_PRELUDE.lines().count()
+        )
+    };
+    let module = crate::dryrun::validate_wgsl(&full).map_err(|e| format!("{e}{}", note()))?;
+    let has_fs = module
+        .entry_points
+        .iter()
+        .any(|e| e.name == "fs_main" && e.stage == naga::ShaderStage::Fragment);
+    if !has_fs {
+        return Err("no `@fragment fn fs_main` found".into());
+    }
+    Ok(source)
+}
+
+pub fn find(name: &str) -> Option<&'static Effect> {
+    EFFECTS.iter().find(|e| e.name == name)
+}
+
+// 番号でエフェクトを引く。ホットキーやパラメータから切り替えるとき用
+pub fn get(index: usize) -> Result<&'static Effect, String> {
+    EFFECTS.get(index).ok_or_else(|| {
+        format!(
+            "effect index {index} is out of range (0..{}: {})",
+            EFFECTS.len(),
+            names()
+        )
+    })
+}
+
+pub fn index_of(effect: &Effect) -> usize {
+    EFFECTS
+        .iter()
+        .position(|e| e.name == effect.name)
+        .unwrap_or(0)
+}
+
+pub fn names() -> String {
+    EFFECTS
+        .iter()
+        .map(|e| e.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Effect {
+    pub fn supported(&self, flags: wgpu::DownlevelFlags, limits: &wgpu::Limits) -> bool {
+        self.unsupported(flags, limits).is_none()
+    }
+
+    // 使えない理由。使えれば None
+    pub fn unsupported(
+        &self,
+        flags: wgpu::DownlevelFlags,
+        limits: &wgpu::Limits,
+    ) -> Option<String> {
+        if !flags.contains(self.needs) {
+            return Some(format!("needs {:?}", self.needs.difference(flags)));
+        }
+        if self.needs.intersects(STORAGE) && limits.max_storage_buffers_per_shader_stage == 0 {
+            return Some("needs storage buffers".into());
+        }
+        if self.kind == Kind::Swarm {
+            return crate::swarm::workgroup_size(limits, None).err();
+        }
+        None
+    }
+}
+
+pub fn available(flags: wgpu::DownlevelFlags, limits: &wgpu::Limits) -> Vec<&'static str> {
+    EFFECTS
+        .iter()
+        .filter(|e| e.supported(flags, limits))
+        .map(|e| e.name)
+        .collect()
+}
+
+// 要求されたエフェクトが使えなければ noise に切り替える
+pub fn select(
+    requested: Option<&str>,
+    flags: wgpu::DownlevelFlags,
+    limits: &wgpu::Limits,
+) -> &'static Effect {
+    let fallback = find(DEFAULT).unwrap();
+    let Some(e) = requested.and_then(find) else {
+        return fallback;
+    };
+    let Some(why) = e.unsupported(flags, limits) else {
+        return e;
+    };
+    eprintln!(
+        "Effect {:?} is not supported by this adapter ({why}); using {:?} instead.",
+        e.name, fallback.name
+    );
+    fallback
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_rejects_out_of_range_indices() {
+        for (i, e) in EFFECTS.iter().enumerate() {
+            assert_eq!(get(i).unwrap().name, e.name);
+            assert_eq!(index_of(e), i);
+        }
+        for i in [EFFECTS.len(), EFFECTS.len() + 1, usize::MAX] {
+            let err = get(i).err().expect("index past the end must fail");
+            assert!(err.contains("out of range"), "{err}");
+        }
+    }
+
+    #[test]
+    fn custom_shader_is_checked_before_use() {
+        assert_eq!(EFFECTS[CUSTOM].name, "noise");
+        assert_eq!(EFFECTS[CUSTOM].kind, Kind::Fullscreen);
+        let path = std::env::temp_dir().join(format!("swarm-shader-{}.wgsl", std::process::id()));
+        let write = |src: &str| std::fs::write(&path, src).unwrap();
+
+        // Params と vs_main は前置き部分にあるので fs_main だけでよい
+        write(
+            "@fragment\nfn fs_main(in: VSOut) -> @location(0) vec4<f32> {\n  \
+             return vec4<f32>(in.uv, sin(frame.time) * 0.5 + 0.5, 1.0) * params.size.x / params.size.x;\n}\n",
+        );
+        assert!(load_custom(&path).is_ok());
+
+        write("@fragment\nfn fs_main() -> @location(0) vec4<f32> { return vec4<f32>(oops); }\n");
+        let err = load_custom(&path).unwrap_err();
+        assert!(err.contains("oops") && err.contains("prelude"), "{err}");
+
+        write("fn helper() -> f32 { return 1.0; }\n");
+        assert!(load_custom(&path).unwrap_err().contains("fs_main"));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_custom(&path).is_err());
+    }
+
+    #[test]
+    fn swarm_needs_a_64_wide_workgroup() {
+        let flags = wgpu::DownlevelFlags::all();
+        let small = wgpu::Limits {
+            max_compute_invocations_per_workgroup: 32,
+            ..wgpu::Limits::default()
+        };
+        let swarm = find("swarm").unwrap();
+        assert!(swarm.supported(flags, &wgpu::Limits::default()));
+        let why = swarm.unsupported(flags, &small).unwrap();
+        assert!(why.contains("32"), "{why}");
+        assert_eq!(select(Some("swarm"), flags, &small).name, DEFAULT);
+        assert_eq!(crate::swarm::workgroup_size(&small, Some(256)), Err(why));
+    }
+}
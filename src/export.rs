@@ -0,0 +1,240 @@
+// 画像の書き出し系（フレームバッファ出力など）で共通のエラー型
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::headless;
+use crate::png::{self, Depth};
+use crate::renderer::Renderer;
+
+#[derive(Debug)]
+pub enum ExportError {
+    // ファイル/FIFO への書き込みや作成の失敗
+    Io { path: PathBuf, source: io::Error },
+    // GPU からの読み戻し（poll / map_async）の失敗
+    Readback { op: &'static str, reason: String },
+    // ヘッドレス書き出し用の GPU が用意できない
+    Device { reason: String },
+    // 書き出しスレッドが止まっている
+    Stopped { path: PathBuf },
+}
+
+impl ExportError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Readback { op, reason } => write!(f, "readback {op} failed: {reason}"),
+            Self::Device { reason } => write!(f, "no GPU for export: {reason}"),
+            Self::Stopped { path } => write!(f, "{}: writer thread stopped", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for ExportError {
+    fn from(e: wgpu::BufferAsyncError) -> Self {
+        Self::Readback {
+            op: "map",
+            reason: e.to_string(),
+        }
+    }
+}
+
+impl From<wgpu::PollError> for ExportError {
+    fn from(e: wgpu::PollError) -> Self {
+        Self::Readback {
+            op: "poll",
+            reason: e.to_string(),
+        }
+    }
+}
+
+// 読み戻したフレーム。pixels は RGBA8 (sRGB) を上の行から詰めたもの
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// 直前のフレームを dir/name に保存する。F12 と S の両方が使う
+pub fn capture_png(
+    r: &mut Renderer,
+    dir: &Path,
+    name: &str,
+    depth: Depth,
+) -> Result<PathBuf, ExportError> {
+    let [w, h] = r.output_size();
+    let data = match depth {
+        Depth::Eight => r.read_rgba8()?,
+        Depth::Sixteen => r.read_rgba16()?,
+    };
+    let path = dir.join(name);
+    png::write(&path, w, h, depth, &data)?;
+    Ok(path)
+}
+
+// F12 の名前。フレーム番号で並ぶ
+pub fn frame_name(r: &Renderer) -> String {
+    format!("swarm-{:06}.png", r.frame)
+}
+
+// 書き出し中に同時に待つ読み戻しの既定数。1 フレーム分のステージングバッファを1つずつ使う
+pub const DEFAULT_IN_FLIGHT: usize = 3;
+
+// S キーの名前。撮った日時（UTC）にする
+pub fn screenshot_name() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    format!("screenshot-{}.png", timestamp(secs))
+}
+
+// UNIX 時刻を YYYYMMDD-HHMMSS に（日付は Howard Hinnant の civil_from_days）
+fn timestamp(secs: u64) -> String {
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + (m <= 2) as i64;
+    format!(
+        "{y:04}{m:02}{d:02}-{:02}{:02}{:02}",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+// --export: フレーム export_start から frames 枚を、窓を開かずに描いて書き出す。
+// 形式は --fb-output と同じ RGBA8（sRGB）で、フレームをヘッダなしで連結する
+pub fn export_range(cfg: &Config) -> Result<(), ExportError> {
+    let Some(path) = &cfg.export else {
+        return Ok(());
+    };
+    let [w, h] = cfg.export_size.unwrap_or(headless::DEFAULT_SIZE);
+    let start = cfg.export_start;
+    let count = cfg.frames.unwrap_or(1);
+
+    let (mut r, view) = headless::renderer(cfg, w, h)?;
+
+    let file = std::fs::File::create(path).map_err(|e| ExportError::io(path, e))?;
+    let mut out = BufWriter::new(file);
+    let in_flight = cfg.export_in_flight.unwrap_or(DEFAULT_IN_FLIGHT);
+    r.seek(&view, start.max(1));
+    for _ in 0..count {
+        r.render_to_view(&view);
+        // 待ちが上限に達していたら、いちばん古いフレームが読めるまで止めて書き出す
+        if r.reads_in_flight() >= in_flight
+            && let Some(px) = r.pop_read()?
+        {
+            out.write_all(&px).map_err(|e| ExportError::io(path, e))?;
+        }
+        r.queue_read_rgba8();
+    }
+    while let Some(px) = r.pop_read()? {
+        out.write_all(&px).map_err(|e| ExportError::io(path, e))?;
+    }
+    out.flush().map_err(|e| ExportError::io(path, e))?;
+    eprintln!(
+        "Exported frames {}..={} ({w}x{h} RGBA8) to {}",
+        r.frame + 1 - count,
+        r.frame,
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn io_error_keeps_path_and_source() {
+        let e = ExportError::io(
+            "/tmp/out.rgba",
+            io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed"),
+        );
+        assert_eq!(e.to_string(), "/tmp/out.rgba: pipe closed");
+        let src = e.source().expect("source");
+        assert_eq!(
+            src.downcast_ref::<io::Error>().unwrap().kind(),
+            io::ErrorKind::BrokenPipe
+        );
+    }
+
+    #[test]
+    fn map_failure_converts_to_readback() {
+        let e: ExportError = wgpu::BufferAsyncError.into();
+        assert!(matches!(e, ExportError::Readback { op: "map", .. }));
+        assert!(e.to_string().starts_with("readback map failed"));
+        assert!(e.source().is_none());
+    }
+
+    #[test]
+    fn poll_failure_converts_to_readback() {
+        let e: ExportError = wgpu::PollError::Timeout.into();
+        assert!(matches!(e, ExportError::Readback { op: "poll", .. }));
+    }
+
+    #[test]
+    fn timestamp_formats_utc_date_and_time() {
+        assert_eq!(timestamp(0), "19700101-000000");
+        assert_eq!(timestamp(951_782_400), "20000229-000000");
+        assert_eq!(timestamp(1_792_067_696), "20261015-123456");
+    }
+
+    #[test]
+    fn capture_png_writes_the_named_file() {
+        let Ok((mut r, view)) = headless::renderer(&Config::default(), 16, 8) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        r.render_to_view(&view);
+        let dir = std::env::temp_dir().join(format!("swarm-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let name = screenshot_name();
+        assert!(
+            name.starts_with("screenshot-") && name.ends_with(".png"),
+            "{name}"
+        );
+        let path = capture_png(&mut r, &dir, &name, Depth::Eight).unwrap();
+        assert_eq!(path, dir.join(&name));
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[1..4], b"PNG");
+        // IHDR の幅と高さ
+        assert_eq!(bytes[16..24], [0, 0, 0, 16, 0, 0, 0, 8]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stopped_names_the_path() {
+        let e = ExportError::Stopped {
+            path: "/tmp/fifo".into(),
+        };
+        assert!(e.to_string().contains("/tmp/fifo"));
+    }
+}
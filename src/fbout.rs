@@ -0,0 +1,106 @@
+// 仮想フレームバッファ出力。描画結果を一定間隔で生の RGBA としてファイルか FIFO に書き出す
+//
+// 形式: ヘッダなし、RGBA8（sRGB）、左上原点、行ストライド = 幅 × 4 バイト。
+// 通常ファイルには毎回最新の1フレームを丸ごと書き直し、FIFO にはフレームを連続して流す。
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::export::ExportError;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+pub struct FbOutput {
+    path: PathBuf,
+    tx: SyncSender<Vec<u8>>,
+    // 書き出しスレッドで起きたエラー。send のたびに拾って返す
+    errors: Receiver<ExportError>,
+    interval: Duration,
+    last: Option<Instant>,
+    size: [u32; 2],
+}
+
+impl FbOutput {
+    pub fn spawn(path: PathBuf, rate: f64) -> Self {
+        // 書き込みが詰まっている間のフレームは捨てる（描画を止めない）
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(1);
+        let (err_tx, errors) = mpsc::channel();
+        let thread_path = path.clone();
+        let _ = std::thread::Builder::new()
+            .name("fb-output".into())
+            .spawn(move || {
+                let path = thread_path;
+                let fifo = is_fifo(&path);
+                let mut stream: Option<File> = None;
+                for frame in rx {
+                    let r = if fifo {
+                        // 読み手がいなくなったら次のフレームで開き直す
+                        let f = match stream.as_mut() {
+                            Some(f) => f,
+                            None => match File::create(&path) {
+                                Ok(f) => stream.insert(f),
+                                Err(e) => {
+                                    let _ = err_tx.send(ExportError::io(&path, e));
+                                    continue;
+                                }
+                            },
+                        };
+                        f.write_all(&frame)
+                    } else {
+                        std::fs::write(&path, &frame)
+                    };
+                    if let Err(e) = r {
+                        let _ = err_tx.send(ExportError::io(&path, e));
+                        stream = None;
+                    }
+                }
+            });
+        Self {
+            path,
+            tx,
+            errors,
+            interval: Duration::from_secs_f64(1.0 / rate.max(0.001)),
+            last: None,
+            size: [0, 0],
+        }
+    }
+
+    // 前回の書き出しから間隔が空いていれば true
+    pub fn due(&self, now: Instant) -> bool {
+        self.last.is_none_or(|t| now - t >= self.interval)
+    }
+
+    pub fn send(
+        &mut self,
+        now: Instant,
+        w: u32,
+        h: u32,
+        pixels: Vec<u8>,
+    ) -> Result<(), ExportError> {
+        self.last = Some(now);
+        if self.size != [w, h] {
+            self.size = [w, h];
+            eprintln!("fb output: {w}x{h} RGBA8, stride {} bytes", w * 4);
+        }
+        if let Ok(e) = self.errors.try_recv() {
+            return Err(e);
+        }
+        match self.tx.try_send(pixels) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Disconnected(_)) => Err(ExportError::Stopped {
+                path: self.path.clone(),
+            }),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &std::path::Path) -> bool {
+    false
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+// フレーム数/経過時間をウィンドウ単位で平均する
+pub struct FpsMeter {
+    window: Duration,
+    frames: u32,
+    elapsed: Duration,
+    last_frames: u32,
+    current: Option<f64>,
+}
+
+impl FpsMeter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            frames: 0,
+            elapsed: Duration::ZERO,
+            last_frames: 0,
+            current: None,
+        }
+    }
+
+    // 1フレーム分の経過時間を加算する。ウィンドウが埋まって値が更新されたら true
+    pub fn tick(&mut self, dt: Duration) -> bool {
+        self.frames += 1;
+        self.elapsed += dt;
+        if self.elapsed < self.window || self.elapsed.is_zero() {
+            return false;
+        }
+        self.current = Some(self.frames as f64 / self.elapsed.as_secs_f64());
+        self.last_frames = self.frames;
+        self.frames = 0;
+        self.elapsed = Duration::ZERO;
+        true
+    }
+
+    pub fn current(&self) -> Option<f64> {
+        self.current
+    }
+
+    // 直近に確定したウィンドウのフレーム数
+    pub fn window_frames(&self) -> u32 {
+        self.last_frames
+    }
+
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.elapsed = Duration::ZERO;
+    }
+}
+
+impl Default for FpsMeter {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(v: u64) -> Duration {
+        Duration::from_millis(v)
+    }
+
+    #[test]
+    fn no_value_before_window_fills() {
+        let mut m = FpsMeter::default();
+        for _ in 0..59 {
+            assert!(!m.tick(ms(16)));
+        }
+        assert_eq!(m.current(), None);
+    }
+
+    #[test]
+    fn steady_rate() {
+        let mut m = FpsMeter::default();
+        let mut updated = false;
+        for _ in 0..100 {
+            updated |= m.tick(ms(10));
+        }
+        assert!(updated);
+        assert!((m.current().unwrap() - 100.0).abs() < 1e-9);
+        assert_eq!(m.window_frames(), 100);
+    }
+
+    #[test]
+    fn uneven_frames_average_over_window() {
+        let mut m = FpsMeter::default();
+        // 10ms × 50 + 50ms × 10 = 1.0s、60フレーム
+        for _ in 0..50 {
+            m.tick(ms(10));
+        }
+        for _ in 0..9 {
+            assert!(!m.tick(ms(50)));
+        }
+        assert!(m.tick(ms(50)));
+        assert!((m.current().unwrap() - 60.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_dt_counts_frames_without_dividing_by_zero() {
+        let mut m = FpsMeter::default();
+        for _ in 0..10 {
+            assert!(!m.tick(Duration::ZERO));
+        }
+        assert!(m.tick(ms(1000)));
+        assert!((m.current().unwrap() - 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn large_dt_closes_window_immediately() {
+        let mut m = FpsMeter::default();
+        assert!(m.tick(Duration::from_secs(4)));
+        assert!((m.current().unwrap() - 0.25).abs() < 1e-9);
+        // 次のウィンドウは前の値を引きずらない
+        for _ in 0..30 {
+            m.tick(ms(33));
+        }
+        assert!(m.tick(ms(10)));
+        assert!((m.current().unwrap() - 31.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reset_discards_partial_window() {
+        let mut m = FpsMeter::default();
+        for _ in 0..50 {
+            m.tick(ms(10));
+        }
+        m.reset();
+        for _ in 0..20 {
+            m.tick(ms(50));
+        }
+        assert!((m.current().unwrap() - 20.0).abs() < 1e-9);
+    }
+}
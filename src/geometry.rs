@@ -0,0 +1,78 @@
+// 前回終了時のウィンドウ位置とサイズ（物理ピクセル）。設定ディレクトリに "x y w h" で保存
+use std::path::PathBuf;
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::monitor::MonitorHandle;
+use winit::window::Window;
+
+use crate::config;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Geometry {
+    pub pos: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+}
+
+fn path() -> Option<PathBuf> {
+    config::config_dir().map(|d| d.join("geometry"))
+}
+
+impl Geometry {
+    pub fn of(window: &Window) -> Option<Self> {
+        Some(Self {
+            pos: window.outer_position().ok()?,
+            size: window.inner_size(),
+        })
+    }
+
+    pub fn load() -> Option<Self> {
+        let text = std::fs::read_to_string(path()?).ok()?;
+        let v: Vec<i64> = text
+            .split_whitespace()
+            .filter_map(|t| t.parse().ok())
+            .collect();
+        let [x, y, w, h] = v.as_slice() else {
+            return None;
+        };
+        Some(Self {
+            pos: PhysicalPosition::new(*x as i32, *y as i32),
+            size: PhysicalSize::new((*w).max(1) as u32, (*h).max(1) as u32),
+        })
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(p) = path() else {
+            return Ok(());
+        };
+        if let Some(dir) = p.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(
+            p,
+            format!(
+                "{} {} {} {}\n",
+                self.pos.x, self.pos.y, self.size.width, self.size.height
+            ),
+        )
+    }
+
+    pub fn clear() {
+        if let Some(p) = path() {
+            let _ = std::fs::remove_file(p);
+        }
+    }
+
+    // モニター構成が変わって画面外に出てしまう位置は復元しない
+    pub fn visible_on(&self, monitors: impl IntoIterator<Item = MonitorHandle>) -> bool {
+        let (x0, y0) = (self.pos.x as i64, self.pos.y as i64);
+        let (x1, y1) = (x0 + self.size.width as i64, y0 + self.size.height as i64);
+        monitors.into_iter().any(|m| {
+            let p = m.position();
+            let s = m.size();
+            let (mx0, my0) = (p.x as i64, p.y as i64);
+            let (mx1, my1) = (mx0 + s.width as i64, my0 + s.height as i64);
+            // タイトルバーをつかめる程度（64px）は重なっていること
+            x0.max(mx0) + 64 <= x1.min(mx1) && y0.max(my0) + 64 <= y1.min(my1)
+        })
+    }
+}
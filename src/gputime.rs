@@ -0,0 +1,176 @@
+// フレームの描画に GPU がかけた時間を timestamp クエリで測る。
+// TIMESTAMP_QUERY のないアダプタでは作らない（State 側は None のまま）
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct GpuTimer {
+    queries: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    // 1 目盛りのナノ秒数
+    period_ns: f64,
+    // staging を読み出し中。終わるまで次の計測は始めない
+    pending: bool,
+    mapped: Arc<AtomicBool>,
+    last_ms: Option<f64>,
+}
+
+const SIZE: u64 = 2 * size_of::<u64>() as u64;
+
+impl GpuTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let queries = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu_time"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_time_resolve"),
+            size: SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_time_staging"),
+            size: SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            queries,
+            resolve,
+            staging,
+            period_ns: queue.get_timestamp_period() as f64,
+            pending: false,
+            mapped: Arc::new(AtomicBool::new(false)),
+            last_ms: None,
+        })
+    }
+
+    // フレームを描く前に呼ぶ。前の読み出しが終わっていなければ今回は測らない
+    pub fn ready(&mut self, device: &wgpu::Device) -> bool {
+        self.collect(device);
+        !self.pending
+    }
+
+    // 最初のパスの開始に 0、最終段の終わりに 1 を書く。両方とも同じパスでもよい
+    pub fn render_writes(&self, begin: bool, end: bool) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.queries,
+            beginning_of_pass_write_index: begin.then_some(0),
+            end_of_pass_write_index: end.then_some(1),
+        }
+    }
+
+    // 最初のパスがコンピュートパスのとき
+    pub fn compute_begin(&self) -> wgpu::ComputePassTimestampWrites<'_> {
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.queries,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: None,
+        }
+    }
+
+    // 描画と同じエンコーダーの最後で結果を読み出し用にコピーする
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.queries, 0..2, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.staging, 0, SIZE);
+    }
+
+    // submit した後に呼ぶ。結果は後のフレームの ready で受け取る
+    pub fn finish(&mut self) {
+        let mapped = self.mapped.clone();
+        self.staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |r| {
+                mapped.store(r.is_ok(), Ordering::Release)
+            });
+        self.pending = true;
+    }
+
+    // 最後に測れたフレームの GPU 時間（ミリ秒）
+    pub fn last_ms(&self) -> Option<f64> {
+        self.last_ms
+    }
+
+    // 読み出しが終わっていれば結果を取り込む。待たない
+    fn collect(&mut self, device: &wgpu::Device) {
+        if !self.pending {
+            return;
+        }
+        let _ = device.poll(wgpu::PollType::Poll);
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+        {
+            let data = self.staging.slice(..).get_mapped_range();
+            let t: [u64; 2] = bytemuck::pod_read_unaligned(&data);
+            // 巻き戻った値は捨てる（一部のドライバはパスの境界で不正確になる）
+            self.last_ms = t[1]
+                .checked_sub(t[0])
+                .map(|d| d as f64 * self.period_ns / 1e6);
+        }
+        self.staging.unmap();
+        self.pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::request_device;
+
+    #[test]
+    fn measures_a_frame_when_supported() {
+        let instance = wgpu::Instance::default();
+        let Ok(adapter) = pollster::block_on(instance.request_adapter(&Default::default())) else {
+            return;
+        };
+        let (device, queue) = pollster::block_on(request_device(&adapter)).unwrap();
+        let Some(mut timer) = GpuTimer::new(&device, &queue) else {
+            eprintln!("no TIMESTAMP_QUERY; skipping");
+            return;
+        };
+        assert_eq!(timer.last_ms(), None);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        assert!(timer.ready(&device));
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: Default::default(),
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: Some(timer.render_writes(true, true)),
+            occlusion_query_set: None,
+        });
+        timer.resolve(&mut encoder);
+        queue.submit(Some(encoder.finish()));
+        timer.finish();
+        device.poll(wgpu::PollType::Wait).unwrap();
+        // 次の ready で受け取る
+        assert!(timer.ready(&device));
+        let ms = timer.last_ms().expect("timestamps were resolved");
+        assert!((0.0..1000.0).contains(&ms), "{ms} ms");
+    }
+}
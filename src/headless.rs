@@ -0,0 +1,151 @@
+// 窓を開かずに描く。--export と --headless が使う。サーフェスを作らずにデバイスを用意し、
+// 窓と同じ Renderer で画面外のテクスチャに描く
+use crate::config::Config;
+use crate::effects;
+use crate::export::{DEFAULT_IN_FLIGHT, ExportError};
+use crate::png::{self, Depth};
+use crate::renderer::{self, Renderer};
+use crate::swarm;
+
+// 書き出しの既定解像度
+pub const DEFAULT_SIZE: [u32; 2] = [1920, 1080];
+
+// w x h の Renderer と、その描画先。読み戻しは read_rgba8 などが別に描くので描画先の中身は使わない
+pub fn renderer(
+    cfg: &Config,
+    w: u32,
+    h: u32,
+) -> Result<(Renderer, wgpu::TextureView), ExportError> {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(renderer::find_adapter(
+        &instance,
+        cfg.adapter.as_deref(),
+        None,
+    ))
+    .map_err(|e| ExportError::Device {
+        reason: e.to_string(),
+    })?;
+    let (device, queue) = pollster::block_on(renderer::request_device(&adapter)).map_err(|e| {
+        ExportError::Device {
+            reason: e.to_string(),
+        }
+    })?;
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let view = device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("headless_target"),
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&Default::default());
+    let limits = device.limits();
+    let effect = effects::select(
+        cfg.effect.as_deref(),
+        adapter.get_downlevel_capabilities().flags,
+        &limits,
+    );
+    let cfg = &Config {
+        effect: Some(effect.name.to_string()),
+        msaa: renderer::supported_msaa(&adapter, &device, cfg.msaa),
+        workgroup: swarm::workgroup_size(&limits, cfg.workgroup).ok(),
+        ..cfg.clone()
+    };
+    let mut r = Renderer::new(device, queue, format, w, h, cfg);
+    if let Some(path) = &cfg.shader {
+        let custom = &effects::EFFECTS[effects::CUSTOM];
+        match effects::load_custom(path).and_then(|src| r.reload_shader(custom, &src)) {
+            Ok(()) => (),
+            Err(e) => eprintln!(
+                "Shader {} not used; keeping the built-in one:\n{e}",
+                path.display()
+            ),
+        }
+    }
+    Ok((r, view))
+}
+
+// --headless: フレーム export_start から frames 枚を <dir>/swarm-<frame>.png に書き出す
+pub fn render_headless(cfg: &Config) -> Result<(), ExportError> {
+    let [w, h] = cfg.export_size.unwrap_or(DEFAULT_SIZE);
+    let frames = cfg.frames.unwrap_or(1);
+    let out_dir = cfg.headless_dir.as_deref().unwrap_or(".".as_ref());
+    std::fs::create_dir_all(out_dir).map_err(|e| ExportError::io(out_dir, e))?;
+    let (mut r, view) = renderer(cfg, w, h)?;
+    let in_flight = cfg.export_in_flight.unwrap_or(DEFAULT_IN_FLIGHT);
+    let first = cfg.export_start.max(1);
+    r.seek(&view, first);
+    // 読み戻しは描いた順に返るので、番号は書いた枚数から決まる
+    let mut frame = first;
+    let mut write = |px: Vec<u8>| {
+        let path = out_dir.join(format!("swarm-{frame:06}.png"));
+        frame += 1;
+        png::write(&path, w, h, Depth::Eight, &px)
+    };
+    for _ in 0..frames {
+        r.render_to_view(&view);
+        if r.reads_in_flight() >= in_flight
+            && let Some(px) = r.pop_read()?
+        {
+            write(px)?;
+        }
+        r.queue_read_rgba8();
+    }
+    while let Some(px) = r.pop_read()? {
+        write(px)?;
+    }
+    eprintln!(
+        "Rendered frames {first}..={} ({w}x{h}) to {}",
+        (first + frames).saturating_sub(1),
+        out_dir.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_png_per_frame() {
+        let dir = std::env::temp_dir().join(format!("swarm-headless-{}", std::process::id()));
+        let cfg = Config {
+            effect: Some("noise".into()),
+            headless: true,
+            headless_dir: Some(dir.clone()),
+            frames: Some(4),
+            export_size: Some([32, 16]),
+            export_in_flight: Some(2),
+            ..Config::default()
+        };
+        match render_headless(&cfg) {
+            Ok(()) => (),
+            // GPU のない環境では飛ばす
+            Err(ExportError::Device { .. }) => return,
+            Err(e) => panic!("{e}"),
+        }
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(
+            names,
+            [
+                "swarm-000001.png",
+                "swarm-000002.png",
+                "swarm-000003.png",
+                "swarm-000004.png"
+            ]
+        );
+    }
+}
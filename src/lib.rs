@@ -0,0 +1,31 @@
+// 描画部分をほかの wgpu / winit アプリに組み込むためのライブラリ。
+// Renderer は渡された Device・Queue・出力形式で任意の TextureView に描き、サーフェスもウィンドウも持たない。
+// ウィンドウごと任せるなら State、単体アプリのイベントループは App（main.rs が使う）
+pub mod app;
+#[cfg(feature = "audio")]
+mod audio;
+pub mod config;
+pub mod dryrun;
+pub mod effects;
+pub mod export;
+mod fbout;
+mod fps;
+mod geometry;
+mod gputime;
+pub mod headless;
+mod metrics;
+#[cfg(feature = "midi")]
+mod midi;
+#[cfg(feature = "osc")]
+mod osc;
+pub mod palette;
+mod platform;
+pub mod png;
+mod power;
+pub mod presets;
+mod remote;
+mod render_thread;
+pub mod renderer;
+pub mod state;
+pub mod swarm;
+mod watch;
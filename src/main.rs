@@ -1,13 +1,51 @@
-mod app;
-mod state;
-
+use swarm_wallpaper::{app, config, dryrun, export, headless, renderer};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 fn main() {
-    let event_loop = EventLoop::new().unwrap();
+    let config = match config::Config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    if config.list_adapters {
+        for (i, a) in renderer::list_adapters().iter().enumerate() {
+            println!("{i}: {a}");
+        }
+        return;
+    }
+
+    if config.dry_run {
+        std::process::exit(if dryrun::run(&config) { 0 } else { 1 });
+    }
+
+    if config.export.is_some() {
+        if let Err(e) = export::export_range(&config) {
+            eprintln!("export failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if config.headless {
+        if let Err(e) = headless::render_headless(&config) {
+            eprintln!("headless rendering failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let event_loop = EventLoop::<app::UserEvent>::with_user_event()
+        .build()
+        .unwrap();
 
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut app = app::App::default();
+    let mut app = app::App::new(config, event_loop.create_proxy());
     let _ = event_loop.run_app(&mut app);
+    if app.failed {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,109 @@
+// --log-metrics: フレーム間隔を CSV に追記する。タイトルバーのない壁紙モードでも FPS を記録できる。
+// 行は `timestamp,frame_ms,fps`（timestamp は UNIX 時刻の秒、fps はそのフレームの間隔から）
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const HEADER: &str = "timestamp,frame_ms,fps";
+
+// 毎フレーム書き込むと描画が I/O で詰まるので、この間隔でまとめて書く
+const FLUSH_EVERY: Duration = Duration::from_secs(2);
+
+pub struct FrameLog {
+    path: PathBuf,
+    out: BufWriter<File>,
+    flushed: Instant,
+}
+
+impl FrameLog {
+    // 既存のファイルには追記する。空のときだけ見出し行を書く
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut out = BufWriter::new(file);
+        if empty {
+            writeln!(out, "{HEADER}")?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            out,
+            flushed: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // 1フレーム分。間隔が 0（最初のフレーム）なら書かない
+    pub fn record(&mut self, now: Instant, dt: Duration) -> io::Result<()> {
+        if dt.is_zero() {
+            return Ok(());
+        }
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        writeln!(self.out, "{}", row(ts, dt))?;
+        if now.duration_since(self.flushed) >= FLUSH_EVERY {
+            self.out.flush()?;
+            self.flushed = now;
+        }
+        Ok(())
+    }
+}
+
+// 書けなくなったらログを止めて描画は続ける
+pub fn record(log: &mut Option<FrameLog>, now: Instant, dt: Duration) {
+    if let Some(l) = log.as_mut()
+        && let Err(e) = l.record(now, dt)
+    {
+        eprintln!("metrics log {}: {e}; no longer logging", l.path().display());
+        *log = None;
+    }
+}
+
+// --log-metrics の開始。開けなければ知らせて記録なしで続ける
+pub fn open(path: Option<&Path>) -> Option<FrameLog> {
+    let path = path?;
+    FrameLog::open(path)
+        .map_err(|e| eprintln!("metrics log {}: {e}", path.display()))
+        .ok()
+}
+
+impl Drop for FrameLog {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+fn row(ts: f64, dt: Duration) -> String {
+    let secs = dt.as_secs_f64();
+    format!("{ts:.3},{:.3},{:.1}", secs * 1000.0, 1.0 / secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_rows_after_a_single_header() {
+        let path = std::env::temp_dir().join(format!("swarm-metrics-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let now = Instant::now();
+        for _ in 0..2 {
+            let mut log = FrameLog::open(&path).unwrap();
+            log.record(now, Duration::ZERO).unwrap();
+            log.record(now, Duration::from_millis(20)).unwrap();
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], HEADER);
+        for l in &lines[1..] {
+            assert!(l.ends_with(",20.000,50.0"), "{l}");
+        }
+    }
+}
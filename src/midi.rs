@@ -0,0 +1,121 @@
+// MIDI CC 入力。生の MIDI バイト列を出すデバイス
+// （ALSA rawmidi の /dev/snd/midiC1D0、OSS の /dev/midi1 など）を読む。
+//
+// 設定例:
+//   [midi]
+//   device = "/dev/snd/midiC1D0"
+//   cc7 = "time_scale.x 0 10"   # CC7 の 0..127 を 0.0..10.0 に割り当て
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::MidiMapping;
+use crate::remote::ParamInbox;
+
+pub fn spawn(device: PathBuf, map: Vec<MidiMapping>, inbox: Arc<ParamInbox>) {
+    let mut file = match std::fs::File::open(&device) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("MIDI disabled: {}: {e}", device.display());
+            return;
+        }
+    };
+    let _ = std::thread::Builder::new()
+        .name("midi".into())
+        .spawn(move || {
+            let mut parser = Parser::default();
+            let mut buf = [0u8; 256];
+            loop {
+                let n = match file.read(&mut buf) {
+                    Ok(0) => return,
+                    Ok(n) => n,
+                    Err(e) => {
+                        eprintln!("MIDI read failed: {e}");
+                        return;
+                    }
+                };
+                for &b in &buf[..n] {
+                    let Some((cc, value)) = parser.feed(b) else {
+                        continue;
+                    };
+                    for m in map.iter().filter(|m| m.cc == cc) {
+                        let t = value as f32 / 127.0;
+                        inbox.push(&m.name, m.min + (m.max - m.min) * t);
+                    }
+                }
+            }
+        });
+}
+
+// ランニングステータス対応の最小パーサ。Control Change だけを返す
+#[derive(Default)]
+struct Parser {
+    status: u8,
+    data: Vec<u8>,
+}
+
+impl Parser {
+    fn feed(&mut self, b: u8) -> Option<(u8, u8)> {
+        if b >= 0xF8 {
+            // リアルタイムメッセージは途中に割り込んでも無視
+            return None;
+        }
+        if b & 0x80 != 0 {
+            self.status = b;
+            self.data.clear();
+            return None;
+        }
+        self.data.push(b);
+        let len = match self.status & 0xF0 {
+            0xC0 | 0xD0 => 1,
+            0x80..=0xE0 => 2,
+            _ => {
+                self.data.clear();
+                return None;
+            }
+        };
+        if self.data.len() < len {
+            return None;
+        }
+        let out = (self.status & 0xF0 == 0xB0).then(|| (self.data[0], self.data[1]));
+        self.data.clear();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(bytes: &[u8]) -> Vec<(u8, u8)> {
+        let mut p = Parser::default();
+        bytes.iter().filter_map(|&b| p.feed(b)).collect()
+    }
+
+    #[test]
+    fn running_status_repeats_the_last_cc() {
+        // 2つ目の CC はステータスバイトを省略している
+        assert_eq!(feed_all(&[0xB0, 7, 100, 10, 64]), [(7, 100), (10, 64)]);
+    }
+
+    #[test]
+    fn realtime_bytes_do_not_break_a_message() {
+        assert_eq!(feed_all(&[0xB3, 0xF8, 1, 0xF8, 127, 0xF8]), [(1, 127)]);
+    }
+
+    #[test]
+    fn program_change_is_not_a_cc() {
+        // データ1バイトのメッセージ。続く CC の読み取りもずれない
+        assert_eq!(feed_all(&[0xC0, 5, 6, 0xB0, 7, 1]), [(7, 1)]);
+        // ノートオン (2バイト) も CC ではない
+        assert!(feed_all(&[0x90, 60, 100]).is_empty());
+    }
+
+    #[test]
+    fn sysex_resets_the_state() {
+        // CC の途中で SysEx が始まったら、中身も続くデータバイトも CC にしない
+        assert!(feed_all(&[0xB0, 7, 0xF0, 0x7E, 1, 2, 0xF7, 3, 4]).is_empty());
+        // 新しいステータスが来れば元に戻る
+        assert_eq!(feed_all(&[0xF0, 1, 2, 0xF7, 0xB1, 7, 9]), [(7, 9)]);
+    }
+}
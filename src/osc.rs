@@ -0,0 +1,183 @@
+// OSC (Open Sound Control) over UDP。
+//
+// アドレス空間: /swarm/<name>  （<name> は State::set_param の名前。
+// "/" は "." と同じ扱いなので /swarm/time_scale/x も可）
+// 引数: 最初の1つを使う。f (float32), i (int32), d (float64), T/F (真偽) に対応。
+// #bundle は中身を順に処理する。
+use std::net::UdpSocket;
+use std::sync::Arc;
+
+use crate::remote::ParamInbox;
+
+const PREFIX: &str = "/swarm/";
+
+pub fn spawn(port: u16, inbox: Arc<ParamInbox>) {
+    // ポートが使用中なら OSC なしで続行
+    let sock = match UdpSocket::bind(("0.0.0.0", port)) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("OSC disabled: cannot bind UDP port {port}: {e}");
+            return;
+        }
+    };
+    eprintln!("OSC listening on udp/{port}");
+    let _ = std::thread::Builder::new()
+        .name("osc".into())
+        .spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = match sock.recv(&mut buf) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        eprintln!("OSC receive failed: {e}");
+                        return;
+                    }
+                };
+                let mut out = Vec::new();
+                parse_packet(&buf[..n], &mut out);
+                for (addr, v) in out {
+                    match addr.strip_prefix(PREFIX) {
+                        Some(name) => inbox.push(&name.replace('/', "."), v),
+                        None => eprintln!("OSC: ignoring address {addr}"),
+                    }
+                }
+            }
+        });
+}
+
+fn parse_packet(data: &[u8], out: &mut Vec<(String, f32)>) {
+    if data.starts_with(b"#bundle\0") {
+        // #bundle, timetag(8), [size(4), element]*
+        let mut rest = data.get(16..).unwrap_or(&[]);
+        while rest.len() >= 4 {
+            let size = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let Some(elem) = rest.get(4..4 + size) else {
+                return;
+            };
+            parse_packet(elem, out);
+            rest = &rest[4 + size..];
+        }
+    } else if let Some(m) = parse_message(data) {
+        out.push(m);
+    }
+}
+
+fn parse_message(data: &[u8]) -> Option<(String, f32)> {
+    let (addr, rest) = read_str(data)?;
+    let (tags, args) = read_str(rest)?;
+    let tag = tags.strip_prefix(',')?.chars().next()?;
+    let v = match tag {
+        'f' => f32::from_be_bytes(args.get(..4)?.try_into().ok()?),
+        'i' => i32::from_be_bytes(args.get(..4)?.try_into().ok()?) as f32,
+        'd' => f64::from_be_bytes(args.get(..8)?.try_into().ok()?) as f32,
+        'T' => 1.0,
+        'F' => 0.0,
+        _ => return None,
+    };
+    Some((addr.to_string(), v))
+}
+
+// NUL 終端、4バイト境界までパディングされた文字列
+fn read_str(data: &[u8]) -> Option<(&str, &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    let s = std::str::from_utf8(&data[..end]).ok()?;
+    let next = (end + 4) & !3;
+    Some((s, data.get(next..).unwrap_or(&[])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NUL 終端して4バイト境界まで埋める
+    fn pad(s: &str) -> Vec<u8> {
+        let mut v = s.as_bytes().to_vec();
+        v.push(0);
+        while !v.len().is_multiple_of(4) {
+            v.push(0);
+        }
+        v
+    }
+
+    fn message(addr: &str, tags: &str, args: &[u8]) -> Vec<u8> {
+        [pad(addr), pad(tags), args.to_vec()].concat()
+    }
+
+    fn parse(data: &[u8]) -> Vec<(String, f32)> {
+        let mut out = Vec::new();
+        parse_packet(data, &mut out);
+        out
+    }
+
+    #[test]
+    fn each_argument_type_is_read() {
+        let cases = [
+            (",f", 2.5f32.to_be_bytes().to_vec(), 2.5),
+            (",i", (-3i32).to_be_bytes().to_vec(), -3.0),
+            (",d", 0.25f64.to_be_bytes().to_vec(), 0.25),
+            (",T", vec![], 1.0),
+            (",F", vec![], 0.0),
+        ];
+        for (tags, args, want) in cases {
+            let got = parse(&message("/swarm/speed", tags, &args));
+            assert_eq!(got, [("/swarm/speed".to_string(), want)], "{tags}");
+        }
+        // 知らない型は無視する
+        assert!(parse(&message("/swarm/speed", ",s", &pad("x"))).is_empty());
+    }
+
+    #[test]
+    fn strings_are_padded_to_four_bytes() {
+        // 長さ 4 の倍数ちょうどの文字列も NUL のために4バイト足される
+        assert_eq!(pad("/abc").len(), 8);
+        let data = message("/abc", ",f", &1.0f32.to_be_bytes());
+        let (addr, rest) = read_str(&data).unwrap();
+        assert_eq!(addr, "/abc");
+        assert_eq!(rest.len(), data.len() - 8);
+        for addr in ["/a", "/ab", "/abc", "/abcd", "/swarm/time_scale/x"] {
+            let got = parse(&message(addr, ",f", &1.0f32.to_be_bytes()));
+            assert_eq!(got, [(addr.to_string(), 1.0)]);
+        }
+    }
+
+    #[test]
+    fn nested_bundles_are_unpacked_in_order() {
+        let element = |data: Vec<u8>| [(data.len() as u32).to_be_bytes().to_vec(), data].concat();
+        let bundle = |elements: Vec<Vec<u8>>| {
+            let mut v = pad("#bundle");
+            v.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+            for e in elements {
+                v.extend(element(e));
+            }
+            v
+        };
+        let inner = bundle(vec![message("/swarm/b", ",i", &2i32.to_be_bytes())]);
+        let outer = bundle(vec![
+            message("/swarm/a", ",f", &1.0f32.to_be_bytes()),
+            inner,
+            message("/swarm/c", ",T", &[]),
+        ]);
+        let got = parse(&outer);
+        let names: Vec<_> = got.iter().map(|(a, v)| (a.as_str(), *v)).collect();
+        assert_eq!(
+            names,
+            [("/swarm/a", 1.0), ("/swarm/b", 2.0), ("/swarm/c", 1.0)]
+        );
+    }
+
+    #[test]
+    fn truncated_packets_are_ignored() {
+        let full = message("/swarm/speed", ",d", &0.5f64.to_be_bytes());
+        // どこで切れてもパニックせず、値を読めなければ何も返さない
+        for n in 0..full.len() {
+            assert!(parse(&full[..n]).is_empty(), "cut at {n}");
+        }
+        // 中身より大きいサイズを名乗る bundle の要素
+        let mut bundle = pad("#bundle");
+        bundle.extend_from_slice(&[0; 8]);
+        bundle.extend_from_slice(&u32::MAX.to_be_bytes());
+        bundle.extend_from_slice(&full);
+        assert!(parse(&bundle).is_empty());
+        assert!(parse(&bundle[..18]).is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+// グレースケールのシーンを色に写すパレット。色は sRGB の 8bit で持ち、GPU へは線形で渡す
+use std::path::Path;
+
+pub const MAX_STOPS: usize = 8;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Palette {
+    pub stops: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    // "#000000, #ff8800, #ffffff" 形式
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let stops = s
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(parse_hex)
+            .collect::<Result<Vec<_>, _>>()?;
+        if stops.len() > MAX_STOPS {
+            return Err(format!("at most {MAX_STOPS} palette stops are supported"));
+        }
+        Ok(Self { stops })
+    }
+
+    // GIMP (.gpl) か Paint.NET (.txt) のパレットファイル。多すぎる色は先頭から MAX_STOPS 個
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut stops = if text.trim_start().starts_with("GIMP Palette") {
+            parse_gpl(&text)
+        } else {
+            parse_paint_net(&text)
+        }
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+        if stops.len() > MAX_STOPS {
+            eprintln!(
+                "{}: {} colors, using the first {MAX_STOPS}",
+                path.display(),
+                stops.len()
+            );
+            stops.truncate(MAX_STOPS);
+        }
+        Ok(Self { stops })
+    }
+
+    // uniform 用。未使用の要素は 0
+    pub fn linear(&self) -> [[f32; 4]; MAX_STOPS] {
+        let mut out = [[0.0; 4]; MAX_STOPS];
+        for (o, c) in out.iter_mut().zip(&self.stops) {
+            *o = linear_rgba(*c);
+        }
+        out
+    }
+
+    pub fn to_config_string(&self) -> String {
+        self.stops
+            .iter()
+            .map(|c| hex(*c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub fn linear_rgba(c: [u8; 3]) -> [f32; 4] {
+    [to_linear(c[0]), to_linear(c[1]), to_linear(c[2]), 1.0]
+}
+
+pub fn hex(c: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+
+pub fn parse_hex(t: &str) -> Result<[u8; 3], String> {
+    let h = t.strip_prefix('#').unwrap_or(t);
+    if h.len() != 6 || !h.is_ascii() {
+        return Err(format!("invalid color {t:?} (expected #rrggbb)"));
+    }
+    let ch =
+        |i: usize| u8::from_str_radix(&h[i..i + 2], 16).map_err(|_| format!("invalid color {t:?}"));
+    Ok([ch(0)?, ch(2)?, ch(4)?])
+}
+
+// "GIMP Palette" 行の後、Name:/Columns: などのヘッダと "# コメント" を飛ばし、"R G B 名前" を読む
+fn parse_gpl(text: &str) -> Result<Vec<[u8; 3]>, String> {
+    let mut stops = Vec::new();
+    for (i, line) in text.lines().enumerate().skip(1) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.contains(':') {
+            continue;
+        }
+        let rgb: Vec<&str> = line.split_whitespace().take(3).collect();
+        let [r, g, b] = rgb.as_slice() else {
+            return Err(format!("line {}: expected \"R G B [name]\"", i + 1));
+        };
+        let ch = |v: &str| {
+            v.parse::<u8>()
+                .map_err(|_| format!("line {}: invalid channel {v:?}", i + 1))
+        };
+        stops.push([ch(r)?, ch(g)?, ch(b)?]);
+    }
+    if stops.is_empty() {
+        return Err("no colors in palette".into());
+    }
+    Ok(stops)
+}
+
+// Paint.NET: 1行1色の AARRGGBB（16進）、";" から行末はコメント。アルファは無視
+fn parse_paint_net(text: &str) -> Result<Vec<[u8; 3]>, String> {
+    let mut stops = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() != 8 {
+            return Err(format!("line {}: expected AARRGGBB, got {line:?}", i + 1));
+        }
+        stops.push(parse_hex(&line[2..]).map_err(|e| format!("line {}: {e}", i + 1))?);
+    }
+    if stops.is_empty() {
+        return Err("no colors in palette".into());
+    }
+    Ok(stops)
+}
+
+fn to_linear(v: u8) -> f32 {
+    let c = v as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_GPL: &str = "GIMP Palette
+Name: Sunset
+Columns: 3
+#
+  0   0   0\tBlack
+255 136   0\tOrange
+# コメント行
+255 255 255\tWhite
+";
+
+    #[test]
+    fn parses_gpl() {
+        assert_eq!(
+            parse_gpl(SAMPLE_GPL).unwrap(),
+            vec![[0, 0, 0], [255, 136, 0], [255, 255, 255]]
+        );
+    }
+
+    #[test]
+    fn gpl_rejects_bad_channels() {
+        let err = parse_gpl("GIMP Palette\n0 0 300 Too bright\n").unwrap_err();
+        assert!(err.contains("line 2"), "{err}");
+        assert!(parse_gpl("GIMP Palette\nName: Empty\n").is_err());
+    }
+
+    #[test]
+    fn parses_paint_net() {
+        let text = "; paint.net Palette File\n;\nFF000000\nFFFF8800 ; orange\n";
+        assert_eq!(
+            parse_paint_net(text).unwrap(),
+            vec![[0, 0, 0], [255, 136, 0]]
+        );
+        assert!(parse_paint_net("FF0000\n").is_err());
+    }
+
+    #[test]
+    fn load_clamps_to_max_stops() {
+        let path = std::env::temp_dir().join(format!("swarm-{}.gpl", std::process::id()));
+        let body: String = (0..12).map(|i| format!("{i} {i} {i}\n")).collect();
+        std::fs::write(&path, format!("GIMP Palette\n{body}")).unwrap();
+        let p = Palette::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let p = p.unwrap();
+        assert_eq!(p.stops.len(), MAX_STOPS);
+        assert_eq!(p.stops[7], [7, 7, 7]);
+    }
+
+    #[test]
+    fn config_string_round_trips() {
+        let p = Palette::parse("#000000, #FF8800,#ffffff").unwrap();
+        assert_eq!(p.to_config_string(), "#000000, #ff8800, #ffffff");
+        assert_eq!(Palette::parse(&p.to_config_string()).unwrap(), p);
+    }
+}
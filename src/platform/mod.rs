@@ -0,0 +1,87 @@
+// OS ごとの壁紙化（デスクトップの背景として描く）
+#[cfg(all(unix, not(target_os = "macos")))]
+pub mod wayland;
+#[cfg(windows)]
+pub mod windows;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub mod x11;
+
+use winit::monitor::MonitorHandle;
+use winit::window::Window;
+
+// ウィンドウの代わりに描く背景レイヤー（Wayland）
+#[cfg(all(unix, not(target_os = "macos")))]
+pub use wayland::Layer;
+
+// 背景レイヤーのないプラットフォームでは作られない
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub enum Layer {}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+impl Layer {
+    pub fn target(&self) -> wgpu::SurfaceTargetUnsafe {
+        match *self {}
+    }
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        match *self {}
+    }
+    pub fn scale_factor(&self) -> f64 {
+        match *self {}
+    }
+    pub fn poll(&mut self) -> bool {
+        match *self {}
+    }
+    pub fn closed(&self) -> bool {
+        match *self {}
+    }
+    pub fn apply_scale(&self) {
+        match *self {}
+    }
+}
+
+// 描画先を作る前の準備。Wayland では monitor の出力に背景レイヤーを用意する（それ以外は何もしない）
+pub fn prepare_wallpaper(window: &Window, monitor: Option<&MonitorHandle>) -> Result<(), String> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    return wayland::attach(window, monitor);
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = (window, monitor);
+        Ok(())
+    }
+}
+
+// prepare_wallpaper で window 用に作った背景レイヤーを受け取る
+pub fn take_layer(window: &Window) -> Option<Layer> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    return wayland::take(window);
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = window;
+        None
+    }
+}
+
+// 描画を作り直す間、背景レイヤーを預ける。次に take_layer した State が使う
+pub fn keep_layer(window: &Window, layer: Layer) {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    wayland::keep(window, layer);
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = window;
+        match layer {}
+    }
+}
+
+// ウィンドウをデスクトップの背景に埋め込む。失敗したら普通のウィンドウのまま。
+// X11 のウィンドウ種類はマップ前でないと無視するWMが多いので、非表示のうちに呼ぶ
+pub fn attach_wallpaper(window: &Window) -> Result<(), String> {
+    #[cfg(windows)]
+    return windows::attach(window);
+    #[cfg(all(unix, not(target_os = "macos")))]
+    return x11::attach(window);
+    #[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+    {
+        let _ = window;
+        Err("wallpaper mode is not supported on this platform".into())
+    }
+}
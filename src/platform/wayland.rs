@@ -0,0 +1,275 @@
+// Wayland では winit のウィンドウに xdg_toplevel の役割が付いていて背景にできないので、
+// 同じ wl_display に別の wl_surface を作り、layer-shell の背景レイヤーに置く。
+// 描画（wgpu のサーフェス）はそちらに作り、winit のウィンドウには何も描かない（表示もされない）。
+// コンポジタが zwlr_layer_shell_v1 を出していなければ普通のウィンドウのまま
+use std::ffi::c_void;
+use std::ptr::NonNull;
+use std::sync::Mutex;
+
+use smithay_client_toolkit::compositor::{CompositorHandler, CompositorState};
+use smithay_client_toolkit::output::{OutputHandler, OutputState};
+use smithay_client_toolkit::registry::{ProvidesRegistryState, RegistryState};
+use smithay_client_toolkit::shell::WaylandSurface;
+use smithay_client_toolkit::shell::wlr_layer::{
+    Anchor, KeyboardInteractivity, Layer as ShellLayer, LayerShell, LayerShellHandler,
+    LayerSurface, LayerSurfaceConfigure,
+};
+use smithay_client_toolkit::{
+    delegate_compositor, delegate_layer, delegate_output, delegate_registry, registry_handlers,
+};
+use wayland_client::globals::registry_queue_init;
+use wayland_client::protocol::{wl_output, wl_surface};
+use wayland_client::{Connection, EventQueue, Proxy, QueueHandle};
+use winit::dpi::PhysicalSize;
+use winit::monitor::MonitorHandle;
+use winit::raw_window_handle::{
+    HasDisplayHandle, RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
+};
+use winit::window::{Window, WindowId};
+
+// 作ったレイヤーを State が受け取るまで（State を作り直す間も）預かる
+static LAYERS: Mutex<Vec<(WindowId, Layer)>> = Mutex::new(Vec::new());
+
+// 背景レイヤーの wl_surface と、そのイベントキュー
+pub struct Layer {
+    // winit の wl_display。wgpu にはこちらを渡す
+    display: NonNull<c_void>,
+    queue: EventQueue<Handler>,
+    handler: Handler,
+    surface: LayerSurface,
+}
+
+// 中身はどれも winit の接続に属するポインタとプロキシで、一度に触るのは持ち主の State だけ
+unsafe impl Send for Layer {}
+
+struct Handler {
+    registry: RegistryState,
+    outputs: OutputState,
+    // 最後に受け取った configure の大きさ（論理ピクセル）。最初の configure までは None
+    size: Option<(u32, u32)>,
+    // 出力の倍率。バッファはこの倍の大きさで描く
+    scale: i32,
+    closed: bool,
+}
+
+// winit の Wayland ウィンドウなら背景レイヤーを作って預ける。それ以外のウィンドウでは何もしない。
+// monitor を指定すればその出力に置き、なければコンポジタが選ぶ
+pub fn attach(window: &Window, monitor: Option<&MonitorHandle>) -> Result<(), String> {
+    let handle = window.display_handle().map_err(|e| e.to_string())?;
+    let RawDisplayHandle::Wayland(display) = handle.as_raw() else {
+        return Ok(());
+    };
+    // winit の接続を借りる。ウィンドウ（と接続）はプロセスの終わりまで残る
+    let backend = unsafe {
+        wayland_backend::client::Backend::from_foreign_display(display.display.as_ptr().cast())
+    };
+    let conn = Connection::from_backend(backend);
+    let (globals, mut queue) = registry_queue_init::<Handler>(&conn).map_err(|e| e.to_string())?;
+    let qh = queue.handle();
+    let compositor = CompositorState::bind(&globals, &qh).map_err(|e| e.to_string())?;
+    let shell = LayerShell::bind(&globals, &qh)
+        .map_err(|_| "the compositor does not advertise zwlr_layer_shell_v1".to_string())?;
+
+    let mut handler = Handler {
+        registry: RegistryState::new(&globals),
+        outputs: OutputState::new(&globals, &qh),
+        size: None,
+        scale: 1,
+        closed: false,
+    };
+    // 出力の名前と倍率を受け取ってから、ウィンドウのモニターに当たる wl_output を探す
+    queue.roundtrip(&mut handler).map_err(|e| e.to_string())?;
+    let monitor = monitor.cloned().or_else(|| window.current_monitor());
+    let name = monitor.and_then(|m| m.name());
+    let output = name.as_ref().and_then(|name| {
+        handler.outputs.outputs().find(|o| {
+            handler
+                .outputs
+                .info(o)
+                .is_some_and(|info| info.name.as_ref() == Some(name))
+        })
+    });
+    if let Some(info) = output.as_ref().and_then(|o| handler.outputs.info(o)) {
+        handler.scale = info.scale_factor.max(1);
+    } else if let Some(name) = &name {
+        eprintln!("Wayland output {name:?} not found; the compositor will choose one.");
+    }
+
+    let surface = shell.create_layer_surface(
+        &qh,
+        compositor.create_surface(&qh),
+        ShellLayer::Background,
+        Some("swarm-wallpaper"),
+        output.as_ref(),
+    );
+    // 大きさ 0 と全辺のアンカーで出力全体に広げる。他のパネルを押しのけない
+    surface.set_anchor(Anchor::all());
+    surface.set_exclusive_zone(0);
+    surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+    surface.set_size(0, 0);
+    surface.wl_surface().set_buffer_scale(handler.scale);
+    surface.commit();
+
+    // 最初の configure で大きさが決まるまではバッファを付けられない
+    while handler.size.is_none() {
+        if handler.closed {
+            return Err("the compositor closed the background layer".into());
+        }
+        queue
+            .blocking_dispatch(&mut handler)
+            .map_err(|e| format!("layer surface was not configured: {e}"))?;
+    }
+    let layer = Layer {
+        display: display.display,
+        queue,
+        handler,
+        surface,
+    };
+    LAYERS.lock().unwrap().push((window.id(), layer));
+    Ok(())
+}
+
+// window 用に作ったレイヤーを受け取る
+pub fn take(window: &Window) -> Option<Layer> {
+    let mut layers = LAYERS.lock().unwrap();
+    let i = layers.iter().position(|(id, _)| *id == window.id())?;
+    Some(layers.swap_remove(i).1)
+}
+
+// State を作り直す間、次の State が受け取れるよう預け直す
+pub fn keep(window: &Window, layer: Layer) {
+    LAYERS.lock().unwrap().push((window.id(), layer));
+}
+
+impl Layer {
+    // wgpu のサーフェスを作る先
+    pub fn target(&self) -> wgpu::SurfaceTargetUnsafe {
+        let surface =
+            NonNull::new(self.surface.wl_surface().id().as_ptr().cast()).expect("live wl_surface");
+        wgpu::SurfaceTargetUnsafe::RawHandle {
+            raw_display_handle: RawDisplayHandle::Wayland(WaylandDisplayHandle::new(self.display)),
+            raw_window_handle: RawWindowHandle::Wayland(WaylandWindowHandle::new(surface)),
+        }
+    }
+
+    // 描画先の大きさ（物理ピクセル）
+    pub fn size(&self) -> PhysicalSize<u32> {
+        let (w, h) = self.handler.size.unwrap_or((1, 1));
+        let scale = self.handler.scale as u32;
+        PhysicalSize::new(w * scale, h * scale)
+    }
+
+    // 出力の倍率。winit のウィンドウは表示されないので、その倍率は当てにならない
+    pub fn scale_factor(&self) -> f64 {
+        self.handler.scale as f64
+    }
+
+    // 届いているイベントを処理する。キューが空なら何もしない。大きさか倍率が変わったら true
+    pub fn poll(&mut self) -> bool {
+        if self.queue.prepare_read().is_some() {
+            return false;
+        }
+        let before = (self.handler.size, self.handler.scale);
+        if let Err(e) = self.queue.dispatch_pending(&mut self.handler) {
+            eprintln!("Wayland layer surface: {e}");
+        }
+        (self.handler.size, self.handler.scale) != before
+    }
+
+    // 出力が外れたときなどにコンポジタが閉じた。もう描けない
+    pub fn closed(&self) -> bool {
+        self.handler.closed
+    }
+
+    // 倍率はバッファの大きさと同じ commit で変える（大きさが倍率で割り切れないとエラーになる）。
+    // サーフェスを構成し直した直後、次の present の前に呼ぶ
+    pub fn apply_scale(&self) {
+        self.surface
+            .wl_surface()
+            .set_buffer_scale(self.handler.scale);
+    }
+}
+
+impl LayerShellHandler for Handler {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {
+        self.closed = true;
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _: u32,
+    ) {
+        // ack は sctk が返す。0 は「任せる」なので 1 にしておく
+        let (w, h) = configure.new_size;
+        self.size = Some((w.max(1), h.max(1)));
+    }
+}
+
+impl CompositorHandler for Handler {
+    fn scale_factor_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        new_factor: i32,
+    ) {
+        self.scale = new_factor.max(1);
+    }
+
+    fn transform_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+
+    fn surface_enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for Handler {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.outputs
+    }
+
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+}
+
+impl ProvidesRegistryState for Handler {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry
+    }
+    registry_handlers![OutputState];
+}
+
+delegate_compositor!(Handler);
+delegate_output!(Handler);
+delegate_layer!(Handler);
+delegate_registry!(Handler);
@@ -0,0 +1,99 @@
+// Progman にメッセージ 0x052C を送ってデスクトップアイコンの裏に WorkerW を作らせ、
+// そこへ自分のウィンドウを SetParent する。windows crate は使わず user32 を直接呼ぶ
+use std::ffi::c_void;
+use std::ptr::{null, null_mut};
+
+use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use winit::window::Window;
+
+type Hwnd = *mut c_void;
+
+const SPAWN_WORKERW: u32 = 0x052C;
+const SMTO_NORMAL: u32 = 0x0000;
+const SWP_NOZORDER: u32 = 0x0004;
+const SWP_NOACTIVATE: u32 = 0x0010;
+const SM_CXVIRTUALSCREEN: i32 = 78;
+const SM_CYVIRTUALSCREEN: i32 = 79;
+
+#[link(name = "user32")]
+unsafe extern "system" {
+    fn FindWindowW(class: *const u16, name: *const u16) -> Hwnd;
+    fn FindWindowExW(parent: Hwnd, after: Hwnd, class: *const u16, name: *const u16) -> Hwnd;
+    fn SendMessageTimeoutW(
+        hwnd: Hwnd,
+        msg: u32,
+        wparam: usize,
+        lparam: isize,
+        flags: u32,
+        timeout: u32,
+        result: *mut usize,
+    ) -> isize;
+    fn EnumWindows(callback: unsafe extern "system" fn(Hwnd, isize) -> i32, lparam: isize) -> i32;
+    fn SetParent(child: Hwnd, parent: Hwnd) -> Hwnd;
+    fn SetWindowPos(hwnd: Hwnd, after: Hwnd, x: i32, y: i32, w: i32, h: i32, flags: u32) -> i32;
+    fn GetSystemMetrics(index: i32) -> i32;
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+// SHELLDLL_DefView（アイコン）を持つトップレベルウィンドウの次の WorkerW が背景になる
+unsafe extern "system" fn find_workerw(top: Hwnd, out: isize) -> i32 {
+    let defview = wide("SHELLDLL_DefView");
+    let workerw = wide("WorkerW");
+    unsafe {
+        if FindWindowExW(top, null_mut(), defview.as_ptr(), null()).is_null() {
+            return 1;
+        }
+        *(out as *mut Hwnd) = FindWindowExW(null_mut(), top, workerw.as_ptr(), null());
+    }
+    0
+}
+
+fn workerw() -> Result<Hwnd, String> {
+    unsafe {
+        let progman = FindWindowW(wide("Progman").as_ptr(), null());
+        if progman.is_null() {
+            return Err("Progman window not found".into());
+        }
+        let mut result = 0;
+        SendMessageTimeoutW(progman, SPAWN_WORKERW, 0, 0, SMTO_NORMAL, 1000, &mut result);
+
+        let mut found: Hwnd = null_mut();
+        EnumWindows(find_workerw, &mut found as *mut Hwnd as isize);
+        // Windows 11 の新しいシェルでは WorkerW が Progman の子になっている
+        if found.is_null() {
+            found = FindWindowExW(progman, null_mut(), wide("WorkerW").as_ptr(), null());
+        }
+        if found.is_null() {
+            return Err("WorkerW window not found".into());
+        }
+        Ok(found)
+    }
+}
+
+pub fn attach(window: &Window) -> Result<(), String> {
+    let handle = window.window_handle().map_err(|e| e.to_string())?;
+    let RawWindowHandle::Win32(h) = handle.as_raw() else {
+        return Err("not a Win32 window".into());
+    };
+    let hwnd = h.hwnd.get() as Hwnd;
+    let parent = workerw()?;
+    unsafe {
+        if SetParent(hwnd, parent).is_null() {
+            return Err("SetParent failed".into());
+        }
+        // WorkerW は仮想スクリーン全体を覆うので、その左上から全体に広げる
+        SetWindowPos(
+            hwnd,
+            null_mut(),
+            0,
+            0,
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            SWP_NOZORDER | SWP_NOACTIVATE,
+        );
+    }
+    Ok(())
+}
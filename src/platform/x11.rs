@@ -0,0 +1,62 @@
+// X11 ではウィンドウの種類を _NET_WM_WINDOW_TYPE_DESKTOP にして、
+// コンポジタやWMに背景として扱わせる（最背面・装飾なし・全ワークスペース）。
+// winit とは別に自前で接続し、同じウィンドウIDに対して操作する
+use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use winit::window::Window;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConfigureWindowAux, ConnectionExt, PropMode, StackMode};
+use x11rb::wrapper::ConnectionExt as _;
+
+pub fn attach(window: &Window) -> Result<(), String> {
+    let handle = window.window_handle().map_err(|e| e.to_string())?;
+    let id = match handle.as_raw() {
+        RawWindowHandle::Xlib(h) => h.window as u32,
+        RawWindowHandle::Xcb(h) => h.window.get(),
+        // Wayland では wayland.rs が別のサーフェスを背景レイヤーに置く
+        RawWindowHandle::Wayland(_) => {
+            return Err("no Wayland background layer".into());
+        }
+        _ => return Err("not an X11 window".into()),
+    };
+    let (conn, screen) = x11rb::connect(None).map_err(|e| format!("no X11 display: {e}"))?;
+    let root = conn.setup().roots[screen].root;
+    let err = |e: &dyn std::fmt::Display| format!("X11 request failed: {e}");
+
+    let atom = |name: &str| -> Result<u32, String> {
+        Ok(conn
+            .intern_atom(false, name.as_bytes())
+            .map_err(|e| err(&e))?
+            .reply()
+            .map_err(|e| err(&e))?
+            .atom)
+    };
+    let window_type = atom("_NET_WM_WINDOW_TYPE")?;
+    let desktop = atom("_NET_WM_WINDOW_TYPE_DESKTOP")?;
+    conn.change_property32(
+        PropMode::REPLACE,
+        id,
+        window_type,
+        AtomEnum::ATOM,
+        &[desktop],
+    )
+    .map_err(|e| err(&e))?;
+
+    // 種類を見ないWMのために、ルートウィンドウ全体に広げて最背面へ送る
+    let root_geom = conn
+        .get_geometry(root)
+        .map_err(|e| err(&e))?
+        .reply()
+        .map_err(|e| err(&e))?;
+    conn.configure_window(
+        id,
+        &ConfigureWindowAux::new()
+            .x(0)
+            .y(0)
+            .width(root_geom.width as u32)
+            .height(root_geom.height as u32)
+            .stack_mode(StackMode::BELOW),
+    )
+    .map_err(|e| err(&e))?;
+    conn.flush().map_err(|e| err(&e))?;
+    Ok(())
+}
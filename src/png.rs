@@ -0,0 +1,530 @@
+// 依存を増やさないための最小 PNG エンコーダ（RGBA、8/16bit、無圧縮 deflate）と、
+// 背景画像を読むためのデコーダ（インターレースなしの全カラータイプ → RGBA8）
+use std::path::Path;
+
+use crate::export::ExportError;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Depth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl Depth {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Depth::Eight => 4,
+            Depth::Sixteen => 8,
+        }
+    }
+}
+
+// data は行ごとに詰めた RGBA。16bit はビッグエンディアン
+pub fn encode(w: u32, h: u32, depth: Depth, data: &[u8]) -> Vec<u8> {
+    let row = w as usize * depth.bytes_per_pixel();
+    assert_eq!(data.len(), row * h as usize, "pixel data size mismatch");
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&w.to_be_bytes());
+    ihdr.extend_from_slice(&h.to_be_bytes());
+    ihdr.push(match depth {
+        Depth::Eight => 8,
+        Depth::Sixteen => 16,
+    });
+    // カラータイプ 6 (RGBA)、圧縮/フィルタ/インターレースは 0
+    ihdr.extend_from_slice(&[6, 0, 0, 0]);
+
+    // 各行の先頭にフィルタ種別 0 (None)
+    let mut raw = Vec::with_capacity((row + 1) * h as usize);
+    for line in data.chunks_exact(row.max(1)).take(h as usize) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+pub fn write(path: &Path, w: u32, h: u32, depth: Depth, data: &[u8]) -> Result<(), ExportError> {
+    std::fs::write(path, encode(w, h, depth, data)).map_err(|e| ExportError::io(path, e))
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// 圧縮しない deflate ブロック（最大 65535 バイトずつ）を zlib で包む
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(b) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = b.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(b);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &x in data {
+        a = (a + x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// PNG を読み込んで (幅, 高さ, RGBA8) を返す。16bit は上位バイトだけ使う
+pub fn decode(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let body = bytes
+        .strip_prefix(b"\x89PNG\r\n\x1a\n")
+        .ok_or("not a PNG file")?;
+    let mut chunks = body;
+    let mut header = None;
+    let mut plte: &[u8] = &[];
+    let mut trns: &[u8] = &[];
+    let mut idat = Vec::new();
+    while chunks.len() >= 12 {
+        let len = u32::from_be_bytes(chunks[..4].try_into().unwrap()) as usize;
+        let kind = &chunks[4..8];
+        let data = chunks.get(8..8 + len).ok_or("truncated chunk")?;
+        match kind {
+            b"IHDR" if len == 13 => header = Some(data),
+            b"PLTE" => plte = data,
+            b"tRNS" => trns = data,
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => break,
+            _ => (),
+        }
+        chunks = &chunks[(12 + len).min(chunks.len())..];
+    }
+    let h = header.ok_or("missing IHDR")?;
+    let width = u32::from_be_bytes(h[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(h[4..8].try_into().unwrap());
+    let (depth, color) = (h[8] as usize, h[9]);
+    if h[12] != 0 {
+        return Err("interlaced PNG is not supported".into());
+    }
+    let channels = match color {
+        0 => 1,
+        2 => 3,
+        3 => 1,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("unknown color type {color}")),
+    };
+    if !matches!(depth, 1 | 2 | 4 | 8 | 16) || (depth < 8 && !matches!(color, 0 | 3)) {
+        return Err(format!(
+            "unsupported bit depth {depth} for color type {color}"
+        ));
+    }
+    if width == 0 || height == 0 {
+        return Err("empty image".into());
+    }
+
+    let raw = inflate(idat.get(2..).ok_or("missing image data")?)?;
+    let (w, ht) = (width as usize, height as usize);
+    let bits_per_pixel = channels * depth;
+    let row = (w * bits_per_pixel).div_ceil(8);
+    let bpp = bits_per_pixel.div_ceil(8);
+    if raw.len() < (row + 1) * ht {
+        return Err("image data is shorter than the image".into());
+    }
+
+    // 各行の先頭のフィルタ種別を戻す
+    let mut pixels = vec![0u8; row * ht];
+    for y in 0..ht {
+        let filter = raw[y * (row + 1)];
+        let src = &raw[y * (row + 1) + 1..(y + 1) * (row + 1)];
+        let (done, cur) = pixels.split_at_mut(y * row);
+        let prev = if y > 0 {
+            &done[(y - 1) * row..]
+        } else {
+            &[][..]
+        };
+        let cur = &mut cur[..row];
+        for x in 0..row {
+            let a = if x >= bpp { cur[x - bpp] } else { 0 };
+            let b = prev.get(x).copied().unwrap_or(0);
+            let c = if x >= bpp {
+                prev.get(x - bpp).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            cur[x] = src[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("unknown filter type {filter}")),
+            });
+        }
+    }
+
+    // サンプル値（0..2^depth-1）を取り出す。16bit は上位バイト
+    let sample = |line: &[u8], i: usize| -> u8 {
+        match depth {
+            8 => line[i],
+            16 => line[i * 2],
+            _ => {
+                let bit = i * depth;
+                let v = (line[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8;
+                if color == 3 {
+                    v
+                } else {
+                    (v as u32 * 255 / ((1 << depth) - 1)) as u8
+                }
+            }
+        }
+    };
+    let mut out = Vec::with_capacity(w * ht * 4);
+    for line in pixels.chunks_exact(row) {
+        for x in 0..w {
+            let s = |c: usize| sample(line, x * channels + c);
+            let px = match color {
+                0 => [s(0), s(0), s(0), 255],
+                2 => [s(0), s(1), s(2), 255],
+                3 => {
+                    let i = s(0) as usize;
+                    let rgb = plte
+                        .get(i * 3..i * 3 + 3)
+                        .ok_or("palette index out of range")?;
+                    [rgb[0], rgb[1], rgb[2], trns.get(i).copied().unwrap_or(255)]
+                }
+                4 => [s(0), s(0), s(0), s(1)],
+                _ => [s(0), s(1), s(2), s(3)],
+            };
+            out.extend_from_slice(&px);
+        }
+    }
+    Ok((width, height, out))
+}
+
+pub fn load(path: &Path) -> Result<(u32, u32, Vec<u8>), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    decode(&bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// deflate の伸張（無圧縮・固定ハフマン・動的ハフマンの各ブロック）
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    const LEN_BASE: [u16; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const LEN_EXTRA: [u8; 29] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+    ];
+    const DIST_BASE: [u16; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    const DIST_EXTRA: [u8; 30] = [
+        0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+        13, 13,
+    ];
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+
+    let mut bits = Bits { data, pos: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                let start = bits.pos.div_ceil(8);
+                let head = data.get(start..start + 4).ok_or("truncated stored block")?;
+                let len = u16::from_le_bytes([head[0], head[1]]) as usize;
+                let block = data
+                    .get(start + 4..start + 4 + len)
+                    .ok_or("truncated stored block")?;
+                out.extend_from_slice(block);
+                bits.pos = (start + 4 + len) * 8;
+            }
+            kind @ (1 | 2) => {
+                let (lit, dist) = if kind == 1 {
+                    let mut lengths = [8u8; 288];
+                    lengths[144..256].fill(9);
+                    lengths[256..280].fill(7);
+                    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+                } else {
+                    let hlit = bits.take(5)? as usize + 257;
+                    let hdist = bits.take(5)? as usize + 1;
+                    let hclen = bits.take(4)? as usize + 4;
+                    let mut code_lengths = [0u8; 19];
+                    for &i in &ORDER[..hclen] {
+                        code_lengths[i] = bits.take(3)? as u8;
+                    }
+                    let code = Huffman::new(&code_lengths);
+                    let mut lengths = Vec::with_capacity(hlit + hdist);
+                    while lengths.len() < hlit + hdist {
+                        let (value, repeat) = match code.decode(&mut bits)? {
+                            sym @ 0..=15 => (sym as u8, 1),
+                            16 => (
+                                *lengths.last().ok_or("repeat without a previous length")?,
+                                3 + bits.take(2)?,
+                            ),
+                            17 => (0, 3 + bits.take(3)?),
+                            _ => (0, 11 + bits.take(7)?),
+                        };
+                        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+                    }
+                    if lengths.len() > hlit + hdist {
+                        return Err("code lengths overflow".into());
+                    }
+                    (
+                        Huffman::new(&lengths[..hlit]),
+                        Huffman::new(&lengths[hlit..]),
+                    )
+                };
+                loop {
+                    let sym = lit.decode(&mut bits)? as usize;
+                    match sym {
+                        0..=255 => out.push(sym as u8),
+                        256 => break,
+                        _ => {
+                            let i = sym - 257;
+                            let len = *LEN_BASE.get(i).ok_or("bad length code")? as usize
+                                + bits.take(LEN_EXTRA[i] as u32)? as usize;
+                            let d = dist.decode(&mut bits)? as usize;
+                            let back = *DIST_BASE.get(d).ok_or("bad distance code")? as usize
+                                + bits.take(DIST_EXTRA[d] as u32)? as usize;
+                            let from =
+                                out.len().checked_sub(back).ok_or("distance too far back")?;
+                            // 重なりがあるので1バイトずつ写す
+                            for k in 0..len {
+                                out.push(out[from + k]);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => return Err("invalid deflate block type".into()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+// LSB から読むビット列
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: u32) -> Result<u32, String> {
+        let mut v = 0;
+        for i in 0..n {
+            let byte = *self
+                .data
+                .get(self.pos / 8)
+                .ok_or("unexpected end of image data")?;
+            v |= (((byte >> (self.pos % 8)) & 1) as u32) << i;
+            self.pos += 1;
+        }
+        Ok(v)
+    }
+}
+
+// 正規ハフマン符号。符号長ごとの個数と、符号順に並べた記号
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|&s| lengths[s as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|&s| lengths[s as usize]);
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.take(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".into())
+    }
+}
+
+// 線形の値を sRGB の 16bit に
+pub fn linear_to_srgb16(v: f32) -> u16 {
+    let v = v.clamp(0.0, 1.0);
+    let s = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (s * 65535.0).round() as u16
+}
+
+// IEEE 754 半精度 → f32
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let frac = (bits & 0x3FF) as f32;
+    match exp {
+        0 => sign * frac * 2f32.powi(-24),
+        0x1F if frac == 0.0 => sign * f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => sign * (1.0 + frac / 1024.0) * 2f32.powi(exp - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn header_describes_image() {
+        let png = encode(2, 1, Depth::Sixteen, &[0; 16]);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(png[24], 16);
+        assert_eq!(png[25], 6);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn stored_blocks_split_large_rows() {
+        let data = vec![7u8; 70_000];
+        let z = zlib_stored(&data);
+        // ヘッダ 2 + ブロック見出し 5 × 2 + データ + adler 4
+        assert_eq!(z.len(), 2 + 10 + data.len() + 4);
+        assert_eq!(z[2], 0);
+        assert_eq!(z[2 + 5 + 0xFFFF], 1);
+    }
+
+    #[test]
+    fn decode_reads_back_encoded_images() {
+        let px: Vec<u8> = (0..3 * 2 * 4).map(|i| (i * 11) as u8).collect();
+        assert_eq!(
+            decode(&encode(3, 2, Depth::Eight, &px)).unwrap(),
+            (3, 2, px)
+        );
+        // 16bit は上位バイトを使う
+        let wide: Vec<u8> = [0x12, 0x34, 0xAB, 0xCD, 0, 0, 0xFF, 0xFF].to_vec();
+        let (_, _, rgba) = decode(&encode(1, 1, Depth::Sixteen, &wide)).unwrap();
+        assert_eq!(rgba, [0x12, 0xAB, 0, 0xFF]);
+    }
+
+    #[test]
+    fn decode_handles_compressed_and_filtered_data() {
+        // 動的ハフマン、行ごとに 5 種類のフィルタを順に使った 16x16 RGB
+        let (w, h, px) = decode(include_bytes!("testdata/gradient-dynamic.png")).unwrap();
+        assert_eq!((w, h), (16, 16));
+        for y in 0..16 {
+            for x in 0..16 {
+                let i = (y * 16 + x) * 4;
+                assert_eq!(
+                    px[i..i + 4],
+                    [x as u8 * 16, y as u8 * 16, (x + y) as u8 * 8, 255]
+                );
+            }
+        }
+        // 固定ハフマン、1bit パレットと tRNS
+        let (w, h, px) = decode(include_bytes!("testdata/palette-1bit.png")).unwrap();
+        assert_eq!((w, h), (8, 2));
+        let red = [255, 0, 0, 128];
+        let blue = [0, 0, 255, 255];
+        assert_eq!(px[..16], [blue, red, blue, blue].concat());
+        assert_eq!(px[32..36], red);
+    }
+
+    #[test]
+    fn decode_rejects_broken_files() {
+        assert!(decode(b"GIF89a").is_err());
+        let png = encode(4, 4, Depth::Eight, &[9; 64]);
+        assert!(decode(&png[..png.len() - 40]).is_err());
+    }
+
+    #[test]
+    fn half_floats_decode() {
+        assert_eq!(f16_to_f32(0x3C00), 1.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0xC000), -2.0);
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+    }
+
+    #[test]
+    fn sixteen_bit_keeps_more_levels_of_a_dark_gradient() {
+        // 暗部の細かい階調は 8bit ではつぶれる
+        let ramp: Vec<f32> = (0..1000).map(|i| i as f32 / 1000.0 * 0.01).collect();
+        let levels16: std::collections::BTreeSet<u16> =
+            ramp.iter().map(|v| linear_to_srgb16(*v)).collect();
+        let levels8: std::collections::BTreeSet<u16> =
+            ramp.iter().map(|v| linear_to_srgb16(*v) >> 8).collect();
+        assert!(levels16.len() > 20 * levels8.len());
+    }
+}
@@ -0,0 +1,94 @@
+// 電源の状態。ノート PC で電池駆動のときに描画を間引くのに使う（--battery-fps）
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+// 電池で動いているか。調べられない環境では false（電源につながっているとみなす）
+pub fn is_on_battery() -> bool {
+    #[cfg(target_os = "linux")]
+    return on_battery_sysfs(Path::new("/sys/class/power_supply"));
+    #[cfg(windows)]
+    return windows::on_battery();
+    #[cfg(not(any(target_os = "linux", windows)))]
+    false
+}
+
+// AC アダプタ（Mains/USB）がつながっていれば電源、そうでなく放電中の電池があれば電池
+#[cfg(target_os = "linux")]
+fn on_battery_sysfs(root: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return false;
+    };
+    let read = |dir: &Path, name: &str| {
+        std::fs::read_to_string(dir.join(name))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut discharging = false;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        match read(&dir, "type").as_str() {
+            "Mains" | "USB" if read(&dir, "online") == "1" => return false,
+            "Battery" if read(&dir, "status") == "Discharging" => discharging = true,
+            _ => (),
+        }
+    }
+    discharging
+}
+
+#[cfg(windows)]
+mod windows {
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        system_status_flag: u8,
+        battery_life_time: u32,
+        battery_full_life_time: u32,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    // ACLineStatus: 0 = 電池, 1 = 電源, 255 = 不明
+    pub fn on_battery() -> bool {
+        let mut status = SystemPowerStatus::default();
+        unsafe { GetSystemPowerStatus(&mut status) != 0 && status.ac_line_status == 0 }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn supply(root: &Path, name: &str, files: &[(&str, &str)]) {
+        let dir = root.join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, value) in files {
+            std::fs::write(dir.join(file), format!("{value}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn sysfs_reports_battery_only_without_ac() {
+        let root = std::env::temp_dir().join(format!("swarm-power-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        supply(
+            &root,
+            "BAT0",
+            &[("type", "Battery"), ("status", "Discharging")],
+        );
+        supply(&root, "AC", &[("type", "Mains"), ("online", "0")]);
+        assert!(on_battery_sysfs(&root));
+
+        supply(&root, "AC", &[("type", "Mains"), ("online", "1")]);
+        assert!(!on_battery_sysfs(&root));
+
+        // デスクトップ機（電池なし）や読めない環境は電源扱い
+        let _ = std::fs::remove_dir_all(&root);
+        assert!(!on_battery_sysfs(&root));
+    }
+}
@@ -0,0 +1,84 @@
+// ←/→ で切り替える noise の見た目の組み合わせ（模様の種類・色・速さ）
+use crate::config::NoiseKind;
+
+pub struct Preset {
+    pub name: &'static str,
+    pub noise: NoiseKind,
+    // sRGB の 8bit。2〜palette::MAX_STOPS 色
+    pub palette: &'static [[u8; 3]],
+    pub speed: f32,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "static",
+        noise: NoiseKind::Hash,
+        palette: &[[0, 0, 0], [255, 255, 255]],
+        speed: 1.0,
+    },
+    Preset {
+        name: "ember",
+        noise: NoiseKind::Perlin,
+        palette: &[[8, 0, 0], [160, 32, 0], [255, 136, 0], [255, 232, 160]],
+        speed: 0.5,
+    },
+    Preset {
+        name: "ocean",
+        noise: NoiseKind::Simplex,
+        palette: &[[0, 8, 24], [0, 64, 128], [32, 160, 192], [224, 255, 255]],
+        speed: 0.3,
+    },
+    Preset {
+        name: "moss",
+        noise: NoiseKind::Value,
+        palette: &[[4, 12, 4], [40, 96, 32], [168, 200, 96]],
+        speed: 0.25,
+    },
+    Preset {
+        name: "neon",
+        noise: NoiseKind::Simplex,
+        palette: &[[16, 0, 32], [255, 0, 160], [0, 240, 255]],
+        speed: 1.5,
+    },
+];
+
+// current から step 個先（負なら前）。未選択から → なら先頭、← なら末尾
+pub fn step(current: Option<usize>, step: isize) -> usize {
+    let n = PRESETS.len() as isize;
+    let from = current.map_or(if step > 0 { -1 } else { 0 }, |i| i as isize);
+    (from + step).rem_euclid(n) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::MAX_STOPS;
+
+    #[test]
+    fn presets_are_usable() {
+        for (i, p) in PRESETS.iter().enumerate() {
+            assert!(
+                (2..=MAX_STOPS).contains(&p.palette.len()),
+                "{}: {} colors",
+                p.name,
+                p.palette.len()
+            );
+            assert!(p.speed > 0.0, "{}", p.name);
+            assert!(
+                PRESETS[..i].iter().all(|q| q.name != p.name),
+                "duplicate preset {}",
+                p.name
+            );
+        }
+    }
+
+    #[test]
+    fn step_wraps_both_ways() {
+        let last = PRESETS.len() - 1;
+        assert_eq!(step(None, 1), 0);
+        assert_eq!(step(None, -1), last);
+        assert_eq!(step(Some(0), 1), 1);
+        assert_eq!(step(Some(last), 1), 0);
+        assert_eq!(step(Some(0), -1), last);
+    }
+}
@@ -0,0 +1,87 @@
+// 外部からのパラメータ入力。受信スレッドは最新値だけを保持し、
+// イベントループには UserEvent::Params で「更新あり」を通知する。
+//
+// パイプのプロトコル（1行1件、UTF-8）:
+//   <name> <value>      例: "time_scale.x 0.5"
+//   <name>=<value>      例: "invert=1"
+// 空行と `#` で始まる行は無視。未知の名前は State::set_param がエラーを返す。
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::app::UserEvent;
+
+pub struct ParamInbox {
+    latest: Mutex<HashMap<String, f32>>,
+    pending: AtomicBool,
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+impl ParamInbox {
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Arc<Self> {
+        Arc::new(Self {
+            latest: Mutex::new(HashMap::new()),
+            pending: AtomicBool::new(false),
+            proxy,
+        })
+    }
+
+    // 同じキーは上書きするので、描画が追いつかなくても溜まらない
+    pub fn push(&self, name: &str, value: f32) {
+        self.latest.lock().unwrap().insert(name.to_string(), value);
+        if !self.pending.swap(true, Ordering::AcqRel) {
+            let _ = self.proxy.send_event(UserEvent::Params);
+        }
+    }
+
+    pub fn drain(&self) -> Vec<(String, f32)> {
+        self.pending.store(false, Ordering::Release);
+        self.latest.lock().unwrap().drain().collect()
+    }
+}
+
+pub fn parse_line(line: &str) -> Option<(&str, f32)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (k, v) = line
+        .split_once('=')
+        .or_else(|| line.split_once(char::is_whitespace))?;
+    Some((k.trim(), v.trim().parse().ok()?))
+}
+
+// FIFO は書き手が閉じるたびに開き直す。通常ファイルは一度だけ読む
+pub fn spawn_pipe(path: PathBuf, inbox: Arc<ParamInbox>) {
+    let _ = std::thread::Builder::new()
+        .name("param-pipe".into())
+        .spawn(move || {
+            loop {
+                let file = match std::fs::File::open(&path) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        eprintln!("param pipe {}: {e}", path.display());
+                        return;
+                    }
+                };
+                let once = file.metadata().map(|m| m.is_file()).unwrap_or(true);
+                for line in BufReader::new(file).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    match parse_line(&line) {
+                        Some((k, v)) => inbox.push(k, v),
+                        None if line.trim().is_empty() || line.trim().starts_with('#') => (),
+                        None => eprintln!("param pipe: ignoring {line:?}"),
+                    }
+                }
+                if once {
+                    return;
+                }
+            }
+        });
+}
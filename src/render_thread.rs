@@ -0,0 +1,168 @@
+// --render-thread: State を専用スレッドに移し、メインスレッドはイベント処理だけを行う。
+// メインスレッドからの操作はクロージャとして送り、描画の合間に実行する。
+//
+// 注意:
+// - macOS ではサーフェスの取得・present をメインスレッド以外から行うと
+//   リサイズ中に表示が乱れることがある。
+// - --interval / --fps-cap / --battery-fps / --redraw=on-input / --fb-output / --watchdog / --idle-timeout はこのモードでは使えない（常に連続描画）。
+// - ウィンドウタイトルには FPS だけを出す。
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::app::{DEFAULT_TIMEOUT_LIMIT, UserEvent};
+use crate::config::Config;
+use crate::fps::FpsMeter;
+use crate::metrics::{self, FrameLog};
+use crate::state::{FrameHook, State, StateError};
+use winit::event_loop::EventLoopProxy;
+use winit::window::Window;
+
+// 複数の出力があると同じ操作をそれぞれの State に対して呼ぶ
+pub type StateFn = Box<dyn FnMut(&mut State) + Send>;
+
+// デバイスを失ったときに同じウィンドウと今のエフェクト（名前）で State を作り直す
+pub type Rebuild = Box<dyn Fn(Arc<Window>, &str) -> Result<State, StateError> + Send>;
+
+enum Cmd {
+    Run(StateFn),
+    Stop,
+}
+
+pub struct RenderThread {
+    tx: Sender<Cmd>,
+    // 作り直しに失敗して止まったときは State が残らない
+    handle: JoinHandle<Option<State>>,
+}
+
+impl RenderThread {
+    pub fn spawn(
+        state: State,
+        cfg: &Config,
+        on_frame: Option<FrameHook>,
+        log: Option<FrameLog>,
+        proxy: Option<EventLoopProxy<UserEvent>>,
+        rebuild: Rebuild,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let verbose = cfg.verbose;
+        let timeout_limit = cfg.timeout_limit.unwrap_or(DEFAULT_TIMEOUT_LIMIT);
+        let handle = std::thread::Builder::new()
+            .name("render".into())
+            .spawn(move || {
+                let failed = |e: StateError| {
+                    // 知らせる先がなければ（埋め込みで proxy を渡していない）ここで伝える
+                    match &proxy {
+                        Some(p) => {
+                            let _ = p.send_event(UserEvent::RenderFailed(e.to_string()));
+                        }
+                        None => eprintln!("Renderer rebuild failed: {e}"),
+                    }
+                };
+                run(state, rx, on_frame, log, rebuild, verbose, timeout_limit)
+                    .map_err(failed)
+                    .ok()
+            })
+            .expect("spawn render thread");
+        Self { tx, handle }
+    }
+
+    pub fn send(&self, f: StateFn) {
+        let _ = self.tx.send(Cmd::Run(f));
+    }
+
+    // スレッドを止めて State を返す。作り直しに失敗して止まっていれば None
+    pub fn stop(self) -> Option<State> {
+        let _ = self.tx.send(Cmd::Stop);
+        self.handle.join().expect("render thread panicked")
+    }
+}
+
+fn run(
+    mut state: State,
+    rx: Receiver<Cmd>,
+    mut on_frame: Option<FrameHook>,
+    mut log: Option<FrameLog>,
+    rebuild: Rebuild,
+    verbose: bool,
+    timeout_limit: u32,
+) -> Result<State, StateError> {
+    let mut fps = FpsMeter::default();
+    let mut timeouts = 0;
+    let mut last = Instant::now();
+    let mut worst = Duration::ZERO;
+    loop {
+        loop {
+            match rx.try_recv() {
+                Ok(Cmd::Run(mut f)) => f(&mut state),
+                Ok(Cmd::Stop) | Err(TryRecvError::Disconnected) => return Ok(state),
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if state.device_lost() {
+            eprintln!("Rebuilding the renderer after device loss.");
+            let window = state.window.clone();
+            let effect = state.renderer.effect().name;
+            // 古いデバイスとサーフェスを先に解放する
+            drop(state);
+            // 作り直せなければスレッドを終え、App に終了を任せる
+            state = rebuild(window, effect)?;
+        }
+        // 最小化中（幅か高さが 0）は描かずに待つ
+        let size = state.window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+        if let Some(hook) = on_frame.as_mut() {
+            let frame = state.renderer.frame;
+            hook(&mut state, frame);
+        }
+        match state.render() {
+            Ok(()) => timeouts = 0,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                let (w, h) = (state.config.width, state.config.height);
+                state.resize(w, h);
+                continue;
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                timeouts += 1;
+                if timeouts >= timeout_limit {
+                    eprintln!("Warning: {timeouts} consecutive surface timeouts; reconfiguring.");
+                    timeouts = 0;
+                    let (w, h) = (state.config.width, state.config.height);
+                    state.resize(w, h);
+                }
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Surface error on render thread: {e:?}");
+                // 最小化中などで取得できない間は空回りしない
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+        }
+        let now = Instant::now();
+        let dt = now - last;
+        last = now;
+        worst = worst.max(dt);
+        metrics::record(&mut log, now, dt);
+        if fps.tick(dt) {
+            let f = fps.current().unwrap_or(0.0);
+            let gpu = state
+                .gpu_ms()
+                .map_or(String::new(), |ms| format!("  |  GPU {ms:.2} ms"));
+            state
+                .window
+                .set_title(&format!("Swarm Wallpaper  |  {f:.1} FPS{gpu}"));
+            if verbose {
+                eprintln!(
+                    "{f:.1} FPS  worst {:.2} ms  (render thread)",
+                    worst.as_secs_f64() * 1000.0
+                );
+            }
+            worst = Duration::ZERO;
+        }
+    }
+}
@@ -0,0 +1,2644 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::config::{Config, NoiseKind};
+use crate::effects::{self, Effect};
+use crate::export::ExportError;
+use crate::gputime::GpuTimer;
+use crate::palette::{self, MAX_STOPS, Palette};
+use crate::png;
+use crate::swarm::{self, Swarm};
+
+// サーフェスに依存しない描画部分。出力先の TextureView を渡して描く
+pub struct Renderer {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    effect: &'static Effect,
+    scene: Scene,
+    // --watch で読み直したエフェクトのパイプライン（名前ごと）。組み込みより優先する
+    reloaded: std::collections::HashMap<&'static str, wgpu::RenderPipeline>,
+    // エフェクトを切り替えて群れを作り直すときに使う
+    swarm_settings: swarm::Settings,
+    params_buf: wgpu::Buffer,
+    params_bg: wgpu::BindGroup,
+    params: Params,
+    params_dirty: bool,
+    frame_buf: wgpu::Buffer,
+    frame_bg: wgpu::BindGroup,
+    post_pipeline: wgpu::RenderPipeline,
+    post_bgl: wgpu::BindGroupLayout,
+    // 背景画像（group 1）。指定がなければ 1x1 の黒
+    background_bgl: wgpu::BindGroupLayout,
+    background_bg: wgpu::BindGroup,
+    post_buf: wgpu::Buffer,
+    post: PostParams,
+    sampler: wgpu::Sampler,
+    smooth_pipeline: wgpu::RenderPipeline,
+    smooth_buf: wgpu::Buffer,
+    smooth: f32,
+    // 残像の減衰率。smooth の履歴を使い、有効なら smooth より優先
+    trail: f32,
+    // 時間方向のアンチエイリアス。smooth の履歴を使い、有効なら smooth・trail より優先
+    taa: bool,
+    // --overlay: 暗いところを透明にして出す（乗算済みアルファ）。書き出しは常に不透明
+    transparent: bool,
+    motion_pipeline: wgpu::RenderPipeline,
+    motion_buf: wgpu::Buffer,
+    motion: f32,
+    targets: Targets,
+    // 出力（サーフェス）の解像度と、シーンを描く解像度の比
+    output_size: [u32; 2],
+    render_scale: f32,
+    readback: Option<Readback>,
+    // queue_read_rgba8 で始めて、まだ受け取っていない読み戻し（古い順）
+    pending_reads: std::collections::VecDeque<PendingRead>,
+    // history のうち最新のもの。history_valid が偽なら中身は未初期化
+    history_cur: usize,
+    history_valid: bool,
+    // 1つ前の history も有効か（動き検出に使う）
+    prev_valid: bool,
+    pub frame: u32,
+    pub frozen: bool,
+    // 1回の描画で進めるフレーム数（--fps-divisor で間引いても動きの速さを保つ）
+    frame_step: u32,
+    speed: f32,
+    time_offset: f32,
+    // 窓に描くときの実時間（秒、State が毎フレーム設定）。None ならフレーム番号から換算
+    elapsed: Option<f32>,
+    // 前回の描画のアニメーション時間（シミュレーションの dt 用）
+    last_time: f32,
+    capture_pending: bool,
+    // ユニフォームへの書き込み量（累計バイト）
+    pub uniform_bytes: u64,
+}
+
+// シーンの描き方（effects::Kind に対応）
+enum Scene {
+    Fullscreen(wgpu::RenderPipeline),
+    Swarm(Box<Swarm>),
+}
+
+const SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+// めったに変わらない値。変更時だけ書き込む (group 0)
+struct Params {
+    size: [f32; 2],
+    time_scale: [f32; 2],
+    // 表示先モニターのリフレッシュレート。不明なら 60
+    refresh_hz: f32,
+    // starfield 用
+    star_density: f32,
+    star_speed: f32,
+    // matrix 用
+    rain_speed: f32,
+    rain_density: f32,
+    // gradient 用。角度はラジアン
+    gradient_angle: f32,
+    gradient_radial: u32,
+    // swarm 用。0 ならぼかした点、それ以外は縁をこの幅で滑らかにした円
+    particle_edge: f32,
+    // カーソル位置（UV）と、それに反応する強さ
+    mouse: [f32; 2],
+    mouse_force: f32,
+    // noise の種類（config::NoiseKind）
+    noise_kind: u32,
+    // 模様の乱数の種（--seed）。起動時に決めてリサイズやエフェクト切り替えでは変えない
+    seed: u32,
+    // 音の強さ 0..1（bass, mid, treble）。--audio-pcm がなければ 0
+    audio: [f32; 3],
+    // ウィンドウの DPI 倍率（物理ピクセル / 論理ピクセル）。窓のないときは 1
+    scale_factor: f32,
+    // swarm の端の扱い（config::Edge）
+    edge: u32,
+    _pad: [f32; 2],
+}
+
+pub const DEFAULT_REFRESH_HZ: f32 = 60.0;
+
+// 毎フレーム書き込む値 (group 1)
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct FrameParams {
+    frame: u32,
+    // アニメーション時間（秒）。speed を掛けたもの
+    time: f32,
+    // TAA のサブピクセルずらし（ピクセル）
+    jitter: [f32; 2],
+    // 前フレームからのアニメーション時間の差（秒、MAX_DT まで）
+    dt: f32,
+    _pad: [f32; 3],
+}
+
+// ポーズ明けやスリープ復帰で dt が跳ねても動きが暴れないようにする上限（秒）
+const MAX_DT: f32 = 0.1;
+
+// 前回と今回のアニメーション時間から dt を出す。巻き戻り（seek など）は 0
+fn frame_dt(prev: f32, now: f32) -> f32 {
+    (now - prev).clamp(0.0, MAX_DT)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct PostParams {
+    invert: u32,
+    channel_mask: u32,
+    // 2 未満ならパレットを使わずグレースケールのまま
+    stop_count: u32,
+    // 万華鏡の分割数。2 未満なら無効
+    segments: u32,
+    // 線形 RGB。輝度 0..1 を等間隔に割り当てる
+    stops: [[f32; 4]; MAX_STOPS],
+    // 黒の部分に敷く色（スクリーン合成）。黒なら変化なし
+    base: [f32; 4],
+    // シーンを読む UV の 2x2 行列（列優先）と平行移動。画面中心まわりに適用する
+    uv_mat: [f32; 4],
+    uv_offset: [f32; 4],
+    // 万華鏡の中心（UV）
+    center: [f32; 4],
+    // x: 重ねるエフェクトの不透明度, y: 背景画像があれば 1, zw: 出力の解像度
+    background: [f32; 4],
+}
+
+// [a, b, c, d, tx, ty]: x' = a*x + b*y + tx, y' = c*x + d*y + ty
+pub const UV_IDENTITY: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+impl PostParams {
+    fn set_uv_transform(&mut self, [a, b, c, d, tx, ty]: [f32; 6]) {
+        self.uv_mat = [a, c, b, d];
+        self.uv_offset = [tx, ty, 0.0, 0.0];
+    }
+}
+
+// 前フレームとの混合率 (0 = 混ぜない)
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SmoothParams {
+    factor: f32,
+    // 0 以外なら前フレームを近傍の範囲に収める（TAA）
+    clamp: u32,
+    // 0 より大きければ混ぜる代わりに、前フレームにこれを掛けて足す（残像）
+    trail: f32,
+    _pad: f32,
+}
+
+// TAA で前フレームに掛ける重み。大きいほどちらつきは減るが変化への追従が遅れる
+const TAA_HISTORY: f32 = 0.9;
+// ずらし方の周期。Halton(2, 3) 列の最初の8点を繰り返す
+const TAA_SAMPLES: u32 = 8;
+
+// -0.5..0.5 ピクセルのずらし
+fn taa_jitter(frame: u32) -> [f32; 2] {
+    let i = frame % TAA_SAMPLES + 1;
+    [halton(i, 2) - 0.5, halton(i, 3) - 0.5]
+}
+
+fn halton(mut i: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut r = 0.0;
+    while i > 0 {
+        f /= base as f32;
+        r += f * (i % base) as f32;
+        i /= base;
+    }
+    r
+}
+
+// 前フレームとの差分で明るくする強さ (0 = 無効)
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct MotionParams {
+    boost: f32,
+    _pad: [f32; 3],
+}
+
+// 解像度に依存するテクスチャと、それを参照するバインドグループ。リサイズ時に作り直す
+struct Targets {
+    scene: wgpu::TextureView,
+    history: [wgpu::TextureView; 2],
+    // scene → 出力
+    post_bg: wgpu::BindGroup,
+    // history[i] → 出力
+    post_hist_bg: [wgpu::BindGroup; 2],
+    // scene と history[i] → history[1 - i]
+    smooth_bg: [wgpu::BindGroup; 2],
+    // history[i] と history[1 - i] の差分 → motion
+    motion: wgpu::TextureView,
+    motion_bg: [wgpu::BindGroup; 2],
+    // motion → 出力
+    post_motion_bg: wgpu::BindGroup,
+}
+
+// ユニフォームは16バイト単位。フィールドを足してパディングを直し忘れたらコンパイルエラーにする
+const _: () = {
+    assert!(size_of::<Params>().is_multiple_of(16));
+    assert!(size_of::<FrameParams>().is_multiple_of(16));
+    assert!(size_of::<PostParams>().is_multiple_of(16));
+    assert!(size_of::<SmoothParams>().is_multiple_of(16));
+    assert!(size_of::<MotionParams>().is_multiple_of(16));
+};
+
+impl Renderer {
+    // device / queue は呼び出し側のもの。format は render_to_view に渡す view の形式
+    pub fn new(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        cfg: &Config,
+    ) -> Self {
+        let render_scale = cfg.render_scale.unwrap_or(1.0).clamp(0.1, 1.0);
+        let (sw, sh) = scaled(width, height, render_scale);
+        let params = Params {
+            size: [sw as f32, sh as f32],
+            time_scale: cfg.time_scale,
+            refresh_hz: DEFAULT_REFRESH_HZ,
+            star_density: cfg.star_density.unwrap_or(0.5),
+            star_speed: cfg.star_speed.unwrap_or(1.0),
+            rain_speed: cfg.rain_speed.unwrap_or(1.0),
+            rain_density: cfg.rain_density.unwrap_or(0.7),
+            gradient_angle: cfg.gradient_angle.to_radians(),
+            gradient_radial: cfg.gradient_radial as u32,
+            particle_edge: cfg.particle_edge.max(0.0),
+            mouse: [0.5, 0.5],
+            mouse_force: cfg.mouse_force,
+            noise_kind: cfg.noise as u32,
+            seed: cfg.seed.unwrap_or(0),
+            audio: [0.0; 3],
+            scale_factor: 1.0,
+            edge: cfg.edge as u32,
+            _pad: [0.0; 2],
+        };
+        let params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let frame_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("frame"),
+            contents: bytemuck::bytes_of(&FrameParams {
+                frame: 0,
+                time: 0.0,
+                jitter: [0.0; 2],
+                dt: 0.0,
+                _pad: [0.0; 3],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let effect = cfg
+            .effect
+            .as_deref()
+            .and_then(effects::find)
+            .or_else(|| effects::find(effects::DEFAULT))
+            .unwrap();
+        let swarm_settings = swarm::Settings::from_config(cfg);
+        let (scene, params_bg, frame_bg) = create_effect(
+            &device,
+            effect,
+            [&params_buf, &frame_buf],
+            &swarm_settings,
+            [sw, sh],
+        );
+
+        // 全画面テクスチャパス共通: 0=入力, 1=サンプラ, 2=パスごとのユニフォーム, 3=履歴
+        let post_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    // post は頂点段でも UV 変換に使う
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("scene_samp"),
+            // UV 変換で 0..1 を出たところは鏡映しで繰り返す。端のフィルタは clamp と同じになる
+            address_mode_u: wgpu::AddressMode::MirrorRepeat,
+            address_mode_v: wgpu::AddressMode::MirrorRepeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let background_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("background_bgl"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let background = cfg.background.as_deref().and_then(|path| {
+            let max = device.limits().max_texture_dimension_2d;
+            match png::load(path) {
+                Ok((w, h, _)) if w > max || h > max => {
+                    eprintln!(
+                        "Background {} is larger than {max}px; ignoring it.",
+                        path.display()
+                    );
+                    None
+                }
+                Ok(img) => Some(img),
+                Err(e) => {
+                    eprintln!("Could not load background: {e}");
+                    None
+                }
+            }
+        });
+        let background_bg = create_background(
+            &device,
+            &queue,
+            &background_bgl,
+            background
+                .as_ref()
+                .map_or((1, 1, &[0, 0, 0, 255][..]), |(w, h, px)| (*w, *h, &px[..])),
+        );
+
+        let post_pipeline = create_post_pipeline(
+            &device,
+            "post",
+            &[&post_bgl, &background_bgl],
+            format,
+            cfg.overlay,
+        );
+        let smooth_pipeline = create_pass_pipeline(
+            &device,
+            "smooth",
+            SMOOTH_SRC,
+            "vs_main",
+            &[&post_bgl],
+            SCENE_FORMAT,
+        );
+        let motion_pipeline = create_pass_pipeline(
+            &device,
+            "motion",
+            MOTION_SRC,
+            "vs_main",
+            &[&post_bgl],
+            SCENE_FORMAT,
+        );
+
+        let [cx, cy] = cfg.kaleidoscope_center.unwrap_or([0.5, 0.5]);
+        let mut post = PostParams {
+            invert: cfg.invert as u32,
+            channel_mask: cfg.channel_mask,
+            stop_count: cfg.palette.stops.len() as u32,
+            segments: cfg.kaleidoscope,
+            stops: cfg.palette.linear(),
+            base: palette::linear_rgba(cfg.base_color),
+            uv_mat: [1.0, 0.0, 0.0, 1.0],
+            uv_offset: [0.0; 4],
+            center: [cx, cy, 0.0, 0.0],
+            background: [
+                cfg.overlay_opacity.unwrap_or(1.0).clamp(0.0, 1.0),
+                background.is_some() as u32 as f32,
+                width as f32,
+                height as f32,
+            ],
+        };
+        post.set_uv_transform(cfg.uv_transform.unwrap_or(UV_IDENTITY));
+        let post_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("post"),
+            contents: bytemuck::bytes_of(&post),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let smooth = cfg.smooth.clamp(0.0, 1.0);
+        let smooth_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("smooth"),
+            contents: bytemuck::bytes_of(&SmoothParams {
+                factor: smooth,
+                clamp: 0,
+                trail: 0.0,
+                _pad: 0.0,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let motion = cfg.motion_boost.max(0.0);
+        let motion_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("motion"),
+            contents: bytemuck::bytes_of(&MotionParams {
+                boost: motion,
+                _pad: [0.0; 3],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let targets = create_targets(
+            &device,
+            &post_bgl,
+            &sampler,
+            [&post_buf, &smooth_buf, &motion_buf],
+            sw,
+            sh,
+        );
+
+        Self {
+            device,
+            queue,
+            effect,
+            scene,
+            swarm_settings,
+            params_buf,
+            params_bg,
+            params,
+            params_dirty: false,
+            frame_buf,
+            frame_bg,
+            post_pipeline,
+            post_bgl,
+            background_bgl,
+            background_bg,
+            post_buf,
+            post,
+            sampler,
+            smooth_pipeline,
+            smooth_buf,
+            smooth,
+            trail: cfg.trail_decay.clamp(0.0, 1.0),
+            taa: cfg.taa,
+            transparent: cfg.overlay,
+            motion_pipeline,
+            motion_buf,
+            motion,
+            targets,
+            output_size: [width, height],
+            render_scale,
+            readback: None,
+            pending_reads: Default::default(),
+            reloaded: Default::default(),
+            history_cur: 0,
+            history_valid: false,
+            prev_valid: false,
+            frame: 0,
+            frozen: false,
+            // 描画スレッドは間引かないので 1 フレームずつ
+            frame_step: if cfg.render_thread {
+                1
+            } else {
+                cfg.fps_divisor.unwrap_or(1).max(1)
+            },
+            speed: cfg.speed.unwrap_or(1.0),
+            time_offset: 0.0,
+            elapsed: None,
+            last_time: 0.0,
+            capture_pending: true,
+            uniform_bytes: 0,
+        }
+    }
+
+    // 出力先フォーマットが変わったときは最終段のパイプラインだけ作り直す
+    pub fn set_target_format(&mut self, format: wgpu::TextureFormat) {
+        self.post_pipeline = create_post_pipeline(
+            &self.device,
+            "post",
+            &[&self.post_bgl, &self.background_bgl],
+            format,
+            self.transparent,
+        );
+    }
+
+    // 静止スナップショットの切り替え。凍結時は最後のシーンをそのまま表示する
+    pub fn toggle_frozen(&mut self) {
+        self.frozen = !self.frozen;
+        self.capture_pending = true;
+    }
+
+    pub fn recapture(&mut self) {
+        self.capture_pending = true;
+    }
+
+    pub fn toggle_invert(&mut self) {
+        self.post.invert ^= 1;
+        self.write_post();
+    }
+
+    // bit: 0=R, 1=G, 2=B
+    pub fn toggle_channel(&mut self, bit: u32) {
+        self.post.channel_mask ^= 1 << bit;
+        self.write_post();
+    }
+
+    // gradient の向きを度単位で回す。放射状なら直線に戻す
+    pub fn rotate_gradient(&mut self, degrees: f32) {
+        let a = self.params.gradient_angle.to_degrees() + degrees;
+        self.params.gradient_angle = a.rem_euclid(360.0).to_radians();
+        self.params.gradient_radial = 0;
+        self.params_dirty = true;
+    }
+
+    pub fn refresh_hz(&self) -> f32 {
+        self.params.refresh_hz
+    }
+
+    pub fn set_refresh_hz(&mut self, hz: f32) {
+        if hz != self.params.refresh_hz {
+            self.keep_time_continuous(|r| r.params.refresh_hz = hz);
+            self.params_dirty = true;
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.keep_time_continuous(|r| r.speed = speed);
+    }
+
+    // 起動からの実時間を与えると、描画間隔によらずその時刻の絵になる
+    pub fn set_elapsed(&mut self, secs: f32) {
+        self.elapsed = Some(secs);
+    }
+
+    // アニメーション時間 = time_offset + speed * 経過時間。
+    // 経過時間は実時間、なければ frame / refresh_hz（書き出しなど、描画間隔を決め打ちできるとき）
+    fn anim_time(&self) -> f32 {
+        let base = self
+            .elapsed
+            .unwrap_or(self.frame as f32 / self.params.refresh_hz);
+        self.time_offset + self.speed * base
+    }
+
+    // speed やリフレッシュレートを変えても今の時刻から続くように time_offset を合わせ直す
+    fn keep_time_continuous(&mut self, change: impl FnOnce(&mut Self)) {
+        let now = self.anim_time();
+        change(self);
+        self.time_offset += now - self.anim_time();
+    }
+
+    // 外部入力（パイプ等）から名前で値を設定する。真偽値は 0 以外を真とみなす
+    pub fn set_param(&mut self, name: &str, v: f32) -> Result<(), String> {
+        match name {
+            "time_scale.x" => self.params.time_scale[0] = v,
+            "time_scale.y" => self.params.time_scale[1] = v,
+            "speed" => {
+                self.set_speed(v);
+                return Ok(());
+            }
+            "star_density" => self.params.star_density = v.clamp(0.0, 1.0),
+            "star_speed" => self.params.star_speed = v,
+            "rain_speed" => self.params.rain_speed = v,
+            "rain_density" => self.params.rain_density = v.clamp(0.0, 1.0),
+            "gradient_angle" => self.params.gradient_angle = v.to_radians(),
+            "gradient_radial" => self.params.gradient_radial = (v != 0.0) as u32,
+            "particle_edge" => self.params.particle_edge = v.max(0.0),
+            "invert" => {
+                self.post.invert = (v != 0.0) as u32;
+                self.write_post();
+                return Ok(());
+            }
+            "channel_mask" => {
+                self.post.channel_mask = v as u32 & 0b111;
+                self.write_post();
+                return Ok(());
+            }
+            "kaleidoscope" => {
+                self.post.segments = v.max(0.0) as u32;
+                self.write_post();
+                return Ok(());
+            }
+            "smooth" => {
+                self.set_smooth(v);
+                return Ok(());
+            }
+            "trail_decay" => {
+                self.set_trail_decay(v);
+                return Ok(());
+            }
+            "mouse_force" => self.params.mouse_force = v,
+            "bass" => self.params.audio[0] = v.clamp(0.0, 1.0),
+            "mid" => self.params.audio[1] = v.clamp(0.0, 1.0),
+            "treble" => self.params.audio[2] = v.clamp(0.0, 1.0),
+            "overlay_opacity" => {
+                self.post.background[0] = v.clamp(0.0, 1.0);
+                self.write_post();
+                return Ok(());
+            }
+            "motion_boost" => {
+                self.set_motion_boost(v);
+                return Ok(());
+            }
+            _ => return Err(format!("unknown parameter `{name}`")),
+        }
+        self.params_dirty = true;
+        Ok(())
+    }
+
+    // 前フレームとの混合率。0 で従来どおり毎フレームそのまま表示
+    pub fn set_smooth(&mut self, v: f32) {
+        self.smooth = v.clamp(0.0, 1.0);
+        self.history_valid = false;
+        self.prev_valid = false;
+    }
+
+    // 残像の減衰率。0 で無効、1 に近いほど尾が長い
+    pub fn set_trail_decay(&mut self, v: f32) {
+        self.trail = v.clamp(0.0, 1.0);
+        self.history_valid = false;
+        self.prev_valid = false;
+    }
+
+    // 前フレームから変化した画素を明るくする強さ。0 で無効
+    pub fn set_motion_boost(&mut self, v: f32) {
+        self.motion = v.max(0.0);
+        self.queue.write_buffer(
+            &self.motion_buf,
+            0,
+            bytemuck::bytes_of(&MotionParams {
+                boost: self.motion,
+                _pad: [0.0; 3],
+            }),
+        );
+        self.history_valid = false;
+        self.prev_valid = false;
+    }
+
+    // smooth・trail・motion_boost・TAA のどれかが有効なら history を毎フレーム更新する
+    fn keeps_history(&self) -> bool {
+        self.smooth > 0.0 || self.trail > 0.0 || self.motion > 0.0 || self.taa
+    }
+
+    // カーソル位置を UV（0..1、Y下向き）で渡す。次の描画で Params に書き込まれ、
+    // リサイズやエフェクトの切り替えでは変わらない
+    pub fn set_mouse(&mut self, u: f32, v: f32) {
+        self.params.mouse = [u.clamp(0.0, 1.0), v.clamp(0.0, 1.0)];
+        self.params_dirty = true;
+    }
+
+    // 不具合報告用に今の設定とユニフォームをまとめて書き出す
+    pub fn dump(&self) -> String {
+        let particles = match &self.scene {
+            Scene::Swarm(swarm) => swarm.agent_count,
+            Scene::Fullscreen(_) => 0,
+        };
+        format!(
+            "effect: {} ({})\n\
+             frame: {}  time: {:.3}  speed: {}  frozen: {}\n\
+             render_scale: {}  smooth: {}  trail_decay: {}  taa: {}  motion_boost: {}\n\
+             particles: {}\n\
+             params: {:#?}\n\
+             post: {:#?}",
+            effects::index_of(self.effect),
+            self.effect.name,
+            self.frame,
+            self.last_time,
+            self.speed,
+            self.frozen,
+            self.render_scale,
+            self.smooth,
+            self.trail,
+            self.taa,
+            self.motion,
+            particles,
+            self.params,
+            self.post,
+        )
+    }
+
+    pub fn effect(&self) -> &'static Effect {
+        self.effect
+    }
+
+    // 描くエフェクトを切り替え、そのエフェクトのパラメータを既定値に戻す。
+    // アダプタが対応しているかは呼び出し側（State::set_effect）で確かめる
+    pub fn set_effect(&mut self, effect: &'static Effect) {
+        let [w, h] = self.params.size;
+        (self.scene, self.params_bg, self.frame_bg) = create_effect(
+            &self.device,
+            effect,
+            [&self.params_buf, &self.frame_buf],
+            &self.swarm_settings,
+            [w as u32, h as u32],
+        );
+        if let Some(pipeline) = self.reloaded.get(effect.name) {
+            self.scene = Scene::Fullscreen(pipeline.clone());
+        }
+        self.effect = effect;
+        for &(name, v) in effect.defaults {
+            // 既定値の名前は effects.rs で決めたものなので失敗しない
+            let _ = self.set_param(name, v);
+        }
+        self.history_valid = false;
+        self.prev_valid = false;
+        self.capture_pending = true;
+    }
+
+    // 全画面エフェクトのシェーダーを差し替える（--watch）。source は fs_main を含む本体だけで、
+    // 共通部分は前に付ける。そのエフェクトのパイプラインだけを作り直し、描画中なら
+    // すぐに、そうでなければ次に選ばれたときに使う。失敗したら前のパイプラインのまま
+    pub fn reload_shader(&mut self, effect: &'static Effect, source: &str) -> Result<(), String> {
+        if effect.kind != effects::Kind::Fullscreen {
+            return Err(format!("{} is not a fullscreen effect", effect.name));
+        }
+        let src = format!("{}{source}", effects::SCENE_PRELUDE);
+        // 検証エラーでパニックさせず、メッセージとして受け取る
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let bgl = scene_bgl(&self.device, effect.kind);
+        let pipeline = create_pass_pipeline(
+            &self.device,
+            effect.name,
+            &src,
+            "vs_scene",
+            &[&bgl, &bgl],
+            SCENE_FORMAT,
+        );
+        if let Some(e) = pollster::block_on(self.device.pop_error_scope()) {
+            return Err(e.to_string());
+        }
+        if effect.name == self.effect.name {
+            self.scene = Scene::Fullscreen(pipeline.clone());
+            self.capture_pending = true;
+        }
+        self.reloaded.insert(effect.name, pipeline);
+        Ok(())
+    }
+
+    pub fn set_scale_factor(&mut self, f: f32) {
+        if f > 0.0 && f != self.params.scale_factor {
+            self.params.scale_factor = f;
+            self.params_dirty = true;
+        }
+    }
+
+    pub fn set_noise(&mut self, kind: NoiseKind) {
+        self.params.noise_kind = kind as u32;
+        self.params_dirty = true;
+    }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.post.stop_count = palette.stops.len() as u32;
+        self.post.stops = palette.linear();
+        self.write_post();
+    }
+
+    // K で順に切り替える万華鏡の分割数
+    pub fn cycle_kaleidoscope(&mut self) {
+        const STEPS: [u32; 6] = [0, 3, 4, 6, 8, 12];
+        let i = STEPS.iter().position(|&n| n == self.post.segments);
+        self.post.segments = STEPS[i.map_or(0, |i| (i + 1) % STEPS.len())];
+        self.write_post();
+    }
+
+    pub fn set_base_color(&mut self, c: [u8; 3]) {
+        self.post.base = palette::linear_rgba(c);
+        self.write_post();
+    }
+
+    fn write_post(&self) {
+        self.queue
+            .write_buffer(&self.post_buf, 0, bytemuck::bytes_of(&self.post));
+    }
+
+    pub fn output_size(&self) -> [u32; 2] {
+        self.output_size
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    // シーンを出力より低い解像度で描き、最終段で拡大する
+    pub fn set_render_scale(&mut self, scale: f32) {
+        let scale = scale.clamp(0.1, 1.0);
+        if scale != self.render_scale {
+            self.render_scale = scale;
+            let [w, h] = self.output_size;
+            self.resize(w, h);
+        }
+    }
+
+    pub fn resize(&mut self, w: u32, h: u32) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        self.output_size = [w, h];
+        self.post.background[2..].copy_from_slice(&[w as f32, h as f32]);
+        self.write_post();
+        let (w, h) = scaled(w, h, self.render_scale);
+        self.targets = create_targets(
+            &self.device,
+            &self.post_bgl,
+            &self.sampler,
+            [&self.post_buf, &self.smooth_buf, &self.motion_buf],
+            w,
+            h,
+        );
+        if let Scene::Swarm(swarm) = &mut self.scene {
+            swarm.resize(&self.device, &self.queue, w, h);
+        }
+        self.history_valid = false;
+        self.prev_valid = false;
+        self.capture_pending = true;
+
+        self.params.size = [w as f32, h as f32];
+        self.params_dirty = true;
+    }
+
+    // フレームを1つ進め、シーンと最終段を view に描いて submit する
+    pub fn render_to_view(&mut self, view: &wgpu::TextureView) {
+        self.render_timed(view, None);
+    }
+
+    // render_to_view と同じ。timer があればシーンの最初のパスから最終段の終わりまでを測る
+    pub fn render_timed(&mut self, view: &wgpu::TextureView, timer: Option<&GpuTimer>) {
+        self.frame = self.frame.wrapping_add(self.frame_step);
+
+        let time = self.anim_time();
+        let dt = frame_dt(self.last_time, time);
+        self.last_time = time;
+        let fp = FrameParams {
+            frame: self.frame,
+            time,
+            jitter: if self.taa {
+                taa_jitter(self.frame)
+            } else {
+                [0.0; 2]
+            },
+            dt,
+            _pad: [0.0; 3],
+        };
+        self.queue
+            .write_buffer(&self.frame_buf, 0, bytemuck::bytes_of(&fp));
+        self.uniform_bytes += size_of::<FrameParams>() as u64;
+        if self.params_dirty {
+            self.queue
+                .write_buffer(&self.params_buf, 0, bytemuck::bytes_of(&self.params));
+            self.uniform_bytes += size_of::<Params>() as u64;
+            self.params_dirty = false;
+        }
+
+        let scene_updated = !self.frozen || self.capture_pending;
+        let smoothing = self.keeps_history();
+        if smoothing && scene_updated {
+            // 履歴が無効な最初のフレームは混ぜずにそのまま書き込む
+            let weight = if self.taa { TAA_HISTORY } else { self.smooth };
+            let trail = if self.taa { 0.0 } else { self.trail };
+            let sp = SmoothParams {
+                factor: if self.history_valid { weight } else { 0.0 },
+                clamp: self.taa as u32,
+                trail: if self.history_valid { trail } else { 0.0 },
+                _pad: 0.0,
+            };
+            self.queue
+                .write_buffer(&self.smooth_buf, 0, bytemuck::bytes_of(&sp));
+            self.uniform_bytes += size_of::<SmoothParams>() as u64;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("encoder"),
+            });
+        if scene_updated {
+            match &mut self.scene {
+                Scene::Swarm(swarm) => swarm.encode(
+                    &mut encoder,
+                    &self.queue,
+                    [&self.params_bg, &self.frame_bg],
+                    &self.targets.scene,
+                    [fp.time, dt],
+                    timer,
+                ),
+                Scene::Fullscreen(pipeline) => {
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("noise"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &self.targets.scene,
+                            depth_slice: None,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: timer.map(|t| t.render_writes(true, false)),
+                        occlusion_query_set: None,
+                    });
+                    rpass.set_pipeline(pipeline);
+                    rpass.set_bind_group(0, &self.params_bg, &[]);
+                    rpass.set_bind_group(1, &self.frame_bg, &[]);
+                    rpass.draw(0..3, 0..1);
+                }
+            }
+            self.capture_pending = false;
+        }
+        if smoothing && scene_updated {
+            let prev = self.history_cur;
+            let next = 1 - prev;
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("smooth"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.targets.history[next],
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(&self.smooth_pipeline);
+            rpass.set_bind_group(0, &self.targets.smooth_bg[prev], &[]);
+            rpass.draw(0..3, 0..1);
+            self.history_cur = next;
+            self.prev_valid = self.history_valid;
+            self.history_valid = true;
+        }
+        if self.motion > 0.0 && self.prev_valid {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("motion"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.targets.motion,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(&self.motion_pipeline);
+            rpass.set_bind_group(0, &self.targets.motion_bg[self.history_cur], &[]);
+            rpass.draw(0..3, 0..1);
+        }
+        // シーンを描かないフレームは最終段だけを測る
+        let post_writes = timer.map(|t| t.render_writes(!scene_updated, true));
+        self.encode_post(&mut encoder, view, &self.post_pipeline, post_writes);
+        if let Some(t) = timer {
+            t.resolve(&mut encoder);
+        }
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    // 次の render_to_view がフレーム n を描くように進める（表示はしない）。
+    // 前フレームの履歴や群れの状態を使わない設定なら描画を省いて番号だけ合わせる
+    pub fn seek(&mut self, view: &wgpu::TextureView, n: u32) {
+        let target = n.saturating_sub(1);
+        if self.keeps_history() || matches!(self.scene, Scene::Swarm(_)) {
+            while self.frame < target {
+                self.render_to_view(view);
+            }
+        } else {
+            self.frame = target;
+        }
+    }
+
+    // 最終段（後処理）だけを view に描く。シーンと履歴は直前の render_to_view のもの
+    fn encode_post(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        pipeline: &wgpu::RenderPipeline,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+    ) {
+        let post_bg = if self.motion > 0.0 && self.prev_valid {
+            &self.targets.post_motion_bg
+        } else if self.keeps_history() && self.history_valid {
+            &self.targets.post_hist_bg[self.history_cur]
+        } else {
+            &self.targets.post_bg
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("post"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, post_bg, &[]);
+        rpass.set_bind_group(1, &self.background_bg, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    // 直前のフレームを出力解像度の RGBA8 (sRGB) で読み戻す
+    pub fn read_rgba8(&mut self) -> Result<Vec<u8>, ExportError> {
+        self.read_pixels(READBACK_FORMAT)
+    }
+
+    // 同じく RGBA16 で読み戻す。値は sRGB 符号化済みのビッグエンディアン（16bit PNG 用）
+    pub fn read_rgba16(&mut self) -> Result<Vec<u8>, ExportError> {
+        let raw = self.read_pixels(wgpu::TextureFormat::Rgba16Float)?;
+        Ok(raw
+            .chunks_exact(2)
+            .flat_map(|b| {
+                let v = png::f16_to_f32(u16::from_le_bytes([b[0], b[1]]));
+                png::linear_to_srgb16(v).to_be_bytes()
+            })
+            .collect())
+    }
+
+    // 直前のフレームの RGBA8 読み戻しを始めるだけで待たない。結果は pop_read で古い順に受け取る。
+    // 待っている数だけステージングバッファを持つので、呼ぶ側で reads_in_flight を見て抑える
+    pub fn queue_read_rgba8(&mut self) {
+        let read = self.start_read(READBACK_FORMAT);
+        self.pending_reads.push_back(read);
+    }
+
+    pub fn reads_in_flight(&self) -> usize {
+        self.pending_reads.len()
+    }
+
+    // いちばん古い読み戻しの完了を待って返す。待ちがなければ None
+    pub fn pop_read(&mut self) -> Result<Option<Vec<u8>>, ExportError> {
+        match self.pending_reads.pop_front() {
+            Some(read) => self.finish_read(read).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // 最終段を format のテクスチャに描き直して読み戻す。行の詰め物は取り除いて返す
+    fn read_pixels(&mut self, format: wgpu::TextureFormat) -> Result<Vec<u8>, ExportError> {
+        let read = self.start_read(format);
+        self.finish_read(read)
+    }
+
+    fn start_read(&mut self, format: wgpu::TextureFormat) -> PendingRead {
+        let [w, h] = self.output_size;
+        if self
+            .readback
+            .as_ref()
+            .is_none_or(|r| r.size != [w, h] || r.format != format)
+        {
+            self.readback = Some(Readback::new(
+                &self.device,
+                &[&self.post_bgl, &self.background_bgl],
+                w,
+                h,
+                format,
+            ));
+        }
+        let rb = self.readback.as_mut().unwrap();
+        let buffer = rb.take_buffer(&self.device);
+        let rb = self.readback.as_ref().unwrap();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("readback"),
+            });
+        self.encode_post(&mut encoder, &rb.view, &rb.pipeline, None);
+        encoder.copy_texture_to_buffer(
+            rb.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(rb.padded_row),
+                    rows_per_image: Some(h),
+                },
+            },
+            wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+        );
+        let submission = self.queue.submit(Some(encoder.finish()));
+
+        let (tx, mapped) = std::sync::mpsc::channel();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+        PendingRead {
+            buffer,
+            submission,
+            mapped,
+            size: [w, h],
+            format,
+            bytes_per_pixel: rb.bytes_per_pixel,
+            padded_row: rb.padded_row,
+        }
+    }
+
+    fn finish_read(&mut self, read: PendingRead) -> Result<Vec<u8>, ExportError> {
+        // このコピーが終わるまで待つ。後から積んだフレームは GPU で進めたままにする
+        self.device
+            .poll(wgpu::PollType::WaitForSubmissionIndex(read.submission))?;
+        read.mapped.recv().map_err(|e| ExportError::Readback {
+            op: "map",
+            reason: e.to_string(),
+        })??;
+
+        let [w, h] = read.size;
+        let row = (w * read.bytes_per_pixel) as usize;
+        let mut out = Vec::with_capacity(row * h as usize);
+        {
+            let data = read.buffer.slice(..).get_mapped_range();
+            for y in 0..h as usize {
+                let start = y * read.padded_row as usize;
+                out.extend_from_slice(&data[start..start + row]);
+            }
+        }
+        read.buffer.unmap();
+        // 読み戻し先が作り直されていなければバッファを使い回す
+        if let Some(rb) = self.readback.as_mut()
+            && rb.size == read.size
+            && rb.format == read.format
+        {
+            rb.spare.push(read.buffer);
+        }
+        Ok(out)
+    }
+}
+
+// 読み戻し用のテクスチャとステージングバッファ。出力サイズが変わったら作り直す
+struct Readback {
+    size: [u32; 2],
+    format: wgpu::TextureFormat,
+    bytes_per_pixel: u32,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    pipeline: wgpu::RenderPipeline,
+    // 読み終わって空いたステージングバッファ。読み戻し待ちの間はそれぞれが1つ持つ
+    spare: Vec<wgpu::Buffer>,
+    // copy_texture_to_buffer は行を 256 バイト境界に揃える必要がある
+    padded_row: u32,
+}
+
+// map の終わりを待っている読み戻し
+struct PendingRead {
+    buffer: wgpu::Buffer,
+    submission: wgpu::SubmissionIndex,
+    mapped: std::sync::mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    size: [u32; 2],
+    format: wgpu::TextureFormat,
+    bytes_per_pixel: u32,
+    padded_row: u32,
+}
+
+const READBACK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+impl Readback {
+    fn new(
+        device: &wgpu::Device,
+        bgls: &[&wgpu::BindGroupLayout],
+        w: u32,
+        h: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let bytes_per_pixel = format.block_copy_size(None).unwrap_or(4);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("readback"),
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = (w * bytes_per_pixel).div_ceil(align) * align;
+        Self {
+            size: [w, h],
+            format,
+            bytes_per_pixel,
+            texture,
+            view,
+            pipeline: create_post_pipeline(device, "readback", bgls, format, false),
+            spare: Vec::new(),
+            padded_row,
+        }
+    }
+
+    fn take_buffer(&mut self, device: &wgpu::Device) -> wgpu::Buffer {
+        self.spare.pop().unwrap_or_else(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback"),
+                size: self.padded_row as u64 * self.size[1] as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        })
+    }
+}
+
+// 既定の制限が通らないアダプタ向けに、順に緩い制限で再試行する
+pub async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    let tiers = [
+        ("default", wgpu::Limits::default()),
+        ("downlevel", wgpu::Limits::downlevel_defaults()),
+        (
+            "downlevel_webgl2",
+            wgpu::Limits::downlevel_webgl2_defaults(),
+        ),
+    ];
+    let mut last_err = None;
+    for (name, limits) in tiers {
+        let desc = wgpu::DeviceDescriptor {
+            // MSAA の 2x/8x など、形式ごとの対応状況をアダプタに合わせる
+            // TIMESTAMP_QUERY は GPU 時間の計測用（gputime.rs）
+            required_features: adapter.features()
+                & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                    | wgpu::Features::TIMESTAMP_QUERY),
+            required_limits: limits.using_resolution(adapter.limits()),
+            ..Default::default()
+        };
+        match adapter.request_device(&desc).await {
+            Ok(dq) => {
+                if last_err.is_some() {
+                    eprintln!("Device created with {name} limits.");
+                }
+                return Ok(dq);
+            }
+            Err(e) => {
+                eprintln!("request_device with {name} limits failed: {e}");
+                last_err = Some(e);
+            }
+        }
+    }
+    Err(last_err.unwrap())
+}
+
+// "名前 (バックエンド, 種類)" の1行。--list-adapters と --adapter の候補表示に使う
+pub fn describe_adapter(adapter: &wgpu::Adapter) -> String {
+    let info = adapter.get_info();
+    format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+}
+
+// --list-adapters: instance から見えるアダプタをすべて並べる
+pub fn list_adapters() -> Vec<String> {
+    wgpu::Instance::default()
+        .enumerate_adapters(wgpu::Backends::all())
+        .iter()
+        .map(describe_adapter)
+        .collect()
+}
+
+// --adapter: 名前に name を含む（大文字小文字は区別しない）アダプタを選ぶ。
+// surface があればそれに描けるものに限る。見つからなければ候補を出して既定の選び方に任せる
+pub async fn find_adapter(
+    instance: &wgpu::Instance,
+    name: Option<&str>,
+    surface: Option<&wgpu::Surface<'_>>,
+) -> Result<wgpu::Adapter, wgpu::RequestAdapterError> {
+    if let Some(name) = name {
+        let wanted = name.to_lowercase();
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+        let usable = |a: &&wgpu::Adapter| surface.is_none_or(|s| a.is_surface_supported(s));
+        if let Some(a) = adapters
+            .iter()
+            .filter(usable)
+            .find(|a| a.get_info().name.to_lowercase().contains(&wanted))
+        {
+            return Ok(a.clone());
+        }
+        eprintln!("No adapter matching {name:?}; using the default. Available:");
+        for a in adapters.iter().filter(usable) {
+            eprintln!("  {}", describe_adapter(a));
+        }
+    }
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: surface,
+            ..Default::default()
+        })
+        .await
+}
+
+// --msaa をシーンの形式で使えるサンプル数に下げる。下げたら警告する
+pub fn supported_msaa(adapter: &wgpu::Adapter, device: &wgpu::Device, requested: u32) -> u32 {
+    if requested <= 1 {
+        return 1;
+    }
+    let features = device.features();
+    let format = if features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+        adapter.get_texture_format_features(SCENE_FORMAT)
+    } else {
+        SCENE_FORMAT.guaranteed_format_features(features)
+    };
+    let resolve = format
+        .flags
+        .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+    let samples = [8, 4, 2]
+        .into_iter()
+        .find(|&n| n <= requested && resolve && format.flags.sample_count_supported(n))
+        .unwrap_or(1);
+    if samples != requested {
+        eprintln!("MSAA x{requested} is not supported by this adapter; using x{samples}.");
+    }
+    samples
+}
+
+const POST_SRC: &str = concat!(
+    include_str!("shaders/fullscreen.wgsl"),
+    include_str!("shaders/post.wgsl")
+);
+const SMOOTH_SRC: &str = concat!(
+    include_str!("shaders/fullscreen.wgsl"),
+    include_str!("shaders/smooth.wgsl")
+);
+const MOTION_SRC: &str = concat!(
+    include_str!("shaders/fullscreen.wgsl"),
+    include_str!("shaders/motion.wgsl")
+);
+
+// エフェクトのパイプラインと、その種類に合わせた Params/Frame のバインドグループ
+fn create_effect(
+    device: &wgpu::Device,
+    effect: &Effect,
+    [params_buf, frame_buf]: [&wgpu::Buffer; 2],
+    swarm_settings: &swarm::Settings,
+    size: [u32; 2],
+) -> (Scene, wgpu::BindGroup, wgpu::BindGroup) {
+    let bgl = scene_bgl(device, effect.kind);
+    let bind = |label, buf: &wgpu::Buffer| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buf.as_entire_binding(),
+            }],
+        })
+    };
+    let params_bg = bind("bg", params_buf);
+    let frame_bg = bind("frame_bg", frame_buf);
+
+    let scene = match effect.kind {
+        effects::Kind::Fullscreen => Scene::Fullscreen(create_pass_pipeline(
+            device,
+            effect.name,
+            effect.source,
+            "vs_scene",
+            &[&bgl, &bgl],
+            SCENE_FORMAT,
+        )),
+        effects::Kind::Swarm => Scene::Swarm(Box::new(Swarm::new(
+            device,
+            &bgl,
+            effect.source,
+            SCENE_FORMAT,
+            swarm_settings,
+            size,
+        ))),
+    };
+    (scene, params_bg, frame_bg)
+}
+
+// Params/Frame 用のレイアウト。群れはコンピュートでも読む
+fn scene_bgl(device: &wgpu::Device, kind: effects::Kind) -> wgpu::BindGroupLayout {
+    let visibility = match kind {
+        effects::Kind::Fullscreen => wgpu::ShaderStages::VERTEX_FRAGMENT,
+        effects::Kind::Swarm => wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+    };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("bgl"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+// エフェクト以外の組み込みシェーダー一覧（--dry-run の検証用）
+pub const SHADERS: &[(&str, &str)] = &[
+    ("post", POST_SRC),
+    ("smooth", SMOOTH_SRC),
+    ("motion", MOTION_SRC),
+];
+
+// シェーダーは線形の値を出す。sRGB 形式ならGPUが変換するが、そうでない 8/10 ビットの形式は
+// 最終段で変換しないと中間調が暗くなる。浮動小数点の形式（HDR、16 ビットの読み戻し）は線形のまま
+pub fn encodes_srgb(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat as F;
+    !format.is_srgb() && !matches!(format, F::Rgba16Float | F::Rgba32Float | F::Rg11b10Ufloat)
+}
+
+// ログ用: format に描くとき sRGB への変換をどこで行うか
+pub fn srgb_path(format: wgpu::TextureFormat) -> &'static str {
+    if format.is_srgb() {
+        "sRGB encoded by the GPU"
+    } else if encodes_srgb(format) {
+        "sRGB encoded in the shader"
+    } else {
+        "linear output"
+    }
+}
+
+// 最終段のパイプライン。出力先の形式に合わせて sRGB 変換の有無を決める
+fn create_post_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    bgls: &[&wgpu::BindGroupLayout],
+    format: wgpu::TextureFormat,
+    transparent: bool,
+) -> wgpu::RenderPipeline {
+    let encode = encodes_srgb(format) as u32 as f64;
+    create_pipeline_with(
+        device,
+        label,
+        POST_SRC,
+        "vs_post",
+        bgls,
+        format,
+        &[
+            ("ENCODE_SRGB", encode),
+            ("TRANSPARENT", transparent as u32 as f64),
+        ],
+    )
+}
+
+// 全画面パスのパイプライン。vs は通常 fullscreen.wgsl の vs_main
+fn create_pass_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    src: &str,
+    vs: &str,
+    bgls: &[&wgpu::BindGroupLayout],
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    create_pipeline_with(device, label, src, vs, bgls, format, &[])
+}
+
+// constants はシェーダーの override に渡す値
+fn create_pipeline_with(
+    device: &wgpu::Device,
+    label: &str,
+    src: &str,
+    vs: &str,
+    bgls: &[&wgpu::BindGroupLayout],
+    format: wgpu::TextureFormat,
+    constants: &[(&str, f64)],
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(src.into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: bgls,
+                push_constant_ranges: &[],
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some(vs),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn create_scene(device: &wgpu::Device, w: u32, h: u32) -> wgpu::TextureView {
+    let tex = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("scene"),
+        size: wgpu::Extent3d {
+            width: w,
+            height: h,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: SCENE_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    tex.create_view(&Default::default())
+}
+
+fn scaled(w: u32, h: u32, scale: f32) -> (u32, u32) {
+    let f = |v: u32| ((v as f32 * scale).round() as u32).max(1);
+    (f(w), f(h))
+}
+
+// 背景画像のテクスチャ。sRGB として読ませ、シェーダーでは線形で合成する
+fn create_background(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    (w, h, rgba): (u32, u32, &[u8]),
+) -> wgpu::BindGroup {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("background"),
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        rgba,
+    );
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("background_bg"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&texture.create_view(&Default::default())),
+        }],
+    })
+}
+
+fn create_pass_bg(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    input: &wgpu::TextureView,
+    history: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    buf: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("pass_bg"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(input),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(history),
+            },
+        ],
+    })
+}
+
+fn create_targets(
+    device: &wgpu::Device,
+    bgl: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    // post, smooth, motion のユニフォーム
+    [post_buf, smooth_buf, motion_buf]: [&wgpu::Buffer; 3],
+    w: u32,
+    h: u32,
+) -> Targets {
+    let scene = create_scene(device, w, h);
+    let history = [create_scene(device, w, h), create_scene(device, w, h)];
+    let post_bg = create_pass_bg(device, bgl, &scene, &scene, sampler, post_buf);
+    let post_hist_bg =
+        [0, 1].map(|i| create_pass_bg(device, bgl, &history[i], &history[i], sampler, post_buf));
+    let smooth_bg =
+        [0, 1].map(|i| create_pass_bg(device, bgl, &scene, &history[i], sampler, smooth_buf));
+    let motion = create_scene(device, w, h);
+    let motion_bg = [0, 1].map(|i| {
+        create_pass_bg(
+            device,
+            bgl,
+            &history[i],
+            &history[1 - i],
+            sampler,
+            motion_buf,
+        )
+    });
+    let post_motion_bg = create_pass_bg(device, bgl, &motion, &motion, sampler, post_buf);
+    Targets {
+        scene,
+        history,
+        post_bg,
+        post_hist_bg,
+        smooth_bg,
+        motion,
+        motion_bg,
+        post_motion_bg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NoiseKind;
+
+    const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    // アダプタがない環境（CIなど）では None を返してテストをスキップする
+    fn headless(w: u32, h: u32) -> Option<Renderer> {
+        headless_with(w, h, &Config::default())
+    }
+
+    fn headless_with(w: u32, h: u32, cfg: &Config) -> Option<Renderer> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default())).ok()?;
+        let (device, queue) = pollster::block_on(request_device(&adapter)).ok()?;
+        Some(Renderer::new(device, queue, TARGET_FORMAT, w, h, cfg))
+    }
+
+    fn target(r: &Renderer, w: u32, h: u32) -> wgpu::TextureView {
+        r.device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("test_target"),
+                size: wgpu::Extent3d {
+                    width: w,
+                    height: h,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TARGET_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    }
+
+    // format のテクスチャに1フレーム描いて、そのままの画素を読み出す（w * 4 は 256 の倍数に）
+    fn draw_to(cfg: &Config, format: wgpu::TextureFormat, w: u32, h: u32) -> Option<Vec<u8>> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default())).ok()?;
+        let (device, queue) = pollster::block_on(request_device(&adapter)).ok()?;
+        let mut r = Renderer::new(device, queue, format, w, h, cfg);
+        let texture = r.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("test_target"),
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        r.render_to_view(&texture.create_view(&Default::default()));
+        let staging = r.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("test_readback"),
+            size: (w * h * 4) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = r.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(w * 4),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        r.queue.submit(Some(encoder.finish()));
+        staging.slice(..).map_async(wgpu::MapMode::Read, |_| ());
+        r.device.poll(wgpu::PollType::Wait).unwrap();
+        let px = staging.slice(..).get_mapped_range().to_vec();
+        Some(px)
+    }
+
+    fn read_frame_uniform(r: &Renderer) -> FrameParams {
+        let size = size_of::<FrameParams>() as u64;
+        let staging = r.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame_readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = r.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&r.frame_buf, 0, &staging, 0, size);
+        r.queue.submit(Some(encoder.finish()));
+        staging.slice(..).map_async(wgpu::MapMode::Read, |_| ());
+        r.device.poll(wgpu::PollType::Wait).unwrap();
+        let data = staging.slice(..).get_mapped_range();
+        bytemuck::pod_read_unaligned::<FrameParams>(&data)
+    }
+
+    #[test]
+    fn render_advances_frame_once_per_call() {
+        let Some(mut r) = headless(64, 64) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let view = target(&r, 64, 64);
+        let start = r.frame;
+        for n in 1..=5 {
+            r.render_to_view(&view);
+            assert_eq!(r.frame, start + n);
+            assert_eq!(read_frame_uniform(&r).frame, r.frame);
+        }
+
+        // 凍結中もフレームは進む
+        r.toggle_frozen();
+        r.render_to_view(&view);
+        assert_eq!(r.frame, start + 6);
+        assert_eq!(read_frame_uniform(&r).frame, r.frame);
+    }
+
+    #[test]
+    fn dt_follows_the_clock_and_is_clamped() {
+        assert_eq!(frame_dt(1.0, 0.5), 0.0);
+        assert_eq!(frame_dt(1.0, 30.0), MAX_DT);
+        let Some(mut r) = headless_with(64, 64, &Config::default()) else {
+            return;
+        };
+        let view = target(&r, 64, 64);
+        r.set_elapsed(1.0);
+        r.render_to_view(&view);
+        r.set_elapsed(1.016);
+        r.render_to_view(&view);
+        assert!((read_frame_uniform(&r).dt - 0.016).abs() < 1e-4);
+        // スリープ明けのような大きな間隔でも MAX_DT まで
+        r.set_elapsed(600.0);
+        r.render_to_view(&view);
+        assert_eq!(read_frame_uniform(&r).dt, MAX_DT);
+    }
+
+    #[test]
+    fn set_effect_matches_fresh_renderer() {
+        // 切り替え前に変えたパラメータは新しいエフェクトの既定値に戻る
+        let cfg = Config {
+            star_density: Some(0.9),
+            ..Default::default()
+        };
+        let fresh_cfg = Config {
+            effect: Some("starfield".into()),
+            ..Default::default()
+        };
+        let (Some(mut switched), Some(mut fresh)) = (
+            headless_with(32, 32, &cfg),
+            headless_with(32, 32, &fresh_cfg),
+        ) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        switched.set_effect(effects::find("starfield").unwrap());
+        assert_eq!(switched.effect().name, "starfield");
+        for r in [&mut switched, &mut fresh] {
+            let view = target(r, 32, 32);
+            for _ in 0..3 {
+                r.render_to_view(&view);
+            }
+        }
+        assert_eq!(switched.read_rgba8().unwrap(), fresh.read_rgba8().unwrap());
+    }
+
+    #[test]
+    fn seek_matches_live_render() {
+        for (smooth, motion) in [(0.0, 0.0), (0.5, 0.0), (0.0, 1.0)] {
+            let cfg = Config {
+                smooth,
+                motion_boost: motion,
+                ..Default::default()
+            };
+            let (Some(mut live), Some(mut seeked)) =
+                (headless_with(32, 32, &cfg), headless_with(32, 32, &cfg))
+            else {
+                eprintln!("no adapter available; skipping");
+                return;
+            };
+            let view = target(&live, 32, 32);
+            for _ in 0..7 {
+                live.render_to_view(&view);
+            }
+            let view2 = target(&seeked, 32, 32);
+            seeked.seek(&view2, 7);
+            seeked.render_to_view(&view2);
+            assert_eq!(seeked.frame, live.frame);
+            assert_eq!(
+                seeked.read_rgba8().unwrap(),
+                live.read_rgba8().unwrap(),
+                "smooth = {smooth}, motion = {motion}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_effect_renders_non_uniform_output() {
+        for effect in effects::EFFECTS {
+            let cfg = Config {
+                effect: Some(effect.name.to_string()),
+                ..Default::default()
+            };
+            let Some(mut r) = headless_with(128, 128, &cfg) else {
+                eprintln!("no adapter available; skipping");
+                return;
+            };
+            let view = target(&r, 128, 128);
+            // 動き出すまで少し進めてから描く
+            r.seek(&view, 120);
+            r.render_to_view(&view);
+            let px = r.read_rgba8().unwrap();
+            let first = &px[..4];
+            assert!(
+                px.chunks_exact(4).any(|p| p != first),
+                "effect {} rendered a uniform image",
+                effect.name
+            );
+        }
+    }
+
+    #[test]
+    fn gradient_follows_angle_and_radial_is_centered() {
+        let (w, h) = (64, 32);
+        let luma = |cfg: &Config, x: u32, y: u32| -> Option<u8> {
+            let mut r = headless_with(w, h, cfg)?;
+            let view = target(&r, w, h);
+            r.render_to_view(&view);
+            let px = r.read_rgba8().unwrap();
+            Some(px[((y * w + x) * 4) as usize])
+        };
+        let cfg = |angle: f32, radial: bool| Config {
+            effect: Some("gradient".into()),
+            gradient_angle: angle,
+            gradient_radial: radial,
+            ..Default::default()
+        };
+        let Some(left) = luma(&cfg(0.0, false), 0, h / 2) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        // 0 度は左が暗く右が明るい、90 度は下が暗く上が明るい
+        assert!(left < luma(&cfg(0.0, false), w - 1, h / 2).unwrap());
+        assert!(
+            luma(&cfg(90.0, false), w / 2, h - 1).unwrap()
+                < luma(&cfg(90.0, false), w / 2, 0).unwrap()
+        );
+        // 放射状は中心が最も暗く、四隅は同じ明るさ
+        let radial = cfg(0.0, true);
+        let center = luma(&radial, w / 2, h / 2).unwrap();
+        let corners: Vec<u8> = [(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)]
+            .iter()
+            .map(|&(x, y)| luma(&radial, x, y).unwrap())
+            .collect();
+        assert!(corners.iter().all(|&c| c == corners[0] && c > center));
+    }
+
+    #[test]
+    fn motion_boost_brightens_only_changing_pixels() {
+        let (w, h) = (64, 64);
+        let sum = |effect: &str, boost: f32| -> Option<u64> {
+            let cfg = Config {
+                effect: Some(effect.into()),
+                motion_boost: boost,
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            r.seek(&view, 60);
+            r.render_to_view(&view);
+            r.render_to_view(&view);
+            Some(r.read_rgba8().unwrap().iter().map(|&v| v as u64).sum())
+        };
+        let Some(still) = sum("gradient", 0.0) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        // 静止した gradient は変わらず、動く noise は明るくなる
+        assert_eq!(still, sum("gradient", 4.0).unwrap());
+        assert!(sum("noise", 4.0).unwrap() > sum("noise", 0.0).unwrap());
+    }
+
+    #[test]
+    fn taa_reduces_shimmer_on_noise() {
+        let (w, h) = (64, 64);
+        // 連続する2フレームの画素ごとの差の平均
+        let shimmer = |taa: bool| -> Option<f64> {
+            let cfg = Config {
+                taa,
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            r.seek(&view, 30);
+            r.render_to_view(&view);
+            let a = r.read_rgba8().unwrap();
+            r.render_to_view(&view);
+            let b = r.read_rgba8().unwrap();
+            let diff: u64 = a.iter().zip(&b).map(|(x, y)| x.abs_diff(*y) as u64).sum();
+            Some(diff as f64 / a.len() as f64)
+        };
+        let Some(plain) = shimmer(false) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let taa = shimmer(true).unwrap();
+        assert!(taa < plain * 0.5, "taa {taa:.1} vs plain {plain:.1}");
+    }
+
+    #[test]
+    fn background_shows_through_dark_overlay() {
+        let (w, h) = (64, 32);
+        let path = std::env::temp_dir().join(format!("swarm-bg-{}.png", std::process::id()));
+        let red: Vec<u8> = [200, 0, 0, 255].repeat(4);
+        png::write(&path, 2, 2, png::Depth::Eight, &red).unwrap();
+        // gradient は左端が黒、右端が白
+        let cfg = Config {
+            effect: Some("gradient".into()),
+            background: Some(path.clone()),
+            ..Default::default()
+        };
+        let r = headless_with(w, h, &cfg);
+        let _ = std::fs::remove_file(&path);
+        let Some(mut r) = r else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let view = target(&r, w, h);
+        r.render_to_view(&view);
+        let px = r.read_rgba8().unwrap();
+        let at = |x: u32| &px[((h / 2 * w + x) * 4) as usize..][..3];
+        // 暗いところは背景の赤、明るいところはエフェクト
+        let left = at(0);
+        assert!(left[0] > 180 && left[1] < 20, "left {left:?}");
+        let right = at(w - 1);
+        assert!(right[1] > 200, "right {right:?}");
+
+        r.set_param("overlay_opacity", 0.0).unwrap();
+        r.render_to_view(&view);
+        let px = r.read_rgba8().unwrap();
+        let right = &px[((h / 2 * w + w - 1) * 4) as usize..][..3];
+        assert!(
+            right[0] > 180 && right[1] < 20,
+            "transparent overlay {right:?}"
+        );
+    }
+
+    #[test]
+    fn reload_shader_keeps_last_working_pipeline() {
+        let (w, h) = (16, 16);
+        let Some(mut r) = headless_with(w, h, &Config::default()) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let view = target(&r, w, h);
+        let red = "@fragment fn fs_main(in: VSOut) -> @location(0) vec4<f32> {\n  return vec4<f32>(1.0, 0.0, 0.0, 1.0);\n}\n";
+        let noise = effects::find("noise").unwrap();
+        r.reload_shader(noise, red).unwrap();
+        let err = r
+            .reload_shader(noise, "@fragment fn fs_main( {")
+            .unwrap_err();
+        assert!(!err.is_empty());
+        r.render_to_view(&view);
+        let px = r.read_rgba8().unwrap();
+        assert!(px[0] > 250 && px[1] < 5, "{:?}", &px[..4]);
+
+        // 描いていないエフェクトは、直したものが次に選んだときに使われる
+        let matrix = effects::find("matrix").unwrap();
+        let green = red.replace("1.0, 0.0, 0.0", "0.0, 1.0, 0.0");
+        r.reload_shader(matrix, "@fragment fn fs_main( {")
+            .unwrap_err();
+        r.reload_shader(matrix, &green).unwrap();
+        r.render_to_view(&view);
+        assert!(r.read_rgba8().unwrap()[0] > 250, "noise changed");
+        r.set_effect(matrix);
+        r.render_to_view(&view);
+        let px = r.read_rgba8().unwrap();
+        assert!(px[1] > 250 && px[0] < 5, "{:?}", &px[..4]);
+        // 戻しても読み直したものを使う
+        r.set_effect(noise);
+        r.render_to_view(&view);
+        assert!(r.read_rgba8().unwrap()[0] > 250);
+    }
+
+    #[test]
+    fn noise_brightens_near_cursor_after_resize() {
+        let cfg = Config {
+            mouse_force: 3.0,
+            ..Default::default()
+        };
+        let Some(mut r) = headless_with(32, 32, &cfg) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        r.set_mouse(0.0, 0.0);
+        // リサイズ後もカーソル位置は残る
+        let (w, h) = (64, 64);
+        r.resize(w, h);
+        let view = target(&r, w, h);
+        r.render_to_view(&view);
+        let px = r.read_rgba8().unwrap();
+        let mean = |x0: u32, y0: u32| {
+            let mut sum = 0u32;
+            for y in y0..y0 + 8 {
+                for x in x0..x0 + 8 {
+                    sum += px[((y * w + x) * 4) as usize] as u32;
+                }
+            }
+            sum as f32 / 64.0
+        };
+        let near = mean(0, 0);
+        let far = mean(w - 8, h - 8);
+        assert!(near > far + 40.0, "near {near} far {far}");
+    }
+
+    #[test]
+    fn kaleidoscope_output_is_mirror_symmetric() {
+        let (w, h) = (64, 64);
+        let cfg = Config {
+            kaleidoscope: 6,
+            ..Default::default()
+        };
+        let Some(mut r) = headless_with(w, h, &cfg) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let view = target(&r, w, h);
+        r.render_to_view(&view);
+        let px = r.read_rgba8().unwrap();
+        let at = |x: u32, y: u32| px[((y * w + x) * 4) as usize] as i32;
+        // 6 分割なら中心を通る水平線で上下対称になる（フィルタ分の誤差は許す）
+        for y in 0..h / 2 {
+            for x in 0..w {
+                let d = (at(x, y) - at(x, h - 1 - y)).abs();
+                assert!(d <= 8, "({x}, {y}) differs from its mirror by {d}");
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_timestep_swarm_matches_across_refresh_rates() {
+        let (w, h) = (96, 64);
+        let cfg = Config {
+            effect: Some("swarm".into()),
+            agents: Some(256),
+            // 2 進で割り切れる刻みにして、1 秒ちょうどで 16 ステップになるようにする
+            fixed_dt: Some(1.0 / 16.0),
+            ..Default::default()
+        };
+        let run = |hz: f32| -> Option<Vec<u8>> {
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            r.set_refresh_hz(hz);
+            // 1 秒分描く
+            for _ in 0..hz as u32 {
+                r.render_to_view(&view);
+            }
+            Some(r.read_rgba8().unwrap())
+        };
+        let Some(slow) = run(30.0) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        assert!(slow == run(144.0).unwrap(), "30 and 144 FPS diverged");
+    }
+
+    #[test]
+    fn swarm_lifetime_keeps_brightness_steady() {
+        let (w, h) = (160, 90);
+        let cfg = Config {
+            effect: Some("swarm".into()),
+            agents: Some(256),
+            lifetime: Some([0.5, 1.0]),
+            ..Default::default()
+        };
+        let Some(mut r) = headless_with(w, h, &cfg) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let view = target(&r, w, h);
+        // 寿命の数倍の間、明るさの合計が大きく落ち込まない（一斉に死なない）
+        let mut sums = Vec::new();
+        for _ in 0..8 {
+            for _ in 0..15 {
+                r.render_to_view(&view);
+            }
+            let px = r.read_rgba8().unwrap();
+            sums.push(px.iter().step_by(4).map(|&v| v as u64).sum::<u64>());
+        }
+        let (lo, hi) = (sums.iter().min().unwrap(), sums.iter().max().unwrap());
+        assert!(*lo * 10 > *hi * 7, "brightness over time: {sums:?}");
+    }
+
+    #[test]
+    fn scale_agents_follows_the_surface_area() {
+        let cfg = Config {
+            effect: Some("swarm".into()),
+            agents: Some(100),
+            scale_agents: true,
+            max_agents: Some(300),
+            ..Default::default()
+        };
+        let Some(mut r) = headless_with(64, 64, &cfg) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let positions = |r: &Renderer| {
+            let Scene::Swarm(swarm) = &r.scene else {
+                panic!("not a swarm");
+            };
+            (swarm.agent_count, swarm.read_positions(&r.device, &r.queue))
+        };
+        r.render_to_view(&target(&r, 64, 64));
+        let (_, before) = positions(&r);
+
+        // 横に倍: 数も倍になり、今いるエージェントはそのまま、増えた分は広がった左右の帯に置く
+        r.resize(128, 64);
+        let (count, after) = positions(&r);
+        assert_eq!(count, 200);
+        assert_eq!(before[..100], after[..100]);
+        for p in &after[100..200] {
+            assert!((1.0..=2.0).contains(&p[0].abs()), "{p:?}");
+        }
+        r.render_to_view(&target(&r, 128, 64));
+
+        // 上限で止まり、縮めれば減る
+        r.resize(256, 128);
+        assert_eq!(positions(&r).0, 300);
+        r.render_to_view(&target(&r, 256, 128));
+        r.resize(32, 32);
+        assert_eq!(positions(&r).0, 25);
+        r.render_to_view(&target(&r, 32, 32));
+    }
+
+    #[test]
+    fn min_dist_keeps_agents_apart() {
+        let (w, h) = (64, 64);
+        // 小さな画面にたくさん入れて群れさせ、いちばん近い2体の距離を測る
+        let closest = |min_dist| -> Option<f32> {
+            let cfg = Config {
+                effect: Some("swarm".into()),
+                agents: Some(512),
+                min_dist,
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            for _ in 0..200 {
+                r.render_to_view(&view);
+            }
+            let Scene::Swarm(swarm) = &r.scene else {
+                panic!("not a swarm");
+            };
+            let pos = swarm.read_positions(&r.device, &r.queue);
+            let mut best = f32::MAX;
+            for (i, a) in pos.iter().enumerate() {
+                for b in &pos[i + 1..] {
+                    best = best.min((a[0] - b[0]).hypot(a[1] - b[1]));
+                }
+            }
+            Some(best)
+        };
+        let Some(plain) = closest(0.0) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let spaced = closest(0.02).unwrap();
+        assert!(
+            spaced > 0.01,
+            "closest pair {spaced} (without min_dist {plain})"
+        );
+        assert!(
+            spaced > plain * 2.0,
+            "closest pair {spaced} (without min_dist {plain})"
+        );
+    }
+
+    #[test]
+    fn workgroup_size_does_not_change_the_swarm() {
+        let (w, h) = (96, 64);
+        // どの大きさでも割り切れない数にして、最後のグループの端数も更新されることを確かめる
+        let run = |workgroup| -> Option<Vec<u8>> {
+            let cfg = Config {
+                effect: Some("swarm".into()),
+                agents: Some(300),
+                workgroup: Some(workgroup),
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            for _ in 0..20 {
+                r.render_to_view(&view);
+            }
+            Some(r.read_rgba8().unwrap())
+        };
+        let Some(base) = run(64) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        for workgroup in [128, 256] {
+            assert!(
+                run(workgroup).unwrap() == base,
+                "workgroup {workgroup} diverged"
+            );
+        }
+    }
+
+    #[test]
+    fn speed_smoothing_softens_sudden_brightness_jumps() {
+        let (w, h) = (128, 96);
+        // カーソルで一斉に引っ張ったとき、直後のフレームで明るさの合計がどれだけ跳ねるか
+        let jump = |speed_smooth| -> Option<f64> {
+            let cfg = Config {
+                effect: Some("swarm".into()),
+                agents: Some(256),
+                speed_smooth,
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            let total = |r: &mut Renderer| {
+                r.render_to_view(&view);
+                let px = r.read_rgba8().unwrap();
+                px.iter().step_by(4).map(|&v| v as u64).sum::<u64>() as f64
+            };
+            for _ in 0..30 {
+                total(&mut r);
+            }
+            let before = total(&mut r);
+            r.set_mouse(0.5, 0.5);
+            r.set_param("mouse_force", 100.0).unwrap();
+            let after = total(&mut r);
+            Some((after - before).abs() / before)
+        };
+        let Some(plain) = jump(0.0) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let smoothed = jump(0.9).unwrap();
+        assert!(
+            smoothed * 2.0 < plain,
+            "smoothed {smoothed} vs plain {plain}"
+        );
+    }
+
+    #[test]
+    fn particle_discs_have_antialiased_edges_at_any_size() {
+        for (w, h) in [(64, 64), (256, 256)] {
+            let cfg = Config {
+                effect: Some("swarm".into()),
+                agents: Some(1),
+                particle_edge: 1.0,
+                ..Default::default()
+            };
+            let Some(mut r) = headless_with(w, h, &cfg) else {
+                eprintln!("no adapter available; skipping");
+                return;
+            };
+            let view = target(&r, w, h);
+            r.render_to_view(&view);
+            let px = r.read_rgba8().unwrap();
+            let lum: Vec<u8> = px.iter().step_by(4).copied().collect();
+            let peak = *lum.iter().max().unwrap() as u32;
+            assert!(peak > 0, "{w}x{h}: nothing drawn");
+            // 中は一様に明るく、縁は中間の明るさで1ピクセルほどの幅になる
+            let core = lum.iter().filter(|&&v| v as u32 * 20 >= peak * 19).count();
+            let edge = lum
+                .iter()
+                .filter(|&&v| v > 0 && (v as u32) * 20 < peak * 19)
+                .count();
+            assert!(core >= 12, "{w}x{h}: core {core}");
+            assert!((8..=48).contains(&edge), "{w}x{h}: edge {edge}");
+        }
+    }
+
+    #[test]
+    fn queued_reads_match_synchronous_reads() {
+        let (w, h) = (48, 32);
+        let cfg = Config::default();
+        let Some(mut sync) = headless_with(w, h, &cfg) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let mut queued = headless_with(w, h, &cfg).unwrap();
+        let view = target(&sync, w, h);
+        let queued_view = target(&queued, w, h);
+        let mut expected = Vec::new();
+        let mut got = Vec::new();
+        for _ in 0..6 {
+            sync.render_to_view(&view);
+            expected.push(sync.read_rgba8().unwrap());
+            queued.render_to_view(&queued_view);
+            if queued.reads_in_flight() >= 2 {
+                got.push(queued.pop_read().unwrap().unwrap());
+            }
+            queued.queue_read_rgba8();
+            assert!(queued.reads_in_flight() <= 2);
+        }
+        while let Some(px) = queued.pop_read().unwrap() {
+            got.push(px);
+        }
+        assert!(got == expected, "queued reads differ from synchronous ones");
+    }
+
+    #[test]
+    fn smooth_noise_kinds_are_smoother_than_hash() {
+        // 格子が数個入る大きさ
+        let (w, h) = (256, 256);
+        // 隣り合う画素の差の平均と、明るさの幅
+        let roughness = |noise| -> Option<(f32, u8)> {
+            let cfg = Config {
+                noise,
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            r.render_to_view(&view);
+            let px = r.read_rgba8().unwrap();
+            let mut sum = 0u32;
+            for y in 0..h {
+                for x in 1..w {
+                    let i = ((y * w + x) * 4) as usize;
+                    sum += px[i].abs_diff(px[i - 4]) as u32;
+                }
+            }
+            let lum = px.iter().step_by(4);
+            let range = lum.clone().max().unwrap() - lum.min().unwrap();
+            Some((sum as f32 / (h * (w - 1)) as f32, range))
+        };
+        let Some((hash, _)) = roughness(NoiseKind::Hash) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        for kind in [NoiseKind::Value, NoiseKind::Perlin, NoiseKind::Simplex] {
+            let (r, range) = roughness(kind).unwrap();
+            assert!(r < 8.0 && r * 10.0 < hash, "{kind:?}: {r} vs hash {hash}");
+            // 一様につぶれていない
+            assert!(range > 60, "{kind:?}: range {range}");
+        }
+    }
+
+    #[test]
+    fn msaa_resolves_into_the_scene_after_resize() {
+        let mut totals = Vec::new();
+        for msaa in [1, 4] {
+            let cfg = Config {
+                effect: Some("swarm".into()),
+                msaa,
+                ..Default::default()
+            };
+            let Some(mut r) = headless_with(64, 64, &cfg) else {
+                eprintln!("no adapter available; skipping");
+                return;
+            };
+            r.render_to_view(&target(&r, 64, 64));
+            r.resize(96, 48);
+            let view = target(&r, 96, 48);
+            r.render_to_view(&view);
+            let px = r.read_rgba8().unwrap();
+            totals.push(px.iter().step_by(4).map(|&v| v as u64).sum::<u64>());
+        }
+        // 縁が少し変わるだけで、描かれる量はほとんど同じ
+        let [plain, msaa] = totals[..] else {
+            unreachable!()
+        };
+        assert!(plain > 0 && msaa > 0, "{totals:?}");
+        assert!(plain.abs_diff(msaa) * 10 < plain, "{totals:?}");
+    }
+
+    #[test]
+    fn seed_changes_the_pattern_and_is_kept() {
+        let (w, h) = (64, 64);
+        let render = |seed| -> Option<(Renderer, Vec<u8>)> {
+            let cfg = Config {
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            r.render_to_view(&target(&r, w, h));
+            let px = r.read_rgba8().unwrap();
+            Some((r, px))
+        };
+        let Some((mut r, a)) = render(1) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let (_, b) = render(2).unwrap();
+        let (_, again) = render(1).unwrap();
+        // 上位ビットだけが違う種でも模様は変わる
+        let (_, high) = render(1 | 1 << 28).unwrap();
+        assert_ne!(a, b);
+        assert_ne!(a, high);
+        assert_eq!(a, again);
+
+        r.resize(32, 32);
+        r.set_effect(effects::find("starfield").unwrap());
+        assert_eq!(r.params.seed, 1);
+    }
+
+    #[test]
+    fn trail_decay_leaves_fading_tails() {
+        let (w, h) = (128, 128);
+        let lit = |trail_decay| -> Option<usize> {
+            let cfg = Config {
+                effect: Some("swarm".into()),
+                agents: Some(4),
+                trail_decay,
+                ..Default::default()
+            };
+            let mut r = headless_with(w, h, &cfg)?;
+            let view = target(&r, w, h);
+            for _ in 0..60 {
+                r.render_to_view(&view);
+            }
+            let px = r.read_rgba8().unwrap();
+            Some(px.iter().step_by(4).filter(|&&v| v > 8).count())
+        };
+        let Some(plain) = lit(0.0) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let trail = lit(0.95).unwrap();
+        assert!(plain > 0);
+        assert!(trail > plain * 3 / 2, "trail {trail} vs plain {plain}");
+    }
+
+    #[test]
+    fn output_looks_the_same_with_and_without_an_srgb_target() {
+        let (w, h) = (64, 4);
+        let cfg = Config {
+            effect: Some("gradient".into()),
+            ..Default::default()
+        };
+        let draw = |format| draw_to(&cfg, format, w, h);
+        let Some(srgb) = draw(wgpu::TextureFormat::Rgba8UnormSrgb) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let unorm = draw(wgpu::TextureFormat::Rgba8Unorm).unwrap();
+        let worst = srgb.iter().zip(&unorm).map(|(a, b)| a.abs_diff(*b)).max();
+        assert!(worst <= Some(1), "worst difference {worst:?}");
+        // 中間調まであるグラデーションで比べている
+        assert!(srgb.iter().step_by(4).any(|&v| (64..192).contains(&v)));
+    }
+
+    #[test]
+    fn overlay_makes_dark_parts_transparent() {
+        let (w, h) = (64, 4);
+        let cfg = Config {
+            effect: Some("gradient".into()),
+            overlay: true,
+            ..Default::default()
+        };
+        let Some(px) = draw_to(&cfg, TARGET_FORMAT, w, h) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        // 乗算済みアルファ: 色はアルファを超えず、暗いところほど透ける
+        for p in px.chunks(4) {
+            assert!(p[..3].iter().all(|&c| c <= p[3]), "{p:?}");
+        }
+        let alphas: Vec<u8> = px.chunks(4).map(|p| p[3]).collect();
+        assert!(alphas.iter().any(|&a| a < 32), "{alphas:?}");
+        assert!(alphas.iter().any(|&a| a > 224), "{alphas:?}");
+        // 通常は不透明
+        let opaque = draw_to(
+            &Config {
+                overlay: false,
+                ..cfg
+            },
+            TARGET_FORMAT,
+            w,
+            h,
+        )
+        .unwrap();
+        assert!(opaque.chunks(4).all(|p| p[3] == 255));
+    }
+
+    #[test]
+    fn adapter_is_picked_by_name() {
+        let instance = wgpu::Instance::default();
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+        let Some(last) = adapters.last() else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        assert_eq!(list_adapters().len(), adapters.len());
+        // 大文字小文字を区別せず、名前の一部で選べる
+        let name = last.get_info().name.to_uppercase();
+        let found = pollster::block_on(find_adapter(&instance, Some(&name), None)).unwrap();
+        assert_eq!(found.get_info().name.to_uppercase(), name);
+        // 合うものがなければ既定のアダプタ
+        let fallback = pollster::block_on(find_adapter(&instance, Some("no such gpu"), None));
+        assert!(fallback.is_ok());
+    }
+
+    #[test]
+    fn noise_cells_follow_the_scale_factor() {
+        let (w, h) = (256, 64);
+        let cfg = Config {
+            noise: NoiseKind::Perlin,
+            ..Default::default()
+        };
+        // 隣り合う画素の差の合計。格子が大きいほど小さい
+        let roughness = |scale| -> Option<u32> {
+            let mut r = headless_with(w, h, &cfg)?;
+            r.set_scale_factor(scale);
+            // 0 以下は無視する
+            r.set_scale_factor(0.0);
+            let view = target(&r, w, h);
+            r.render_to_view(&view);
+            let px = r.read_rgba8().unwrap();
+            Some(
+                px.chunks(4)
+                    .zip(px.chunks(4).skip(1))
+                    .map(|(a, b)| a[0].abs_diff(b[0]) as u32)
+                    .sum(),
+            )
+        };
+        let Some(normal) = roughness(1.0) else {
+            eprintln!("no adapter available; skipping");
+            return;
+        };
+        let hidpi = roughness(2.0).unwrap();
+        assert!(
+            hidpi * 3 < normal * 2,
+            "scale 2: {hidpi} vs scale 1: {normal}"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+// 群れ（boids）の更新。agents_in を読んで agents_out に書く（毎フレーム入れ替え）
+struct Agent {
+  pos:  vec2<f32>, // x: -aspect..aspect, y: -1..1（端は反対側へ回り込む）
+  vel:  vec2<f32>, // 単位/秒
+  age:  f32,       // 生まれてからの秒数
+  life: f32,       // 寿命。0 なら死なない
+  speed: f32,      // 色に使う速さ 0..1。speed_smooth で前のステップの値と混ぜる
+}
+
+// 1ステップの時間と、描画時の前ステップとの補間率
+struct Sim {
+  dt:       f32,
+  alpha:    f32, // 0 = 前のステップ, 1 = 最新
+  life_min: f32, // 生まれ直すときの寿命の範囲（秒）。life_max が 0 なら死なない
+  life_max: f32,
+  speed_smooth: f32, // 0 なら今の速さそのまま
+  min_dist:     f32, // エージェント同士をこれより近づけない。0 なら無効
+  count:        u32, // 生きているエージェントの数
+  _pad0: f32,
+}
+
+@group(2) @binding(0) var<uniform> sim: Sim;
+@group(3) @binding(0) var<storage, read> agents_in: array<Agent>;
+@group(3) @binding(1) var<storage, read_write> agents_out: array<Agent>;
+
+// 近傍の半径と、それぞれの規則の強さ（/秒）
+const COHESION_R:   f32 = 0.15;
+const ALIGN_R:      f32 = 0.08;
+const SEPARATION_R: f32 = 0.03;
+const COHESION:     f32 = 0.6;
+const ALIGN:        f32 = 2.0;
+const SEPARATION:   f32 = 12.0;
+const MIN_SPEED:    f32 = 0.1;
+const MAX_SPEED:    f32 = 0.35;
+// カーソルの影響が届く広さ。大きいほど狭い
+const MOUSE_FALLOFF: f32 = 4.0;
+// 低音で速度の上限をどれだけ上げるか（bass = 1 で倍率 1 + これ）と、押し出す強さ（/秒）
+const BASS_SPEED: f32 = 1.5;
+const BASS_KICK:  f32 = 6.0;
+
+// 生まれ直しの位置などに使う乱数。pcg は scene.wgsl にある
+fn rand01(v: u32) -> f32 {
+  return f32(pcg(v)) / 4294967295.0;
+}
+
+// 生まれた直後と死ぬ直前の明るさ 0..1
+fn fade(age: f32, life: f32) -> f32 {
+  if (life <= 0.0) {
+    return 1.0;
+  }
+  let f = min(0.5, life * 0.25);
+  return smoothstep(0.0, f, age) * smoothstep(life, life - f, age);
+}
+
+// 回り込みを考えた最短の差分
+fn wrap_delta(d: vec2<f32>, extent: vec2<f32>) -> vec2<f32> {
+  return d - 2.0 * extent * round(d / (2.0 * extent));
+}
+
+// 近傍やカーソルへの差分。回り込むときだけ反対側の端とつながっているとみなす
+fn edge_delta(d: vec2<f32>, extent: vec2<f32>) -> vec2<f32> {
+  if (params.edge == 0u) {
+    return wrap_delta(d, extent);
+  }
+  return d;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+  // バッファは面積に合わせて増やす分まで確保してあるので、生きている数は sim.count
+  let n = sim.count;
+  let i = id.x;
+  if (i >= n) {
+    return;
+  }
+  let extent = vec2<f32>(params.size.x / params.size.y, 1.0);
+  let me = agents_in[i];
+
+  var center = vec2<f32>(0.0);
+  var heading = vec2<f32>(0.0);
+  var avoid = vec2<f32>(0.0);
+  var spread = vec2<f32>(0.0);
+  var near = 0u;
+  var aligned = 0u;
+  for (var j = 0u; j < n; j = j + 1u) {
+    if (j == i) {
+      continue;
+    }
+    let other = agents_in[j];
+    let d = edge_delta(other.pos - me.pos, extent);
+    let dist = length(d);
+    if (dist < COHESION_R) {
+      center = center + d;
+      near = near + 1u;
+    }
+    if (dist < ALIGN_R) {
+      heading = heading + other.vel;
+      aligned = aligned + 1u;
+    }
+    if (dist < SEPARATION_R) {
+      avoid = avoid - d;
+    }
+    if (dist < sim.min_dist) {
+      // ぴったり重なっているときは2体の番号で決めた向きに、互いに反対へ離す
+      var away = -d / max(dist, 1e-6);
+      if (dist < 1e-6) {
+        let a = rand01(pcg(min(i, j) * 65537u + max(i, j))) * 6.2831853;
+        away = vec2<f32>(cos(a), sin(a)) * select(1.0, -1.0, i < j);
+      }
+      spread = spread + away * (sim.min_dist - dist);
+    }
+  }
+
+  // ポーズ明けなどで大きな dt が来ても暴れないように抑える
+  let dt = min(sim.dt, 0.1);
+  var vel = me.vel;
+  if (near > 0u) {
+    vel = vel + center / f32(near) * COHESION * dt;
+  }
+  if (aligned > 0u) {
+    vel = vel + (heading / f32(aligned) - vel) * ALIGN * dt;
+  }
+  vel = vel + avoid * SEPARATION * dt;
+
+  // カーソルへ引き寄せる（mouse_force が負なら遠ざける）。近いほど強い
+  let cursor = (params.mouse * 2.0 - 1.0) * vec2<f32>(extent.x, -1.0);
+  let to_cursor = edge_delta(cursor - me.pos, extent);
+  let reach = length(to_cursor);
+  if (reach > 0.0) {
+    vel = vel + to_cursor / reach * params.mouse_force * exp(-reach * reach * MOUSE_FALLOFF) * dt;
+  }
+
+  // 低音が鳴るたびに進む向きへ加速させる
+  vel = vel * (1.0 + params.bass * BASS_KICK * dt);
+
+  let speed = length(vel);
+  if (speed > 0.0) {
+    vel = vel / speed * clamp(speed, MIN_SPEED, MAX_SPEED * (1.0 + params.bass * BASS_SPEED));
+  }
+  // 近すぎる相手とは位置を直接ずらして離す。相手も同じだけ動くので半分ずつ
+  var pos = me.pos + vel * dt + spread * 0.5;
+  switch params.edge {
+    // bounce: 端を越えた分だけ折り返し、その向きの速度を反転する
+    case 1u: {
+      let over = abs(pos) > extent;
+      pos = select(pos, sign(pos) * 2.0 * extent - pos, over);
+      vel = select(vel, -vel, over);
+      pos = clamp(pos, -extent, extent);
+    }
+    // clamp: 端で止める。向きは群れの規則に任せる
+    case 2u: {
+      pos = clamp(pos, -extent, extent);
+    }
+    default: {
+      pos = wrap_delta(pos, extent);
+    }
+  }
+  let age = me.age + dt;
+  if (me.life > 0.0 && age >= me.life && sim.life_max > 0.0) {
+    // 寿命が来たら適当な場所・向きで生まれ直す。位置のビットを混ぜて毎回違う値にする
+    let seed = pcg(i ^ bitcast<u32>(me.pos.x)) ^ bitcast<u32>(me.pos.y);
+    let p = vec2<f32>(rand01(seed), rand01(seed + 1u)) * 2.0 - 1.0;
+    let angle = rand01(seed + 2u) * 6.2831853;
+    let life = mix(sim.life_min, sim.life_max, rand01(seed + 3u));
+    let v = vec2<f32>(cos(angle), sin(angle)) * MIN_SPEED * 2.0;
+    agents_out[i] = Agent(p * extent, v, 0.0, life, MIN_SPEED * 2.0 / MAX_SPEED);
+    return;
+  }
+  // 速い個体が明るさの両端を行き来して明滅しないよう、色の速さは前の値と混ぜて均す
+  let shade = mix(min(length(vel) / MAX_SPEED, 1.0), me.speed, sim.speed_smooth);
+  agents_out[i] = Agent(pos, vel, age, me.life, shade);
+}
@@ -0,0 +1,20 @@
+// 全画面三角形。テクスチャを読むパスの先頭に連結して使う
+struct VSOut { @builtin(position) pos: vec4<f32>, @location(0) uv: vec2<f32>, };
+
+fn fullscreen(vid: u32) -> VSOut {
+  var p = array<vec2<f32>, 3>(
+    vec2<f32>(-1.0, -3.0),
+    vec2<f32>(-1.0,  1.0),
+    vec2<f32>( 3.0,  1.0)
+  );
+  var o: VSOut;
+  o.pos = vec4<f32>(p[vid], 0.0, 1.0);
+  // テクスチャ座標はY下向き
+  o.uv = vec2<f32>(p[vid].x * 0.5 + 0.5, 0.5 - p[vid].y * 0.5);
+  return o;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vid: u32) -> VSOut {
+  return fullscreen(vid);
+}
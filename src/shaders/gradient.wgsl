@@ -0,0 +1,18 @@
+// 輝度 0→1 のグラデーション。パレットと組み合わせて使う
+@fragment
+fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
+  let aspect = params.size.x / params.size.y;
+  // 画面中心が原点、Y上向き、縦の長さが 1
+  let p = (in.uv - 0.5) * vec2<f32>(aspect, -1.0);
+  var c: f32;
+  if (params.gradient_radial != 0u) {
+    // 中心 0、四隅 1
+    c = length(p) / length(vec2<f32>(aspect, 1.0) * 0.5);
+  } else {
+    // 0 度で左→右、反時計回り。角度によらず両端の角がちょうど 0 と 1 になるよう正規化
+    let dir = vec2<f32>(cos(params.gradient_angle), sin(params.gradient_angle));
+    let half = 0.5 * (abs(dir.x) * aspect + abs(dir.y));
+    c = dot(p, dir) / half * 0.5 + 0.5;
+  }
+  return vec4<f32>(vec3<f32>(clamp(c, 0.0, 1.0)), 1.0);
+}
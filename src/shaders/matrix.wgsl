@@ -0,0 +1,44 @@
+// 緑の文字が列ごとに流れ落ちる。文字はフォントを使わず 3x5 のドットをハッシュで決める
+const CELL_PX: f32 = 16.0;
+
+fn hash21(p: vec2<f32>) -> f32 {
+  var q = fract(p * vec2<f32>(233.34, 851.73));
+  q = q + dot(q, q + 23.45);
+  return fract(q.x * q.y);
+}
+
+@fragment
+fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
+  let px = in.uv * params.size;
+  let cell = floor(px / CELL_PX);
+  let local = fract(px / CELL_PX);
+
+  // 列ごとに有無・速さ・位相を変える
+  let col = hash21(vec2<f32>(cell.x, 1.0));
+  if (col > params.rain_density) {
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+  }
+  let rows = params.size.y / CELL_PX;
+  // 画面の1.5倍の長さを周回させ、列が途切れる間をつくる
+  let span = rows * 1.5;
+  let speed = params.rain_speed * (0.5 + col) * 8.0; // 行/秒
+  let head = fract(time() * speed / span + hash21(vec2<f32>(cell.x, 7.0))) * span;
+  let dist = head - cell.y;
+  if (dist < 0.0) {
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+  }
+
+  // 文字はときどき入れ替わる
+  let glyph = floor(time() * (1.0 + 3.0 * hash21(cell)) + hash21(cell + 3.1) * 10.0);
+  let dot_id = floor(local * vec2<f32>(3.0, 5.0));
+  let inside = all(local > vec2<f32>(0.1, 0.08)) && all(local < vec2<f32>(0.9, 0.92));
+  let on = select(0.0, 1.0, inside && hash21(cell * 1.3 + dot_id * 17.0 + glyph) > 0.45);
+
+  let trail = exp(-dist * 0.15);
+  var c = vec3<f32>(0.1, 1.0, 0.35) * trail * on;
+  // 先頭の文字は白く光らせる
+  if (dist < 1.0) {
+    c = vec3<f32>(0.8, 1.0, 0.85) * on;
+  }
+  return vec4<f32>(c, 1.0);
+}
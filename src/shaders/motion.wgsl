@@ -0,0 +1,21 @@
+@group(0) @binding(0) var cur: texture_2d<f32>;
+@group(0) @binding(1) var samp: sampler;
+
+struct Motion {
+  boost: f32, // 差分に掛ける倍率
+  _pad0: f32,
+  _pad1: f32,
+  _pad2: f32,
+}
+
+@group(0) @binding(2) var<uniform> motion: Motion;
+@group(0) @binding(3) var prev: texture_2d<f32>;
+
+// 前フレームから変わった分だけ明るくする。静止部分はそのまま
+@fragment
+fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
+  let c = textureSample(cur, samp, in.uv);
+  let p = textureSample(prev, samp, in.uv);
+  let d = abs(c.rgb - p.rgb);
+  return vec4<f32>(c.rgb + d * motion.boost, c.a);
+}
@@ -1,26 +1,3 @@
-struct Params {
-  size:  vec2<f32>, // 8B
-  frame: u32,       // +4B
-  _pad:  u32,       // +4B → 合計16B（std140でもOK）
-}
-
-@group(0) @binding(0) var<uniform> params: Params;
-
-struct VSOut { @builtin(position) pos: vec4<f32>, @location(0) uv: vec2<f32>, };
-
-@vertex
-fn vs_main(@builtin(vertex_index) vid: u32) -> VSOut {
-  var p = array<vec2<f32>, 3>(
-    vec2<f32>(-1.0, -3.0),
-    vec2<f32>(-1.0,  1.0),
-    vec2<f32>( 3.0,  1.0)
-  );
-  var o: VSOut;
-  o.pos = vec4<f32>(p[vid], 0.0, 1.0);
-  o.uv = (p[vid] * 0.5 + vec2<f32>(0.5, 0.5));
-  return o;
-}
-
 // 適当ハッシュ（そのままでOK）
 fn hash2(p: vec2<f32>, seed: f32) -> f32 {
   let q = vec2<f32>(
@@ -31,9 +8,143 @@ fn hash2(p: vec2<f32>, seed: f32) -> f32 {
   return fract(sin(dot(s, vec2<f32>(1.0, 7.0))) * 0.5 + 0.5);
 }
 
+// 模様の切り替わりとドリフトの基準。表示のリフレッシュレートによらずこの速さで変わる
+const NOISE_HZ: f32 = 60.0;
+// 滑らかなノイズの格子の大きさ（論理ピクセル）と、模様が流れ変わる速さ（格子/秒）
+const NOISE_CELL: f32 = 96.0;
+const FLOW_SPEED: f32 = 0.25;
+
+// 格子点の整数ハッシュ。種ごとに別の値になる
+fn lattice(c: vec3<i32>) -> u32 {
+  var h = (bitcast<u32>(c.x) * 73856093u) ^ (bitcast<u32>(c.y) * 19349663u) ^ (bitcast<u32>(c.z) * 83492791u) ^ seed_hash();
+  h = (h ^ (h >> 16u)) * 0x7feb352du;
+  h = (h ^ (h >> 15u)) * 0x846ca68bu;
+  return h ^ (h >> 16u);
+}
+
+fn quintic(f: vec3<f32>) -> vec3<f32> {
+  return f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+}
+
+// 格子点の乱数を補間する。0..1
+fn value3(p: vec3<f32>) -> f32 {
+  let i = vec3<i32>(floor(p));
+  let u = quintic(fract(p));
+  var v: array<f32, 8>;
+  for (var k = 0; k < 8; k = k + 1) {
+    let d = vec3<i32>(k & 1, (k >> 1) & 1, (k >> 2) & 1);
+    v[k] = f32(lattice(i + d)) / 4294967295.0;
+  }
+  let x0 = mix(vec4<f32>(v[0], v[2], v[4], v[6]), vec4<f32>(v[1], v[3], v[5], v[7]), u.x);
+  let y0 = mix(x0.xz, x0.yw, u.y);
+  return mix(y0.x, y0.y, u.z);
+}
+
+// 立方体の辺の中点方向 12 本から勾配を選ぶ（Ken Perlin の improved noise と同じ 16 通り）
+fn grad3(h: u32, f: vec3<f32>) -> f32 {
+  let g = h & 15u;
+  let u = select(f.y, f.x, g < 8u);
+  let v = select(select(f.z, f.x, g == 12u || g == 14u), f.y, g < 4u);
+  return select(-u, u, (g & 1u) == 0u) + select(-v, v, (g & 2u) == 0u);
+}
+
+// 勾配ノイズ。おおよそ -1..1
+fn perlin3(p: vec3<f32>) -> f32 {
+  let i = vec3<i32>(floor(p));
+  let f = fract(p);
+  let u = quintic(f);
+  var v: array<f32, 8>;
+  for (var k = 0; k < 8; k = k + 1) {
+    let d = vec3<i32>(k & 1, (k >> 1) & 1, (k >> 2) & 1);
+    v[k] = grad3(lattice(i + d), f - vec3<f32>(d));
+  }
+  let x0 = mix(vec4<f32>(v[0], v[2], v[4], v[6]), vec4<f32>(v[1], v[3], v[5], v[7]), u.x);
+  let y0 = mix(x0.xz, x0.yw, u.y);
+  return mix(y0.x, y0.y, u.z);
+}
+
+fn mod289_3(x: vec3<f32>) -> vec3<f32> {
+  return x - floor(x * (1.0 / 289.0)) * 289.0;
+}
+
+fn mod289_4(x: vec4<f32>) -> vec4<f32> {
+  return x - floor(x * (1.0 / 289.0)) * 289.0;
+}
+
+fn permute(x: vec4<f32>) -> vec4<f32> {
+  return mod289_4(((x * 34.0) + 10.0) * x);
+}
+
+// 3次元シンプレックスノイズ（Ashima Arts / Stefan Gustavson の webgl-noise を移植）。おおよそ -1..1
+fn simplex3(v: vec3<f32>) -> f32 {
+  let C = vec2<f32>(1.0 / 6.0, 1.0 / 3.0);
+  let D = vec4<f32>(0.0, 0.5, 1.0, 2.0);
+
+  var i = floor(v + dot(v, C.yyy));
+  let x0 = v - i + dot(i, C.xxx);
+
+  let g = step(x0.yzx, x0.xyz);
+  let l = 1.0 - g;
+  let i1 = min(g.xyz, l.zxy);
+  let i2 = max(g.xyz, l.zxy);
+  let x1 = x0 - i1 + C.xxx;
+  let x2 = x0 - i2 + C.yyy;
+  let x3 = x0 - D.yyy;
+
+  // 勾配の並びは 289 格子で繰り返すので、種はその中で読む位置を選ぶ
+  let sd = seed_hash();
+  i = mod289_3(i + vec3<f32>(f32(sd % 289u), f32((sd / 289u) % 289u), f32((sd / 83521u) % 289u)));
+  let p = permute(permute(permute(
+            i.z + vec4<f32>(0.0, i1.z, i2.z, 1.0))
+          + i.y + vec4<f32>(0.0, i1.y, i2.y, 1.0))
+          + i.x + vec4<f32>(0.0, i1.x, i2.x, 1.0));
+
+  // 7x7 の点から勾配を作る
+  let ns = 0.142857142857 * D.wyz - D.xzx;
+  let j = p - 49.0 * floor(p * ns.z * ns.z);
+  let x_ = floor(j * ns.z);
+  let y_ = floor(j - 7.0 * x_);
+  let x = x_ * ns.x + ns.yyyy;
+  let y = y_ * ns.x + ns.yyyy;
+  let h = 1.0 - abs(x) - abs(y);
+  let b0 = vec4<f32>(x.xy, y.xy);
+  let b1 = vec4<f32>(x.zw, y.zw);
+  let s0 = floor(b0) * 2.0 + 1.0;
+  let s1 = floor(b1) * 2.0 + 1.0;
+  let sh = -step(h, vec4<f32>(0.0));
+  let a0 = b0.xzyw + s0.xzyw * sh.xxyy;
+  let a1 = b1.xzyw + s1.xzyw * sh.zzww;
+  let p0 = normalize(vec3<f32>(a0.xy, h.x));
+  let p1 = normalize(vec3<f32>(a0.zw, h.y));
+  let p2 = normalize(vec3<f32>(a1.xy, h.z));
+  let p3 = normalize(vec3<f32>(a1.zw, h.w));
+
+  var m = max(0.5 - vec4<f32>(dot(x0, x0), dot(x1, x1), dot(x2, x2), dot(x3, x3)), vec4<f32>(0.0));
+  m = m * m;
+  return 105.0 * dot(m * m, vec4<f32>(dot(p0, x0), dot(p1, x1), dot(p2, x2), dot(p3, x3)));
+}
+
 @fragment
 fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
-  let coord = in.uv * params.size;
-  let n = hash2(coord, f32(params.frame));
+  // ドリフトの向きは Y 上向き
+  let uv = vec2<f32>(in.uv.x, 1.0 - in.uv.y);
+  // ドリフトは 60 FPS 換算のピクセル/フレーム。速度変更で跳ばないよう時間から換算する
+  let tick = time() * NOISE_HZ;
+  let coord = uv * params.size + params.time_scale * tick;
+  // 滑らかなノイズは時間を3つ目の軸にして、模様そのものがゆっくり変わっていくようにする
+  // 格子は論理ピクセルで測り、高DPIでも模様の見た目の大きさを変えない
+  let p = vec3<f32>(coord / (NOISE_CELL * params.scale_factor), time() * FLOW_SPEED);
+  var base: f32;
+  switch params.noise_kind {
+    case 1u: { base = value3(p); }
+    // perlin は値が中央に寄りがちなので、ほかと同じくらいの幅に広げる
+    case 2u: { base = clamp(0.5 + 0.7 * perlin3(p), 0.0, 1.0); }
+    case 3u: { base = clamp(0.5 + 0.5 * simplex3(p), 0.0, 1.0); }
+    default: { base = hash2(coord, floor(tick) + f32(seed_hash() >> 16u)); }
+  }
+  // カーソルの近くほど明るく（mouse_force が負なら暗く）
+  let aspect = vec2<f32>(params.size.x / params.size.y, 1.0);
+  let d = (in.uv - params.mouse) * aspect;
+  let n = base * max(0.0, 1.0 + params.mouse_force * exp(-dot(d, d) * 30.0));
   return vec4<f32>(vec3<f32>(n), 1.0);
-}
\ No newline at end of file
+}
@@ -0,0 +1,107 @@
+@group(0) @binding(0) var scene: texture_2d<f32>;
+@group(0) @binding(1) var scene_samp: sampler;
+
+struct Post {
+  invert:       u32,
+  channel_mask: u32, // bit0=R, bit1=G, bit2=B（1でそのチャンネルを0に）
+  stop_count:   u32, // 2 未満ならパレットなし
+  segments:     u32, // 万華鏡の分割数。2 未満なら無効
+  stops:        array<vec4<f32>, 8>, // 線形 RGB
+  base:         vec4<f32>, // 黒の部分に敷く色
+  uv_mat:       vec4<f32>, // シーンを読む UV の 2x2 行列（列優先）
+  uv_offset:    vec4<f32>, // xy: 平行移動
+  center:       vec4<f32>, // xy: 万華鏡の中心
+  background:   vec4<f32>, // x: 重ねる不透明度, y: 背景画像の有無, zw: 出力の解像度
+}
+
+@group(0) @binding(2) var<uniform> post: Post;
+// 出力先が sRGB 形式でないとき true（renderer::encodes_srgb）。線形の値をここで sRGB にする
+override ENCODE_SRGB: bool = false;
+// --overlay: 出力の明るさを不透明度にして、暗いところからデスクトップが透けるようにする
+override TRANSPARENT: bool = false;
+@group(1) @binding(0) var background: texture_2d<f32>;
+
+// 画面を覆うように縦横比を保って拡大した背景画像。はみ出た分は切り落とす
+fn background_color(frag: vec2<f32>) -> vec3<f32> {
+  let screen = post.background.zw;
+  let img = vec2<f32>(textureDimensions(background));
+  let scale = max(screen.x / img.x, screen.y / img.y);
+  let uv = (frag / screen - 0.5) * screen / (img * scale) + 0.5;
+  return textureSampleLevel(background, scene_samp, uv, 0.0).rgb;
+}
+
+// UV を画面中心まわりに変換する。0..1 の外はサンプラで鏡映しに繰り返す
+@vertex
+fn vs_post(@builtin(vertex_index) vid: u32) -> VSOut {
+  var o = fullscreen(vid);
+  let m = mat2x2<f32>(post.uv_mat.xy, post.uv_mat.zw);
+  o.uv = m * (o.uv - 0.5) + 0.5 + post.uv_offset.xy;
+  return o;
+}
+
+// 輝度 t を等間隔に並んだ色の間で線形補間する
+fn palette(t: f32) -> vec3<f32> {
+  let n = post.stop_count;
+  let x = clamp(t, 0.0, 1.0) * f32(n - 1u);
+  let i = min(u32(floor(x)), n - 2u);
+  return mix(post.stops[i].rgb, post.stops[i + 1u].rgb, x - f32(i));
+}
+
+const PI: f32 = 3.14159265;
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+  let x = clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+  return select(1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055, x * 12.92, x <= vec3<f32>(0.0031308));
+}
+
+// 中心まわりの角度を segments 個の扇形に折りたたむ。隣り合う扇形は鏡映しなので境目がつながる
+fn kaleidoscope(uv: vec2<f32>) -> vec2<f32> {
+  let size = vec2<f32>(textureDimensions(scene));
+  let aspect = vec2<f32>(size.x / size.y, 1.0);
+  let p = (uv - post.center.xy) * aspect;
+  let seg = 2.0 * PI / f32(post.segments);
+  var a = atan2(p.y, p.x);
+  a = a - seg * floor(a / seg);
+  a = min(a, seg - a);
+  return post.center.xy + length(p) * vec2<f32>(cos(a), sin(a)) / aspect;
+}
+
+@fragment
+fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
+  var uv = in.uv;
+  if (post.segments >= 2u) {
+    uv = kaleidoscope(uv);
+  }
+  var c = textureSample(scene, scene_samp, uv).rgb;
+  // 背景画像に重ねるときはシーンの明るさをそのまま不透明度にする
+  let alpha = clamp(dot(c, vec3<f32>(0.2126, 0.7152, 0.0722)), 0.0, 1.0) * post.background.x;
+  if (post.stop_count >= 2u) {
+    c = palette(dot(c, vec3<f32>(0.2126, 0.7152, 0.0722)));
+  }
+  // スクリーン合成: 黒は base に、白は白のまま
+  c = vec3<f32>(1.0) - (vec3<f32>(1.0) - post.base.rgb) * (vec3<f32>(1.0) - clamp(c, vec3<f32>(0.0), vec3<f32>(1.0)));
+  // 反転はトーン調整の後、最終出力の直前に適用する
+  if (post.invert != 0u) {
+    c = vec3<f32>(1.0) - clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+  }
+  let m = post.channel_mask;
+  let keep = vec3<f32>(
+    select(1.0, 0.0, (m & 1u) != 0u),
+    select(1.0, 0.0, (m & 2u) != 0u),
+    select(1.0, 0.0, (m & 4u) != 0u)
+  );
+  c = c * keep;
+  if (post.background.y != 0.0) {
+    c = mix(background_color(in.pos.xy), c, alpha);
+  }
+  if (TRANSPARENT) {
+    // 乗算済みアルファ。合成は表示上の値で行われるので、sRGB にした色の一番明るい成分を不透明度にする
+    let s = linear_to_srgb(clamp(c, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let a = max(max(s.r, s.g), s.b);
+    return vec4<f32>(select(c, s, ENCODE_SRGB), a);
+  }
+  if (ENCODE_SRGB) {
+    c = linear_to_srgb(c);
+  }
+  return vec4<f32>(c, 1.0);
+}
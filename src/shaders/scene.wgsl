@@ -0,0 +1,64 @@
+// シーン用エフェクト共通の定義。fullscreen.wgsl の後、各エフェクトの前に連結する
+
+// めったに変わらない値
+struct Params {
+  size:         vec2<f32>, // 8B
+  time_scale:   vec2<f32>, // +8B 軸ごとのドリフト → 16B
+  refresh_hz:   f32,       // 表示先モニターのリフレッシュレート
+  star_density: f32,       // starfield: セルに星が入る確率 0..1
+  star_speed:   f32,       // starfield: 前進速度
+  rain_speed:   f32,       // matrix: 落下速度
+  rain_density: f32,       // matrix: 文字が流れる列の割合 0..1
+  gradient_angle:  f32,    // gradient: 向き（ラジアン、0 で左→右）
+  gradient_radial: u32,    // gradient: 0 以外なら中心からの放射状
+  particle_edge: f32,      // swarm: 0 以外なら縁を滑らかにした円で描く（縁の幅、ピクセル） → 48B
+  mouse:        vec2<f32>, // カーソル位置（UV、Y下向き）。動かすまでは画面中心
+  mouse_force:  f32,       // カーソルへの反応の強さ。負なら逆向き
+  noise_kind:   u32,       // noise: 0 hash, 1 value, 2 perlin, 3 simplex → 64B
+  seed:         u32,       // 乱数の種。同じ種なら同じ模様になる
+  bass:         f32,       // 音の強さ 0..1（--audio）
+  mid:          f32,
+  treble:       f32,
+  scale_factor: f32,       // DPI 倍率。size / scale_factor が論理ピクセルでの大きさ
+  edge:         u32,       // swarm の端の扱い。0: wrap, 1: bounce, 2: clamp
+  _pad0:        f32,
+  _pad1:        f32,       // → 96B
+}
+
+// 毎フレーム更新される値
+struct Frame {
+  frame:  u32,
+  time:   f32,       // アニメーション時間（秒）。速度変更をまたいでも連続
+  jitter: vec2<f32>, // TAA のサブピクセルずらし（ピクセル、Y下向き）。無効なら 0
+  dt:     f32,       // 前フレームからの秒数（アニメーション時間、0.1 まで）
+  _pad0:  f32,
+  _pad1:  f32,
+  _pad2:  f32,       // → 32B
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(1) @binding(0) var<uniform> frame: Frame;
+
+fn time() -> f32 {
+  return frame.time;
+}
+
+// 整数ハッシュ（PCG）
+fn pcg(v: u32) -> u32 {
+  let s = v * 747796405u + 2891336453u;
+  let w = ((s >> ((s >> 28u) + 4u)) ^ s) * 277803737u;
+  return (w >> 22u) ^ w;
+}
+
+// 種の 32 ビットすべてを混ぜたハッシュ。模様の乱数にこれを混ぜて種ごとに変える
+fn seed_hash() -> u32 {
+  return pcg(params.seed);
+}
+
+// シーン用の全画面三角形。TAA のときは模様を読む位置をフレームごとにずらす
+@vertex
+fn vs_scene(@builtin(vertex_index) vid: u32) -> VSOut {
+  var o = fullscreen(vid);
+  o.uv += frame.jitter / params.size;
+  return o;
+}
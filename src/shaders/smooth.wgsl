@@ -0,0 +1,41 @@
+@group(0) @binding(0) var cur: texture_2d<f32>;
+@group(0) @binding(1) var samp: sampler;
+
+struct Smooth {
+  factor: f32, // 前フレームの重み
+  clamp:  u32, // TAA: 0 以外なら前フレームを今の 3x3 近傍の範囲に収める
+  trail:  f32, // 0 より大きければ残像: 前フレームにこれを掛けて足す
+  _pad0:  f32,
+}
+
+@group(0) @binding(2) var<uniform> blend: Smooth;
+@group(0) @binding(3) var prev: texture_2d<f32>;
+
+// 動きベクトルがないので再投影は同じ位置。近傍から外れた履歴を切ってゴーストを抑える
+fn clamp_to_neighborhood(p: vec4<f32>, uv: vec2<f32>) -> vec4<f32> {
+  let dims = vec2<i32>(textureDimensions(cur));
+  let px = vec2<i32>(uv * vec2<f32>(dims));
+  var lo = vec4<f32>(1e9);
+  var hi = vec4<f32>(-1e9);
+  for (var y = -1; y <= 1; y++) {
+    for (var x = -1; x <= 1; x++) {
+      let c = textureLoad(cur, clamp(px + vec2<i32>(x, y), vec2<i32>(0), dims - 1), 0);
+      lo = min(lo, c);
+      hi = max(hi, c);
+    }
+  }
+  return clamp(p, lo, hi);
+}
+
+@fragment
+fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
+  let c = textureSample(cur, samp, in.uv);
+  var p = textureSample(prev, samp, in.uv);
+  if (blend.trail > 0.0) {
+    return vec4<f32>(c.rgb + p.rgb * blend.trail, c.a);
+  }
+  if (blend.clamp != 0u) {
+    p = clamp_to_neighborhood(p, in.uv);
+  }
+  return mix(c, p, blend.factor);
+}
@@ -0,0 +1,37 @@
+// 奥から手前へ流れる星。深さの違う層を重ねて視差を出す
+const LAYERS: i32 = 4;
+
+fn hash22(p: vec2<f32>) -> vec2<f32> {
+  var q = fract(p * vec2<f32>(123.34, 456.21));
+  q = q + dot(q, q + 45.32);
+  return fract(vec2<f32>(q.x * q.y, q.x + q.y));
+}
+
+// 1セルに最大1個の星。中心からの距離で明るさを決める
+fn star_layer(p: vec2<f32>) -> f32 {
+  let id = floor(p);
+  let f = fract(p) - 0.5;
+  let h = hash22(id);
+  if (h.x > params.star_density) {
+    return 0.0;
+  }
+  let offs = (hash22(id + 17.0) - 0.5) * 0.7;
+  let d = length(f - offs);
+  return smoothstep(0.08, 0.0, d) * (0.4 + 0.6 * h.y);
+}
+
+@fragment
+fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
+  let aspect = params.size.x / params.size.y;
+  let p = (in.uv - 0.5) * vec2<f32>(aspect, 1.0);
+  let t = time() * params.star_speed * 0.1;
+  var c = 0.0;
+  for (var i = 0; i < LAYERS; i = i + 1) {
+    // depth: 0 = 奥, 1 = 手前。手前ほど拡大されて外へ流れる
+    let depth = fract(f32(i) / f32(LAYERS) + t);
+    let zoom = mix(12.0, 0.5, depth);
+    let fade = smoothstep(0.0, 0.2, depth) * smoothstep(1.0, 0.8, depth);
+    c = c + star_layer(p * zoom + f32(i) * 31.7) * fade;
+  }
+  return vec4<f32>(vec3<f32>(c), 1.0);
+}
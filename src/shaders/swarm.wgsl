@@ -0,0 +1,69 @@
+// エージェントを小さなぼかした点、または縁の滑らかな円として加算合成で描く（compute.wgsl の後に連結）
+const RADIUS_PX: f32 = 3.0;
+// 音に合わせた点の膨らみ（bass）と明るさ（mid, treble）の強さ
+const BASS_PULSE:     f32 = 0.5;
+const MID_GLOW:       f32 = 0.6;
+const TREBLE_SPARKLE: f32 = 0.4;
+
+struct AgentOut {
+  @builtin(position) pos: vec4<f32>,
+  @location(0) local: vec2<f32>, // 点の中心からの位置 -1..1
+  @location(1) speed: f32,       // 0..1
+  @location(2) fade: f32,        // 生まれた直後と死ぬ直前は暗く
+}
+
+// 円のときは縁のぼかしがはみ出す分だけ四角形を広げる
+fn quad_radius() -> f32 {
+  return RADIUS_PX * (1.0 + params.bass * BASS_PULSE) + params.particle_edge;
+}
+
+// スロット 0 が1つ前のステップ、1 が最新。固定ステップのときはその間を補間する
+@vertex
+fn vs_agent(
+  @builtin(vertex_index) vid: u32,
+  @location(0) prev_pos: vec2<f32>,
+  @location(1) prev_vel: vec2<f32>,
+  @location(2) prev_life: vec2<f32>, // x: age, y: life
+  @location(3) cur_pos: vec2<f32>,
+  @location(4) cur_vel: vec2<f32>,
+  @location(5) cur_life: vec2<f32>,
+  @location(6) prev_speed: f32,
+  @location(7) cur_speed: f32,
+) -> AgentOut {
+  var corners = array<vec2<f32>, 6>(
+    vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(-1.0, 1.0),
+    vec2<f32>(-1.0, 1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0)
+  );
+  let c = corners[vid];
+  let aspect = params.size.x / params.size.y;
+  let extent = vec2<f32>(aspect, 1.0);
+  // 端で回り込んだ直後は最短方向に補間する。生まれ直した直後は補間しない
+  let reborn = cur_life.x < prev_life.x;
+  let t = select(sim.alpha, 1.0, reborn);
+  let pos = wrap_delta(cur_pos - wrap_delta(cur_pos - prev_pos, extent) * (1.0 - t), extent);
+  let age = mix(prev_life.x, cur_life.x, t);
+  var o: AgentOut;
+  let jitter = frame.jitter * vec2<f32>(2.0, -2.0) / params.size;
+  let r = quad_radius();
+  o.pos = vec4<f32>(pos.x / aspect + c.x * r * 2.0 / params.size.x + jitter.x,
+                    pos.y + c.y * r * 2.0 / params.size.y + jitter.y, 0.0, 1.0);
+  // local は点の半径（低音で膨らむ）で 1 になる単位
+  o.local = c * r / (RADIUS_PX * (1.0 + params.bass * BASS_PULSE));
+  o.speed = mix(prev_speed, cur_speed, t);
+  o.fade = fade(age, cur_life.y);
+  return o;
+}
+
+@fragment
+fn fs_agent(in: AgentOut) -> @location(0) vec4<f32> {
+  var shape = exp(-4.0 * dot(in.local, in.local));
+  if (params.particle_edge > 0.0) {
+    // 円の縁までの距離を画面上のピクセル数に直して覆う割合を出す。解像度によらず縁の幅が一定になる
+    let dist = length(in.local) - 1.0;
+    let w = max(fwidth(dist) * params.particle_edge, 1e-4);
+    shape = clamp(0.5 - dist / w, 0.0, 1.0);
+  }
+  let glow = 1.0 + params.mid * MID_GLOW + params.treble * TREBLE_SPARKLE;
+  let a = shape * (0.35 + 0.65 * in.speed) * in.fade * glow;
+  return vec4<f32>(vec3<f32>(a), 1.0);
+}
@@ -1,213 +1,655 @@
+use std::fmt;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use bytemuck::{Pod, Zeroable};
-use wgpu::util::DeviceExt;
 use winit::window::Window;
+
+use crate::config::{ColorSpace, Config};
+use crate::effects;
+use crate::export::{ExportError, RgbaImage};
+use crate::gputime::GpuTimer;
+use crate::palette::Palette;
+use crate::platform::Layer;
+use crate::presets::Preset;
+use crate::renderer::{
+    DEFAULT_REFRESH_HZ, Renderer, encodes_srgb, find_adapter, request_device, srgb_path,
+    supported_msaa,
+};
+use crate::swarm;
+
 pub struct State {
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
     pub surface: wgpu::Surface<'static>,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub window: Arc<Window>,
-    pipeline: wgpu::RenderPipeline,
-    params_buf: wgpu::Buffer,
-    params_bg: wgpu::BindGroup,
-    frame: u32,
+    pub renderer: Renderer,
+    pub timings: FrameTimings,
+    hidpi: HiDpi,
+    // アニメーションの基準時刻。リフレッシュレートや描画の間引きによらず実時間で進める
+    clock: Instant,
+    // 一時停止した時刻。止めている間はアニメーション時間を進めない
+    paused_at: Option<Instant>,
+    // GPU 時間の計測。TIMESTAMP_QUERY がなければ None
+    gpu_timer: Option<GpuTimer>,
+    // ドライバのリセットなどでデバイスが失われたら true。State ごと作り直すしかない
+    device_lost: Arc<AtomicBool>,
+    // 壁紙化で用意した背景レイヤー（Wayland）。あればウィンドウではなくこちらに描く
+    layer: Option<Layer>,
+}
+
+// 既定では 4K (3840x2160) を超えると高DPIとみなす
+const DEFAULT_HIDPI_THRESHOLD: u64 = 3840 * 2160;
+
+// 高解像度サーフェスで render_scale を下げるかどうかの設定
+struct HiDpi {
+    // 明示指定された render_scale。こちらが常に優先
+    manual: Option<f32>,
+    auto: bool,
+    threshold: u64,
+    // 提案のログは1回だけ出す
+    noted: bool,
+}
+
+// ディスプレイ構成の変更直後などはサーフェス作成が一時的に失敗することがある
+const SURFACE_CREATE_ATTEMPTS: u32 = 5;
+const SURFACE_CREATE_DELAY: Duration = Duration::from_millis(200);
+
+// ウィンドウか、背景レイヤーがあればそちらからサーフェスを作る
+fn surface_for(
+    instance: &wgpu::Instance,
+    window: &Arc<Window>,
+    layer: Option<&Layer>,
+) -> Result<wgpu::Surface<'static>, wgpu::CreateSurfaceError> {
+    match layer {
+        // レイヤーは State と一緒に残り、サーフェスより先には破棄しない
+        Some(layer) => unsafe { instance.create_surface_unsafe(layer.target()) },
+        None => instance.create_surface(window.clone()),
+    }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
-struct Params {
-    size: [f32; 2],
-    frame: u32,
-    _pad: u32,
+fn create_surface(
+    instance: &wgpu::Instance,
+    window: &Arc<Window>,
+    layer: Option<&Layer>,
+) -> Result<wgpu::Surface<'static>, String> {
+    let mut attempt = 1;
+    loop {
+        match surface_for(instance, window, layer) {
+            Ok(surface) => return Ok(surface),
+            Err(e) if attempt < SURFACE_CREATE_ATTEMPTS => {
+                eprintln!(
+                    "Surface creation failed ({e}); retrying ({attempt}/{})",
+                    SURFACE_CREATE_ATTEMPTS - 1
+                );
+                std::thread::sleep(SURFACE_CREATE_DELAY);
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "surface creation failed after {SURFACE_CREATE_ATTEMPTS} attempts: {e}"
+                ));
+            }
+        }
+    }
+}
+
+// 選べる present モード。Fifo はどのサーフェスでも使える
+pub const PRESENT_MODES: [wgpu::PresentMode; 3] = [
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Mailbox,
+    wgpu::PresentMode::Immediate,
+];
+
+// サーフェスが対応していなければ警告して Fifo にする
+fn supported_present_mode(
+    mode: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    if supported.contains(&mode) {
+        return mode;
+    }
+    eprintln!("Present mode {mode:?} is not supported by this surface; using Fifo.");
+    wgpu::PresentMode::Fifo
+}
+
+// State を作れなかった理由。ヘッドレス環境や壊れたドライバでもパニックせずに報告する
+#[derive(Debug)]
+pub enum StateError {
+    // ウィンドウに描画先のサーフェスを作れない
+    Surface(String),
+    // どのバックエンドにもサーフェスに描けるアダプタがない
+    Adapter(String),
+    // アダプタはあるがデバイスを開けない
+    Device(String),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Surface(e) => write!(f, "could not create a window surface: {e}"),
+            Self::Adapter(e) => write!(
+                f,
+                "no compatible GPU adapter found ({e}); a driver with Vulkan, Metal, DX12 or OpenGL support is required"
+            ),
+            Self::Device(e) => write!(f, "the GPU adapter could not open a device: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+type Gpu = (
+    wgpu::Instance,
+    wgpu::Surface<'static>,
+    wgpu::Adapter,
+    wgpu::Device,
+    wgpu::Queue,
+);
+
+async fn open_gpu(
+    window: &Arc<Window>,
+    layer: Option<&Layer>,
+    backends: wgpu::Backends,
+    name: Option<&str>,
+) -> Result<Gpu, StateError> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+    let surface = create_surface(&instance, window, layer).map_err(StateError::Surface)?;
+    let adapter = find_adapter(&instance, name, Some(&surface))
+        .await
+        .map_err(|e| StateError::Adapter(e.to_string()))?;
+    let (device, queue) = request_device(&adapter)
+        .await
+        .map_err(|e| StateError::Device(e.to_string()))?;
+    Ok((instance, surface, adapter, device, queue))
+}
+
+// 埋め込み用のフレームごとのフック。引数は直前に描いたフレーム番号
+pub type FrameHook = Box<dyn FnMut(&mut State, u32) + Send>;
+
+// 直近フレームのCPU側計測。wait は acquire と present でのブロック時間
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+    pub cpu: Duration,
+    pub wait: Duration,
 }
 
 impl State {
-    pub async fn new(window: Window) -> Self {
-        let window = Arc::new(window);
-        let size = window.inner_size();
-
-        let instance = wgpu::Instance::default();
-        let surface = instance
-            .create_surface(window.clone())
-            .expect("create surface");
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                compatible_surface: Some(&surface),
-                ..Default::default()
-            })
-            .await
-            .expect("adapter");
+    pub async fn new(window: Arc<Window>, cfg: &Config) -> Result<Self, StateError> {
+        let layer = crate::platform::take_layer(&window);
+        let size = layer
+            .as_ref()
+            .map_or_else(|| window.inner_size(), Layer::size);
+
+        // まずすべてのバックエンドで探し、だめなら GL だけで探し直す
+        let name = cfg.adapter.as_deref();
+        let mut gpu = open_gpu(&window, layer.as_ref(), wgpu::Backends::all(), name).await;
+        if let Err(e) = &gpu {
+            eprintln!("{e}; retrying with OpenGL");
+            gpu = open_gpu(&window, layer.as_ref(), wgpu::Backends::GL, name).await;
+        }
+        let (instance, surface, adapter, device, queue) = gpu?;
+        if cfg.verbose {
+            eprintln!("Granted limits: {:#?}", device.limits());
+        }
 
-        let (device, queue) = adapter
-            .request_device(&Default::default())
-            .await
-            .expect("device");
+        // ストレージバッファなどが使えないバックエンドでは noise に落とす
+        let flags = adapter.get_downlevel_capabilities().flags;
+        let limits = device.limits();
+        if cfg.verbose {
+            eprintln!(
+                "Effects available: {}",
+                effects::available(flags, &limits).join(", ")
+            );
+        }
+        let effect = effects::select(cfg.effect.as_deref(), flags, &limits);
+        let cfg = &Config {
+            effect: Some(effect.name.to_string()),
+            msaa: supported_msaa(&adapter, &device, cfg.msaa),
+            workgroup: swarm::workgroup_size(&limits, cfg.workgroup).ok(),
+            ..cfg.clone()
+        };
 
         let caps = surface.get_capabilities(&adapter);
+        // linear でも浮動小数点（HDR）の形式は選ばない
+        let wanted = |f: &wgpu::TextureFormat| match cfg.color_space {
+            ColorSpace::Srgb => f.is_srgb(),
+            ColorSpace::Linear => encodes_srgb(*f),
+        };
         let format = caps
             .formats
             .iter()
             .copied()
-            .find(|f| f.is_srgb())
+            .find(wanted)
             .unwrap_or(caps.formats[0]);
+        if cfg.verbose || !format.is_srgb() {
+            eprintln!("Surface format {format:?}; {}.", srgb_path(format));
+        }
+
+        // --overlay は乗算済みアルファで合成できるサーフェスでだけ透過させる
+        let alpha_mode = if cfg.overlay {
+            caps.alpha_modes
+                .iter()
+                .copied()
+                .find(|m| *m == wgpu::CompositeAlphaMode::PreMultiplied)
+        } else {
+            None
+        };
+        if cfg.overlay && alpha_mode.is_none() {
+            eprintln!(
+                "Surface cannot blend with the desktop (alpha modes {:?}); drawing opaque.",
+                caps.alpha_modes
+            );
+        }
+        let cfg = &Config {
+            overlay: alpha_mode.is_some(),
+            ..cfg.clone()
+        };
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format,
             width: size.width.max(1),
             height: size.height.max(1),
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: caps.alpha_modes[0],
+            present_mode: supported_present_mode(cfg.present_mode, &caps.present_modes),
+            alpha_mode: alpha_mode.unwrap_or(caps.alpha_modes[0]),
             view_formats: vec![],
             desired_maximum_frame_latency: 1,
         };
 
         surface.configure(&device, &config);
 
-        let params_init = Params {
-            frame: 0,
-            _pad: 0,
-            size: [config.width as f32, config.height as f32],
-        };
-        let params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("params"),
-            contents: bytemuck::bytes_of(&params_init),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("bgl"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let lost = device_lost.clone();
+        device.set_device_lost_callback(move |reason, msg| {
+            // 自分で破棄したとき（作り直しや終了）も Destroyed で呼ばれるので数えない
+            if reason != wgpu::DeviceLostReason::Destroyed {
+                eprintln!("GPU device lost ({reason:?}): {msg}");
+                lost.store(true, Ordering::Release);
+            }
         });
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        if cfg.verbose && gpu_timer.is_none() {
+            eprintln!("GPU timestamps are not supported; GPU frame time unavailable.");
+        }
+        let renderer = Renderer::new(device, queue, format, config.width, config.height, cfg);
 
-        let params_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bg"),
-            layout: &bgl,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            }],
-        });
+        let mut state = Self {
+            instance,
+            adapter,
+            surface,
+            config,
+            window,
+            renderer,
+            timings: FrameTimings::default(),
+            hidpi: HiDpi {
+                manual: cfg.render_scale,
+                auto: cfg.auto_render_scale,
+                threshold: cfg.hidpi_threshold.unwrap_or(DEFAULT_HIDPI_THRESHOLD),
+                noted: false,
+            },
+            clock: Instant::now(),
+            paused_at: None,
+            gpu_timer,
+            device_lost,
+            layer,
+        };
+        state.update_refresh_rate();
+        state.update_scale_factor();
+        state.update_render_scale();
+        Ok(state)
+    }
 
-        let shader_src = include_str!("shaders/noise.wgsl");
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("noise"),
-            source: wgpu::ShaderSource::Wgsl(shader_src.into()),
-        });
+    // タイトル用の短いアダプタ名。"llvmpipe (LLVM 15.0.7, 256 bits) · Gl" のような形
+    pub fn adapter_summary(&self) -> String {
+        let info = self.adapter.get_info();
+        let mut name = info.name;
+        if name.chars().count() > 40 {
+            name = name.chars().take(39).collect::<String>() + "…";
+        }
+        format!("{name} · {:?}", info.backend)
+    }
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("pipe"),
-            layout: Some(
-                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("layout"),
-                    bind_group_layouts: &[&bgl],
-                    push_constant_ranges: &[],
-                }),
-            ),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-            cache: None,
+    // ウィンドウのいるモニターのリフレッシュレートを Params に反映する。変わったら新しい値を返す
+    pub fn update_refresh_rate(&mut self) -> Option<f32> {
+        let hz = self
+            .window
+            .current_monitor()
+            .and_then(|m| m.refresh_rate_millihertz())
+            .map_or(DEFAULT_REFRESH_HZ, |mhz| mhz as f32 / 1000.0);
+        if hz == self.renderer.refresh_hz() {
+            return None;
+        }
+        self.renderer.set_refresh_hz(hz);
+        Some(hz)
+    }
+
+    // ウィンドウ（背景レイヤーならその出力）の DPI 倍率を Params に反映する。モニター間の移動や設定の変更で変わる
+    pub fn update_scale_factor(&mut self) {
+        let scale = self
+            .layer
+            .as_ref()
+            .map_or_else(|| self.window.scale_factor(), Layer::scale_factor);
+        self.renderer.set_scale_factor(scale as f32);
+    }
+
+    // ドライバ更新などでサーフェス自体が無効になった場合に、同じウィンドウから作り直す
+    pub fn recreate_surface(&mut self) -> Result<(), String> {
+        let surface = surface_for(&self.instance, &self.window, self.layer.as_ref())
+            .map_err(|e| e.to_string())?;
+        if !self.adapter.is_surface_supported(&surface) {
+            return Err("new surface is not supported by the current adapter".into());
+        }
+        // 古いスワップチェーンを先に破棄してから構成する
+        self.surface = surface;
+        self.surface.configure(&self.renderer.device, &self.config);
+        Ok(())
+    }
+
+    // サーフェスが対応するフォーマットのうち、描画先にできるものだけを返す
+    pub fn usable_formats(&self) -> Vec<wgpu::TextureFormat> {
+        self.surface
+            .get_capabilities(&self.adapter)
+            .formats
+            .into_iter()
+            .filter(|f| {
+                matches!(
+                    f.sample_type(None, None),
+                    Some(wgpu::TextureSampleType::Float { .. })
+                ) && self
+                    .adapter
+                    .get_texture_format_features(*f)
+                    .allowed_usages
+                    .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+            })
+            .collect()
+    }
+
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) -> Result<(), String> {
+        if !self.usable_formats().contains(&format) {
+            return Err(format!("{format:?} is not usable with this surface"));
+        }
+        self.config.format = format;
+        self.surface.configure(&self.renderer.device, &self.config);
+        self.renderer.set_target_format(format);
+        Ok(())
+    }
+
+    // 対応していないモードなら Fifo になる。実際に使うモードを返す
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let caps = self.surface.get_capabilities(&self.adapter);
+        self.config.present_mode = supported_present_mode(mode, &caps.present_modes);
+        self.surface.configure(&self.renderer.device, &self.config);
+        self.config.present_mode
+    }
+
+    // V: サーフェスが対応する present モードを順に切り替える
+    pub fn cycle_present_mode(&mut self) {
+        let caps = self.surface.get_capabilities(&self.adapter);
+        let modes: Vec<_> = PRESENT_MODES
+            .into_iter()
+            .filter(|m| caps.present_modes.contains(m))
+            .collect();
+        let i = modes.iter().position(|m| *m == self.config.present_mode);
+        let next = modes[i.map_or(0, |i| (i + 1) % modes.len())];
+        eprintln!("Present mode: {:?}", self.set_present_mode(next));
+    }
+
+    // 再開したときは止めていた時間だけ基準時刻をずらし、止めた場面から続ける
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(Instant::now()),
+            (false, Some(t)) => {
+                self.clock += t.elapsed();
+                self.paused_at = None;
+            }
+            _ => (),
+        }
+    }
+
+    // 今のフレームを出力解像度の RGBA8 (sRGB) で取り出す
+    pub fn capture_frame(&mut self) -> Result<RgbaImage, ExportError> {
+        let [width, height] = self.renderer.output_size();
+        Ok(RgbaImage {
+            width,
+            height,
+            pixels: self.renderer.read_rgba8()?,
+        })
+    }
+
+    pub fn reload_shader(&mut self, effect: usize, source: &str) -> Result<(), String> {
+        self.renderer.reload_shader(effects::get(effect)?, source)
+    }
+
+    // GPU デバイスを失った（ドライバのリセットなど）。作り直すまで描画できない
+    pub fn device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Acquire)
+    }
+
+    // 不具合報告用。サーフェスの状態とレンダラーの中身を1つのブロックにする
+    pub fn dump(&self, fps: Option<f64>) -> String {
+        let fps = fps.map_or("-".to_string(), |f| format!("{f:.1}"));
+        let gpu = self
+            .gpu_ms()
+            .map_or("-".to_string(), |ms| format!("{ms:.2} ms"));
+        format!(
+            "--- state ---\n\
+             surface: {}x{} {:?} {:?}\n\
+             fps: {fps}\n\
+             gpu: {gpu}\n\
+             {}\n\
+             -------------",
+            self.config.width,
+            self.config.height,
+            self.config.format,
+            self.config.present_mode,
+            self.renderer.dump(),
+        )
+    }
+
+    // 最後に測れたフレームの GPU 時間（ミリ秒）。測れないアダプタでは None
+    pub fn gpu_ms(&self) -> Option<f64> {
+        self.gpu_timer.as_ref().and_then(GpuTimer::last_ms)
+    }
+
+    pub fn cycle_format(&mut self) {
+        let formats = self.usable_formats();
+        let Some(i) = formats.iter().position(|f| *f == self.config.format) else {
+            return;
+        };
+        let next = formats[(i + 1) % formats.len()];
+        match self.set_format(next) {
+            Ok(()) => eprintln!("Surface format: {next:?} ({})", srgb_path(next)),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    // ホットキー・パラメータ・起動時の指定はどれもここを通してエフェクトを切り替える
+    pub fn set_effect(&mut self, index: usize) -> Result<(), String> {
+        let effect = effects::get(index)?;
+        let flags = self.adapter.get_downlevel_capabilities().flags;
+        if let Some(why) = effect.unsupported(flags, &self.renderer.device.limits()) {
+            return Err(format!(
+                "effect {:?} is not supported by this adapter ({why})",
+                effect.name
+            ));
+        }
+        self.renderer.set_effect(effect);
+        Ok(())
+    }
+
+    // パラメータ入力（パイプ・OSC・MIDI）の入口。effect だけは番号としてここで扱う
+    pub fn set_param(&mut self, name: &str, v: f32) -> Result<(), String> {
+        if name != "effect" {
+            return self.renderer.set_param(name, v);
+        }
+        if v < 0.0 || v.fract() != 0.0 {
+            return Err(format!(
+                "effect index must be a non-negative integer, got {v}"
+            ));
+        }
+        self.set_effect(v as usize)
+    }
+
+    // ウィンドウ座標（物理ピクセル）のカーソル位置をシーンの UV にして渡す
+    pub fn set_mouse(&mut self, x: f64, y: f64) {
+        let (w, h) = (self.config.width.max(1), self.config.height.max(1));
+        self.renderer
+            .set_mouse((x / w as f64) as f32, (y / h as f64) as f32);
+    }
+
+    // 使えないエフェクトは飛ばして次へ
+    pub fn cycle_effect(&mut self) {
+        let start = effects::index_of(self.renderer.effect());
+        for step in 1..=effects::EFFECTS.len() {
+            let i = (start + step) % effects::EFFECTS.len();
+            if self.set_effect(i).is_ok() {
+                eprintln!("Effect: {}", effects::EFFECTS[i].name);
+                return;
+            }
+        }
+    }
+
+    // noise のプリセットを当てる。ほかのエフェクトなら noise に切り替える。
+    // 模様の種類は uniform で選ぶのでパイプラインは作り直さない
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        if self.renderer.effect().name != "noise"
+            && let Some(noise) = effects::find("noise")
+        {
+            self.renderer.set_effect(noise);
+        }
+        self.renderer.set_noise(preset.noise);
+        self.renderer.set_palette(&Palette {
+            stops: preset.palette.to_vec(),
         });
+        self.renderer.set_speed(preset.speed);
+    }
 
-        Self {
-            surface,
-            device,
-            queue,
-            config,
-            window: window.into(),
-            pipeline,
-            params_buf,
-            params_bg,
-            frame: 0,
+    // 背景レイヤーに描いているか
+    pub fn on_layer(&self) -> bool {
+        self.layer.is_some()
+    }
+
+    // 背景レイヤーの大きさや倍率は winit を通らずに変わる。閉じられたら普通のウィンドウに戻す
+    fn poll_layer(&mut self) {
+        let Some(layer) = self.layer.as_mut() else {
+            return;
+        };
+        let changed = layer.poll();
+        if layer.closed() {
+            eprintln!("The compositor closed the background layer; using a normal window.");
+            let layer = self.layer.take();
+            if let Err(e) = self.recreate_surface() {
+                // 古いサーフェスがまだレイヤーに描いているので手放さない
+                eprintln!("Could not switch to the window: {e}");
+                self.layer = layer;
+                return;
+            }
+            drop(layer);
+            self.window.set_visible(true);
+            let size = self.window.inner_size();
+            self.resize(size.width, size.height);
+        } else if changed {
+            let size = layer.size();
+            self.resize(size.width, size.height);
         }
     }
 
     pub fn resize(&mut self, w: u32, h: u32) {
+        // 背景レイヤーに描いているなら、（表示されない）ウィンドウではなくレイヤーの大きさに合わせる
+        let (w, h) = self
+            .layer
+            .as_ref()
+            .map_or((w, h), |l| (l.size().width, l.size().height));
         if w == 0 || h == 0 {
             return;
         }
         self.config.width = w;
         self.config.height = h;
-        self.surface.configure(&self.device, &self.config);
+        self.surface.configure(&self.renderer.device, &self.config);
+        if let Some(layer) = &self.layer {
+            layer.apply_scale();
+        }
+        self.renderer.resize(w, h);
+        self.update_scale_factor();
+        self.update_render_scale();
+    }
 
-        let p = Params {
-            frame: self.frame,
-            _pad: 0,
-            size: [w as f32, h as f32],
-        };
-        self.queue
-            .write_buffer(&self.params_buf, 0, bytemuck::bytes_of(&p));
+    // 画素数がしきい値を超えたら、しきい値相当まで落とす倍率を提案（--auto-render-scale なら適用）
+    fn update_render_scale(&mut self) {
+        if self.hidpi.manual.is_some() {
+            return;
+        }
+        let (w, h) = (self.config.width, self.config.height);
+        let pixels = w as u64 * h as u64;
+        if pixels <= self.hidpi.threshold {
+            self.renderer.set_render_scale(1.0);
+            return;
+        }
+        // 0.05 刻みに切り捨て
+        let scale = (self.hidpi.threshold as f64 / pixels as f64).sqrt();
+        let scale = ((scale * 20.0).floor() / 20.0).max(0.1) as f32;
+        if self.hidpi.auto {
+            if scale != self.renderer.render_scale() {
+                eprintln!(
+                    "High-DPI surface {w}x{h} ({pixels} px > {}); render_scale {scale}",
+                    self.hidpi.threshold
+                );
+                self.renderer.set_render_scale(scale);
+            }
+        } else if !self.hidpi.noted {
+            eprintln!(
+                "High-DPI surface {w}x{h} ({pixels} px > {}); consider --render-scale {scale} \
+                 or --auto-render-scale",
+                self.hidpi.threshold
+            );
+            self.hidpi.noted = true;
+        }
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        self.frame = self.frame.wrapping_add(1);
-
-        let p = Params {
-            frame: self.frame,
-            _pad: 0,
-            size: [self.config.width as f32, self.config.height as f32],
-        };
-        self.queue
-            .write_buffer(&self.params_buf, 0, bytemuck::bytes_of(&p));
-
+        self.poll_layer();
+        let t_acquire = Instant::now();
         let output = self.surface.get_current_texture()?;
+        let t_work = Instant::now();
         let view = output.texture.create_view(&Default::default());
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("encoder"),
-            });
+        let now = self.paused_at.unwrap_or_else(Instant::now);
+        self.renderer.set_elapsed((now - self.clock).as_secs_f32());
+        let mut timer = self.gpu_timer.as_mut();
+        if let Some(t) = timer.as_mut()
+            && !t.ready(&self.renderer.device)
         {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("noise"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    depth_slice: None,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            rpass.set_pipeline(&self.pipeline);
-            rpass.set_bind_group(0, &self.params_bg, &[]);
-            rpass.draw(0..3, 0..1);
-        }
-        self.queue.submit(Some(encoder.finish()));
+            timer = None;
+        }
+        self.renderer.render_timed(&view, timer.as_deref());
+        if let Some(t) = timer {
+            t.finish();
+        }
+
+        let t_present = Instant::now();
         output.present();
+        let t_end = Instant::now();
+
+        self.timings = FrameTimings {
+            cpu: t_present - t_work,
+            wait: (t_work - t_acquire) + (t_end - t_present),
+        };
         Ok(())
     }
 }
+
+impl Drop for State {
+    // 作り直しで捨てられても、背景レイヤーは次の State が引き継ぐ
+    fn drop(&mut self) {
+        if let Some(layer) = self.layer.take()
+            && !layer.closed()
+        {
+            crate::platform::keep_layer(&self.window, layer);
+        }
+    }
+}
@@ -0,0 +1,577 @@
+// 群れのシミュレーション。エージェントをコンピュートで更新し、インスタンス描画で点として重ねる
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::config::Config;
+use crate::gputime::GpuTimer;
+
+pub const DEFAULT_AGENTS: u32 = 1024;
+// compute.wgsl に書いてあるワークグループの大きさ。--workgroup で置き換える
+const WORKGROUP_SIZE: u32 = 64;
+// compute.wgsl の MAX_SPEED。色に使う速さの初期値を出す
+const MAX_SPEED: f32 = 0.35;
+// 固定ステップで1フレームに進める上限。遅れがこれを超えた分は捨てる
+const MAX_STEPS: u32 = 8;
+
+// compute.wgsl の Agent と同じ並び
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Agent {
+    pos: [f32; 2],
+    vel: [f32; 2],
+    // 生まれてからの秒数と寿命。寿命 0 なら死なない
+    age: f32,
+    life: f32,
+    // 色に使う速さ 0..1（speed_smooth で均したもの）
+    speed: f32,
+    _pad: f32,
+}
+
+// compute.wgsl の Sim
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SimParams {
+    dt: f32,
+    alpha: f32,
+    // 寿命の範囲（秒）。life_max が 0 なら無効
+    life_min: f32,
+    life_max: f32,
+    speed_smooth: f32,
+    min_dist: f32,
+    // 生きているエージェントの数。バッファの残りは使わない
+    count: u32,
+    _pad: f32,
+}
+
+const _: () = assert!(size_of::<SimParams>().is_multiple_of(16));
+
+// Config のうち群れに関わる値。エフェクトを切り替えたときに作り直せるよう Renderer が持つ
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub agents: u32,
+    pub fixed_dt: Option<f32>,
+    pub lifetime: [f32; 2],
+    pub speed_smooth: f32,
+    pub min_dist: f32,
+    // 面積に合わせて増減するときのエージェント数の上限。None なら agents のまま
+    pub max_agents: Option<u32>,
+    // cs_main のワークグループの大きさ（--workgroup）。workgroup_size で決めた値にしておく
+    pub workgroup: u32,
+    // 描画のサンプル数（--msaa）。1 なら MSAA なし。アダプタが対応している値にしておく
+    pub samples: u32,
+}
+
+impl Settings {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            agents: cfg.agents.unwrap_or(DEFAULT_AGENTS).max(1),
+            fixed_dt: cfg.fixed_dt.filter(|dt| *dt > 0.0),
+            lifetime: cfg.lifetime.unwrap_or([0.0; 2]),
+            speed_smooth: cfg.speed_smooth,
+            min_dist: cfg.min_dist,
+            max_agents: cfg.scale_agents.then(|| {
+                let agents = cfg.agents.unwrap_or(DEFAULT_AGENTS).max(1);
+                cfg.max_agents.unwrap_or(agents * 4).max(agents)
+            }),
+            workgroup: cfg.workgroup.unwrap_or(WORKGROUP_SIZE),
+            samples: cfg.msaa.max(1),
+        }
+    }
+}
+
+pub struct Swarm {
+    pub agent_count: u32,
+    compute: wgpu::ComputePipeline,
+    workgroup: u32,
+    render: wgpu::RenderPipeline,
+    sim_buf: wgpu::Buffer,
+    sim_bg: wgpu::BindGroup,
+    buffers: [wgpu::Buffer; 2],
+    // bind_groups[i]: buffers[i] を読んで buffers[1 - i] に書く
+    bind_groups: [wgpu::BindGroup; 2],
+    // 最新の状態が入っている方。もう一方は1つ前のステップ
+    cur: usize,
+    // 指定があればフレームレートによらずこの刻みで進める（秒）
+    fixed_dt: Option<f32>,
+    // 固定ステップで進めた回数。アニメーション時間との差が accumulator になる
+    steps: u64,
+    lifetime: [f32; 2],
+    speed_smooth: f32,
+    min_dist: f32,
+    max_agents: Option<u32>,
+    // 面積に合わせるときの基準（作ったときのエージェント数とシーンの大きさ）と今の大きさ
+    base: (u32, [u32; 2]),
+    size: [u32; 2],
+    // 増やしたエージェントの配置を毎回変えるための通し番号
+    spawned: u32,
+    // MSAA のときの描画先。描いたあとシーンのテクスチャに解決する
+    format: wgpu::TextureFormat,
+    samples: u32,
+    msaa: Option<wgpu::TextureView>,
+}
+
+impl Swarm {
+    // scene_bgl は Params/Frame 用（group 0, 1）。size はシーンの解像度で、初期配置にも使う
+    pub fn new(
+        device: &wgpu::Device,
+        scene_bgl: &wgpu::BindGroupLayout,
+        source: &str,
+        format: wgpu::TextureFormat,
+        settings: &Settings,
+        [w, h]: [u32; 2],
+    ) -> Self {
+        let Settings {
+            agents: agent_count,
+            fixed_dt,
+            lifetime,
+            speed_smooth,
+            min_dist,
+            max_agents,
+            workgroup,
+            samples,
+        } = *settings;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("swarm"),
+            source: wgpu::ShaderSource::Wgsl(with_workgroup(source, workgroup).into()),
+        });
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let sim_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sim_bgl"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let agents_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("agents_bgl"),
+            entries: &[storage(0, true), storage(1, false)],
+        });
+
+        let compute = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("swarm_step"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("swarm_step"),
+                    bind_group_layouts: &[scene_bgl, scene_bgl, &sim_bgl, &agents_bgl],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        // 加算合成で重なったところほど明るくする
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let render = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("swarm_draw"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("swarm_draw"),
+                    bind_group_layouts: &[scene_bgl, scene_bgl, &sim_bgl],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_agent"),
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: size_of::<Agent>() as u64,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x2, 1 => Float32x2, 2 => Float32x2, 6 => Float32
+                        ],
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: size_of::<Agent>() as u64,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            3 => Float32x2, 4 => Float32x2, 5 => Float32x2, 7 => Float32
+                        ],
+                    },
+                ],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_agent"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: samples,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        let sim_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sim"),
+            size: size_of::<SimParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sim_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sim_bg"),
+            layout: &sim_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: sim_buf.as_entire_binding(),
+            }],
+        });
+
+        // 面積に合わせて増やすときのために上限の数だけ確保しておく
+        let mut agents = initial_agents(agent_count, w as f32 / h as f32, lifetime);
+        agents.resize(max_agents.unwrap_or(agent_count) as usize, Agent::zeroed());
+        let buffers = [0, 1].map(|i| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("agents{i}")),
+                contents: bytemuck::cast_slice(&agents),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            })
+        });
+        let bind_groups = [0, 1].map(|i| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("agents_bg"),
+                layout: &agents_bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffers[i].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffers[1 - i].as_entire_binding(),
+                    },
+                ],
+            })
+        });
+
+        let mut swarm = Self {
+            agent_count,
+            compute,
+            workgroup,
+            render,
+            sim_buf,
+            sim_bg,
+            buffers,
+            bind_groups,
+            cur: 0,
+            fixed_dt,
+            steps: 0,
+            lifetime,
+            speed_smooth,
+            min_dist,
+            max_agents,
+            base: (agent_count, [w, h]),
+            size: [w, h],
+            spawned: 0,
+            format,
+            samples,
+            msaa: None,
+        };
+        swarm.create_msaa(device, w, h);
+        swarm
+    }
+
+    // シーンの解像度が変わったら MSAA の描画先を作り直し、
+    // 面積に合わせる設定ならエージェント数も合わせる
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, w: u32, h: u32) {
+        if let Some(max) = self.max_agents {
+            self.fit_area(queue, [w, h], max);
+        }
+        self.size = [w, h];
+        self.create_msaa(device, w, h);
+    }
+
+    // MSAA のときだけ、複数サンプルの描画先をシーンの大きさで作る
+    fn create_msaa(&mut self, device: &wgpu::Device, w: u32, h: u32) {
+        if self.samples <= 1 {
+            return;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("swarm_msaa"),
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: self.samples,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        self.msaa = Some(texture.create_view(&Default::default()));
+    }
+
+    // 面積の比でエージェント数を決める。増えた分は新しく見えるようになった所に生まれさせ、
+    // 今いるエージェントはそのまま。減った分はバッファに残したまま更新も描画もしない
+    fn fit_area(&mut self, queue: &wgpu::Queue, [w, h]: [u32; 2], max: u32) {
+        let (base_count, [bw, bh]) = self.base;
+        let ratio = (w as f64 * h as f64) / (bw as f64 * bh as f64);
+        let count = ((base_count as f64 * ratio).round() as u32).clamp(1, max);
+        if count > self.agent_count {
+            let old = self.size[0] as f32 / self.size[1] as f32;
+            let aspect = w as f32 / h as f32;
+            // 高さが画面の単位なので、横に広がった分だけが新しく見える所になる
+            let band = if aspect > old {
+                [old, aspect]
+            } else {
+                [0.0, aspect]
+            };
+            self.spawned = self.spawned.wrapping_add(1);
+            let agents = spawn_agents(count - self.agent_count, self.spawned, band, self.lifetime);
+            let offset = self.agent_count as u64 * size_of::<Agent>() as u64;
+            for buffer in &self.buffers {
+                queue.write_buffer(buffer, offset, bytemuck::cast_slice(&agents));
+            }
+        }
+        self.agent_count = count;
+    }
+
+    // time はアニメーション時間、dt は前フレームからの差。
+    // 固定ステップなら time までに必要な回数だけ進め、端数は描画時の補間に回す
+    fn plan(&mut self, time: f32, dt: f32) -> (u32, SimParams) {
+        let Some(fixed) = self.fixed_dt else {
+            let sim = SimParams {
+                dt,
+                alpha: 1.0,
+                life_min: self.lifetime[0],
+                life_max: self.lifetime[1],
+                speed_smooth: self.speed_smooth,
+                min_dist: self.min_dist,
+                count: self.agent_count,
+                _pad: 0.0,
+            };
+            return (1, sim);
+        };
+        let due = (time.max(0.0) / fixed).floor() as u64;
+        let mut steps = due.saturating_sub(self.steps);
+        if steps > MAX_STEPS as u64 {
+            steps = MAX_STEPS as u64;
+            self.steps = due - steps;
+        }
+        self.steps += steps;
+        let accumulator = time - self.steps as f32 * fixed;
+        let sim = SimParams {
+            dt: fixed,
+            alpha: (accumulator / fixed).clamp(0.0, 1.0),
+            life_min: self.lifetime[0],
+            life_max: self.lifetime[1],
+            speed_smooth: self.speed_smooth,
+            min_dist: self.min_dist,
+            count: self.agent_count,
+            _pad: 0.0,
+        };
+        (steps as u32, sim)
+    }
+
+    // 必要なステップだけ進めてから target に描く
+    pub fn encode(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        [params_bg, frame_bg]: [&wgpu::BindGroup; 2],
+        target: &wgpu::TextureView,
+        [time, dt]: [f32; 2],
+        timer: Option<&GpuTimer>,
+    ) {
+        let (steps, sim) = self.plan(time, dt);
+        queue.write_buffer(&self.sim_buf, 0, bytemuck::bytes_of(&sim));
+        if steps > 0 {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("swarm_step"),
+                timestamp_writes: timer.map(GpuTimer::compute_begin),
+            });
+            cpass.set_pipeline(&self.compute);
+            cpass.set_bind_group(0, params_bg, &[]);
+            cpass.set_bind_group(1, frame_bg, &[]);
+            cpass.set_bind_group(2, &self.sim_bg, &[]);
+            for _ in 0..steps {
+                cpass.set_bind_group(3, &self.bind_groups[self.cur], &[]);
+                // 端数のエージェントは最後のグループで拾い、余った分は cs_main が読み飛ばす
+                cpass.dispatch_workgroups(self.agent_count.div_ceil(self.workgroup), 1, 1);
+                self.cur = 1 - self.cur;
+            }
+        }
+
+        // MSAA なら複数サンプルの方に描いて target に解決する。サンプル自体は残さない
+        let (view, resolve_target, store) = match &self.msaa {
+            Some(msaa) => (msaa, Some(target), wgpu::StoreOp::Discard),
+            None => (target, None, wgpu::StoreOp::Store),
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("swarm_draw"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            // 進めるステップがなければこのパスから測る
+            timestamp_writes: timer.map(|t| t.render_writes(steps == 0, false)),
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.render);
+        rpass.set_bind_group(0, params_bg, &[]);
+        rpass.set_bind_group(1, frame_bg, &[]);
+        rpass.set_bind_group(2, &self.sim_bg, &[]);
+        rpass.set_vertex_buffer(0, self.buffers[1 - self.cur].slice(..));
+        rpass.set_vertex_buffer(1, self.buffers[self.cur].slice(..));
+        rpass.draw(0..6, 0..self.agent_count);
+    }
+}
+
+impl Swarm {
+    // 最新のステップのエージェントの位置を読み戻す
+    #[cfg(test)]
+    pub fn read_positions(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<[f32; 2]> {
+        let size = self.buffers[self.cur].size();
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("agents_readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&self.buffers[self.cur], 0, &staging, 0, size);
+        queue.submit(Some(encoder.finish()));
+        staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::PollType::Wait).unwrap();
+        let data = staging.slice(..).get_mapped_range();
+        let agents: Vec<Agent> = bytemuck::pod_collect_to_vec(&data);
+        agents.iter().map(|a| a.pos).collect()
+    }
+}
+
+// --workgroup をデバイスの上限に収まる大きさにする。収まらなければ下げて警告する。
+// 既定はどのバックエンドでも 64。llvmpipe (GL) で 4096/8192 体を測ったところ
+// 64/128/256 の差は誤差の範囲だったので、AMD の wave64 や NVIDIA の warp 2つ分を埋められる最小の値にしている。
+// 64 も収まらないデバイスでは swarm を使えないので Err
+pub fn workgroup_size(limits: &wgpu::Limits, requested: Option<u32>) -> Result<u32, String> {
+    let wanted = requested.unwrap_or(WORKGROUP_SIZE);
+    let max = limits
+        .max_compute_invocations_per_workgroup
+        .min(limits.max_compute_workgroup_size_x);
+    let size = [256, 128, 64]
+        .into_iter()
+        .find(|&n| n <= wanted && n <= max)
+        .ok_or_else(|| {
+            format!("allows {max} invocations per workgroup; the swarm needs {WORKGROUP_SIZE}")
+        })?;
+    if requested.is_some_and(|n| n != size) {
+        eprintln!("Workgroup size {wanted} is not supported by this adapter; using {size}.");
+    }
+    Ok(size)
+}
+
+// compute.wgsl の @workgroup_size を n に書き換える
+fn with_workgroup(source: &str, n: u32) -> String {
+    let from = format!("@workgroup_size({WORKGROUP_SIZE})");
+    debug_assert!(source.contains(&from));
+    source.replacen(&from, &format!("@workgroup_size({n})"), 1)
+}
+
+// 乱数の crate は使わず、シードを決めた xorshift で毎回同じ値にする。0..1
+fn xorshift(seed: u32) -> impl FnMut() -> f32 {
+    let mut state = seed;
+    move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    }
+}
+
+// 固定シードで毎回同じ配置にする
+// 寿命があるときは年齢もばらして、死ぬ時期が揃わないようにする
+fn initial_agents(count: u32, aspect: f32, [life_min, life_max]: [f32; 2]) -> Vec<Agent> {
+    let mut next = xorshift(0x9E37_79B9);
+    (0..count)
+        .map(|_| {
+            let pos = [(next() * 2.0 - 1.0) * aspect, next() * 2.0 - 1.0];
+            let angle = next() * std::f32::consts::TAU;
+            let speed = 0.1 + next() * 0.2;
+            let life = life_min + (life_max - life_min) * next();
+            Agent {
+                pos,
+                vel: [angle.cos() * speed, angle.sin() * speed],
+                age: life * next(),
+                life,
+                speed: (speed / MAX_SPEED).min(1.0),
+                _pad: 0.0,
+            }
+        })
+        .collect()
+}
+
+// 面積に合わせて増やしたエージェント。x の絶対値が band の範囲（左右どちらか）、y は全体に置く。
+// 生まれたばかりとして年齢 0 から始め、寿命があればフェードインさせる
+fn spawn_agents(
+    count: u32,
+    round: u32,
+    band: [f32; 2],
+    [life_min, life_max]: [f32; 2],
+) -> Vec<Agent> {
+    let mut next = xorshift(round.wrapping_mul(0x85EB_CA6B) ^ 0x9E37_79B9 | 1);
+    (0..count)
+        .map(|_| {
+            let side = if next() < 0.5 { -1.0 } else { 1.0 };
+            let x = side * (band[0] + (band[1] - band[0]) * next());
+            let pos = [x, next() * 2.0 - 1.0];
+            let angle = next() * std::f32::consts::TAU;
+            let speed = 0.1 + next() * 0.2;
+            Agent {
+                pos,
+                vel: [angle.cos() * speed, angle.sin() * speed],
+                age: 0.0,
+                life: life_min + (life_max - life_min) * next(),
+                speed: (speed / MAX_SPEED).min(1.0),
+                _pad: 0.0,
+            }
+        })
+        .collect()
+}
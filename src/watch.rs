@@ -0,0 +1,83 @@
+// --watch: エフェクトのシェーダーファイルの更新時刻を定期的に見て、変わったものだけ
+// 中身を UserEvent::Shader で送る。依存を増やさないよう、OS の通知 API ではなくポーリングで済ませる
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::app::UserEvent;
+use crate::effects::{CUSTOM, EFFECTS};
+
+// 開発用なのでビルドしたソースツリーのファイルを直接読む
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+const POLL: Duration = Duration::from_millis(250);
+
+pub fn path(file: &str) -> PathBuf {
+    Path::new(SHADER_DIR).join(file)
+}
+
+struct Watched {
+    effect: usize,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    // エディタによっては保存中に一瞬消えるので、エラーは続けて出さない
+    failed: bool,
+}
+
+// 各ファイルの最初の1回は起動時に読み込む。イベントループが終わったらスレッドも終わる。
+// custom（--shader）があれば noise.wgsl の代わりにそれを見る
+pub fn spawn(proxy: EventLoopProxy<UserEvent>, custom: Option<PathBuf>) {
+    let mut files: Vec<Watched> = EFFECTS
+        .iter()
+        .enumerate()
+        .filter_map(|(effect, e)| {
+            let file = match &custom {
+                Some(p) if effect == CUSTOM => p.clone(),
+                _ => path(e.file?),
+            };
+            Some(Watched {
+                effect,
+                path: file,
+                modified: None,
+                failed: false,
+            })
+        })
+        .collect();
+    let _ = std::thread::Builder::new()
+        .name("shader-watch".into())
+        .spawn(move || {
+            loop {
+                for f in &mut files {
+                    let read = std::fs::metadata(&f.path)
+                        .and_then(|m| m.modified())
+                        .and_then(|t| {
+                            if f.modified == Some(t) {
+                                return Ok(None);
+                            }
+                            std::fs::read_to_string(&f.path).map(|src| Some((t, src)))
+                        });
+                    match read {
+                        Ok(Some((t, source))) => {
+                            f.modified = Some(t);
+                            f.failed = false;
+                            let event = UserEvent::Shader {
+                                effect: f.effect,
+                                source,
+                            };
+                            if proxy.send_event(event).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => (),
+                        Err(e) if !f.failed => {
+                            eprintln!("watch {}: {e}", f.path.display());
+                            f.failed = true;
+                        }
+                        Err(_) => (),
+                    }
+                }
+                std::thread::sleep(POLL);
+            }
+        });
+}